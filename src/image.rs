@@ -1,16 +1,25 @@
+pub mod denoise;
+pub mod orbit;
+pub mod output;
 pub mod ray;
 
+use argh::FromArgValue;
 use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
 use png::{BitDepth, ColorType, Encoder};
 use rand::Rng;
+use ray::bvh::Bvh;
 use ray::color::{Color, SuperColor};
+use ray::light::Light;
+use ray::medium::Medium;
+use ray::object::{Hit, Object};
 use ray::vec3::{Direction, Location, UnitDirection};
-use ray::{Background, Ray, Sphere};
+use ray::{Background, Ray};
 use rayon::prelude::*;
 use std::f64::consts::PI;
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::Path;
+use std::sync::Arc;
 
 struct Viewport {
     origin: Location,
@@ -19,13 +28,37 @@ struct Viewport {
     y_step: Direction,
 }
 
+// Times between which the camera records each frame, in [0, 1]
+#[derive(Copy, Clone)]
+pub struct Shutter {
+    open: f64,
+    close: f64,
+}
+
+#[derive(Copy, Clone)]
+pub struct Pixel {
+    pub color: Color,
+    pub albedo: Color,
+    pub normal: Direction,
+    // share of the samples hitting an object, 1 if the background is kept
+    pub alpha: f64,
+}
+
 pub struct Image {
     width: usize,
     height: usize,
     multisampling: u8,
     viewport: Viewport,
     background: Background,
-    data: Option<Vec<u8>>,
+    shutter: Shutter,
+    // time of the start of the frame
+    frame: f64,
+    // the background is left out, with an alpha channel
+    alpha: bool,
+    // filling the scene, outside of the objects
+    medium: Option<Arc<Medium>>,
+    lights: Vec<Light>,
+    data: Option<Vec<Pixel>>,
 }
 
 impl Viewport {
@@ -51,6 +84,40 @@ impl Viewport {
             y_step: (-2.0 * half_height / height as f64) * ver,
         }
     }
+
+    // Direction of the camera at `location` for `target` to be in the center
+    // (the camera can't look straight up or down)
+    fn look_at(location: Location, target: Location) -> UnitDirection {
+        (target - location).as_unit_vector()
+    }
+}
+
+impl Shutter {
+    pub fn new(open: f64, close: f64) -> Result<Self, String> {
+        if 0.0 <= open && open <= close && close <= 1.0 {
+            Ok(Shutter { open, close })
+        } else {
+            Err("shutter times must be in [0, 1] and in order".to_string())
+        }
+    }
+}
+
+impl FromArgValue for Shutter {
+    fn from_arg_value(value: &str) -> Result<Self, String> {
+        let times: Vec<&str> = value.split(',').collect();
+        if times.len() != 2 {
+            return Err("expected the opening and closing times".to_string());
+        }
+        let open = times[0]
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| format!("{} for the opening time", e))?;
+        let close = times[1]
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| format!("{} for the closing time", e))?;
+        Shutter::new(open, close)
+    }
 }
 
 impl Image {
@@ -68,89 +135,384 @@ impl Image {
             height,
             multisampling,
             viewport: Viewport::new(width, height, location, direction, fov),
-            background,
+            background: background.resolved(),
+            shutter: Shutter {
+                open: 0.0,
+                close: 1.0,
+            },
+            frame: 0.0,
+            alpha: false,
+            medium: None,
+            lights: Vec::new(),
             data: None,
         }
     }
 
-    fn color(&self, ray: Ray, spheres: &Vec<Sphere>) -> Color {
+    // The image seen from `location` with `target` in its center
+    pub fn looking_at(
+        width: usize,
+        height: usize,
+        multisampling: u8,
+        location: Location,
+        target: Location,
+        fov: f64,
+        background: Background,
+    ) -> Self {
+        Image::new(
+            width,
+            height,
+            multisampling,
+            location,
+            Viewport::look_at(location, target),
+            fov,
+            background,
+        )
+    }
+
+    pub fn set_shutter(&mut self, shutter: Shutter) {
+        self.shutter = shutter;
+    }
+
+    pub fn set_frame(&mut self, frame: f64) {
+        self.frame = frame;
+    }
+
+    // Times recorded by the camera
+    pub fn interval(&self) -> (f64, f64) {
+        (
+            self.frame + self.shutter.open,
+            self.frame + self.shutter.close,
+        )
+    }
+
+    // Leaves the background out of the image, still lighting the scene
+    pub fn set_alpha(&mut self, alpha: bool) {
+        self.alpha = alpha;
+    }
+
+    pub fn set_medium(&mut self, medium: Option<Arc<Medium>>) {
+        self.medium = medium;
+    }
+
+    pub fn set_lights(&mut self, lights: Vec<Light>) {
+        self.lights = lights;
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.alpha || matches!(self.background, Background::Transparent)
+    }
+
+    // Light coming along the ray, `pdf` being the probability density of its
+    // direction when it was chosen by bouncing off a surface
+    fn color(&self, ray: Ray, objects: &Bvh, pdf: Option<f64>) -> Color {
         if ray.is_dead() {
-            return self.background.color(ray);
-        }
-        let mut lowest_t = ray.range;
-        let mut hit = None;
-        for sphere in spheres {
-            match sphere.hit(&ray) {
-                Some(t) => {
-                    if t < lowest_t {
-                        lowest_t = t;
-                        hit = Some(sphere);
-                    }
+            return ray::color::BLACK;
+        }
+        let hit = objects.hit(&ray);
+        // light scattered by the medium before reaching the surface
+        if let Some(medium) = ray.get_medium() {
+            let distance = hit.as_ref().map_or(ray.range, |(hit, _)| hit.t);
+            if let Some(t) = medium.sample_distance(&ray, distance) {
+                return self.scattered(&ray, t, medium, objects);
+            }
+        }
+        match hit {
+            Some((hit, object)) if object.is_boundary() => {
+                self.color(self.through(&ray, &hit, object), objects, pdf)
+            }
+            Some((hit, object)) => self.surface(&ray, &hit, object, objects),
+            None => {
+                let direction = ray.get_direction();
+                let light_pdf = self.background.pdf(direction);
+                let weight = match pdf {
+                    Some(pdf) if light_pdf > 0.0 => pdf * pdf / (pdf * pdf + light_pdf * light_pdf),
+                    _ => 1.0,
+                };
+                weight * self.background.color(direction)
+            }
+        }
+    }
+
+    // Light leaving the surface of `object` back along the ray
+    fn surface(&self, ray: &Ray, hit: &Hit, object: &Object, objects: &Bvh) -> Color {
+        let color = object.color(hit);
+        let material = object.get_material();
+        let mut res = material.emission(hit);
+        let towards = -ray.get_direction();
+        // lights, which bouncing rays never find
+        let point = ray.at(hit.t);
+        for light in &self.lights {
+            if let Some((direction, distance, light)) = light.sample(point) {
+                let (value, _) = material.eval(color, hit, towards, direction);
+                if value.luminance() > 0.0 {
+                    let mut shadow = self.leaving(ray, hit, object, direction);
+                    shadow.range = shadow.range.min(distance);
+                    res = res + self.transmittance(shadow, objects) * (value * light);
                 }
-                None => (),
+            }
+        }
+        // light sampled from the background, weighted against finding it by
+        // bouncing (power heuristic)
+        if let Some((direction, light_pdf)) = self.background.sample() {
+            let (value, bounce_pdf) = material.eval(color, hit, towards, direction);
+            if bounce_pdf > 0.0 && light_pdf > 0.0 {
+                let shadow = self.leaving(ray, hit, object, direction);
+                let weight =
+                    light_pdf * light_pdf / (light_pdf * light_pdf + bounce_pdf * bounce_pdf);
+                let transmittance = self.transmittance(shadow, objects);
+                res = res
+                    + (weight * transmittance / light_pdf)
+                        * (value * self.background.color(direction));
+            }
+        }
+        if let Some(sample) = material.sample(color, hit, towards) {
+            let bounce = self.leaving(ray, hit, object, sample.direction);
+            res = res + sample.weight * self.color(bounce, objects, sample.pdf);
+        }
+        res
+    }
+
+    // Light coming along a camera ray, and the albedo and normal of what it
+    // sees unless it is the background, the boundaries of media being unseen
+    fn seen(&self, ray: Ray, objects: &Bvh) -> (Color, Option<(Color, Direction)>) {
+        let hit = objects.hit(&ray);
+        if let Some(medium) = ray.get_medium() {
+            let distance = hit.as_ref().map_or(ray.range, |(hit, _)| hit.t);
+            if let Some(t) = medium.sample_distance(&ray, distance) {
+                let features = (medium.albedo(), Direction::new(0.0, 0.0, 0.0));
+                return (self.scattered(&ray, t, medium, objects), Some(features));
             }
         }
         match hit {
-            Some(sphere) => {
-                let mut res = SuperColor::new();
-                let normal = (ray.at(lowest_t) - sphere.center).as_unit_vector();
-                // res.add(normal.as_color());
-                res.add(sphere.color.clone());
-                res.add(ray::color::BLACK);
-                res.add(self.color(ray.diffuse(lowest_t, normal), spheres));
-                res.as_color()
+            Some((hit, object)) if object.is_boundary() => {
+                self.seen(self.through(&ray, &hit, object), objects)
+            }
+            Some((hit, object)) => (
+                self.surface(&ray, &hit, object, objects),
+                Some((object.color(&hit), hit.normal.as_vector())),
+            ),
+            // the background is left out, colors being premultiplied by the
+            // alpha
+            None if self.has_alpha() => (ray::color::BLACK, None),
+            None => (self.background.color(ray.get_direction()), None),
+        }
+    }
+
+    // Light scattered back along the ray by the medium at `t`
+    fn scattered(&self, ray: &Ray, t: f64, medium: &Medium, objects: &Bvh) -> Color {
+        let mut res = ray::color::BLACK;
+        let direction = ray.get_direction();
+        for light in &self.lights {
+            if let Some((towards, distance, light)) = light.sample(ray.at(t)) {
+                let mut shadow = ray.towards(t, towards);
+                shadow.range = shadow.range.min(distance);
+                let phase = medium.phase(-towards, -direction);
+                res = res + (phase * self.transmittance(shadow, objects)) * light;
+            }
+        }
+        // light sampled from the background, weighted against finding it by
+        // scattering (power heuristic)
+        if let Some((towards, light_pdf)) = self.background.sample() {
+            let phase = medium.phase(-towards, -direction);
+            if light_pdf > 0.0 {
+                let weight = light_pdf * light_pdf / (light_pdf * light_pdf + phase * phase);
+                let transmittance = self.transmittance(ray.towards(t, towards), objects);
+                res = res
+                    + (weight * phase * transmittance / light_pdf) * self.background.color(towards);
             }
-            None => self.background.color(ray),
         }
+        let (scattered, pdf) = medium.sample_phase(direction);
+        res = res + self.color(ray.towards(t, scattered), objects, Some(pdf));
+        medium.albedo() * res
     }
 
-    fn compute_pixel(&self, x: usize, y: usize, spheres: &Vec<Sphere>) -> Color {
-        let mut res = SuperColor::new();
+    // The ray leaving a surface along `direction`, in the medium on that side
+    fn leaving(&self, ray: &Ray, hit: &Hit, object: &Object, direction: UnitDirection) -> Ray {
+        let res = ray.towards(hit.t, direction);
+        if direction * hit.normal < 0.0 {
+            res.with_medium(self.beyond(hit, object))
+        } else {
+            res
+        }
+    }
+
+    // The ray going on through the boundary of a medium, which is not a bounce
+    fn through(&self, ray: &Ray, hit: &Hit, object: &Object) -> Ray {
+        ray.onwards(hit.t).with_medium(self.beyond(hit, object))
+    }
+
+    // The medium past the surface, into the object or out of it: media don't
+    // nest, leaving one goes back to the medium of the scene
+    fn beyond(&self, hit: &Hit, object: &Object) -> Option<Arc<Medium>> {
+        if hit.front_face {
+            object.get_medium()
+        } else {
+            self.medium.clone()
+        }
+    }
+
+    // Share of the light coming back along the ray through media and the
+    // boundaries of media, 0 if an object is in the way
+    fn transmittance(&self, ray: Ray, objects: &Bvh) -> f64 {
+        let mut ray = ray;
+        let mut res = 1.0;
+        // however many bounces it took to get there
+        while ray.range > 0.0 {
+            let hit = objects.hit(&ray);
+            if let Some(medium) = ray.get_medium() {
+                let distance = hit.as_ref().map_or(ray.range, |(hit, _)| hit.t);
+                res *= medium.transmittance(&ray, distance);
+            }
+            match hit {
+                Some((hit, object)) if object.is_boundary() => {
+                    ray = self.through(&ray, &hit, object);
+                }
+                Some(_) => return 0.0,
+                None => break,
+            }
+        }
+        res
+    }
+
+    fn compute_pixel(&self, x: usize, y: usize, objects: &Bvh) -> Pixel {
+        let mut color = SuperColor::new();
+        let mut albedo = SuperColor::new();
+        let mut normal = Direction::new(0.0, 0.0, 0.0);
+        let mut covered = 0;
         let mut rng = rand::thread_rng();
         for _ in 0..self.multisampling {
-            let ray = Ray::new(
-                self.viewport.origin,
-                (self.viewport.corner
-                    + (x as f64 + rng.gen::<f64>()) * self.viewport.x_step
-                    + (y as f64 + rng.gen::<f64>()) * self.viewport.y_step
-                    - self.viewport.origin)
-                    .as_unit_vector(),
-            );
-            res.add(self.color(ray, spheres));
-        }
-        res.as_color()
-    }
-
-    pub fn compute(&mut self, spheres: Vec<Sphere>) {
-        let mut data = vec![0; 3 * self.width * self.height];
-        data.par_chunks_mut(3 * self.width)
+            let direction = (self.viewport.corner
+                + (x as f64 + rng.gen::<f64>()) * self.viewport.x_step
+                + (y as f64 + rng.gen::<f64>()) * self.viewport.y_step
+                - self.viewport.origin)
+                .as_unit_vector();
+            let time = self.frame
+                + self.shutter.open
+                + rng.gen::<f64>() * (self.shutter.close - self.shutter.open);
+            let ray = Ray::new(self.viewport.origin, direction)
+                .with_time(time)
+                .with_medium(self.medium.clone());
+            // traced once for both the features and the color
+            let (sample_color, features) = self.seen(ray, objects);
+            match features {
+                Some((sample_albedo, sample_normal)) => {
+                    covered += 1;
+                    albedo.add(sample_albedo);
+                    normal = normal + sample_normal;
+                }
+                None => albedo.add(self.background.color(direction)),
+            }
+            color.add(sample_color);
+        }
+        Pixel {
+            color: color.as_color(),
+            albedo: albedo.as_color(),
+            normal: normal / self.multisampling as f64,
+            alpha: if self.has_alpha() {
+                covered as f64 / self.multisampling as f64
+            } else {
+                1.0
+            },
+        }
+    }
+
+    pub fn compute(&mut self, objects: &Bvh) {
+        let mut data = vec![
+            Pixel {
+                color: ray::color::BLACK,
+                albedo: ray::color::BLACK,
+                normal: Direction::new(0.0, 0.0, 0.0),
+                alpha: 0.0,
+            };
+            self.width * self.height
+        ];
+        data.par_chunks_mut(self.width)
             .enumerate()
             .progress_with(
                 ProgressBar::new(self.height as u64)
                     .with_style(ProgressStyle::default_bar().template("{wide_bar} ETA: {eta}")),
             )
             .for_each(|(y, row)| {
-                row.chunks_mut(3).enumerate().for_each(|(x, pixel)| {
-                    let color = self.compute_pixel(x, y, &spheres);
-                    pixel[0] = color.get_red();
-                    pixel[1] = color.get_green();
-                    pixel[2] = color.get_blue();
+                row.iter_mut().enumerate().for_each(|(x, pixel)| {
+                    *pixel = self.compute_pixel(x, y, objects);
                 })
             });
         self.data = Some(data);
     }
 
-    pub fn write(self, file_name: &str) {
-        match self.data {
+    pub fn denoise(&mut self) {
+        match self.data.as_mut() {
+            Some(data) => denoise::denoise(data, self.width, self.height),
+            None => eprintln!("Error: call Image::compute before Image::denoise"),
+        }
+    }
+
+    // 8 bits RGB values of the pixels, row by row
+    pub fn rgb(&self) -> Option<Vec<u8>> {
+        let data = self.data.as_ref()?;
+        let mut bytes = Vec::with_capacity(3 * data.len());
+        for pixel in data {
+            bytes.push(pixel.color.get_red());
+            bytes.push(pixel.color.get_green());
+            bytes.push(pixel.color.get_blue());
+        }
+        Some(bytes)
+    }
+
+    // 8 bits RGBA values of the pixels, row by row, with colors not
+    // premultiplied by the alpha
+    pub fn rgba(&self) -> Option<Vec<u8>> {
+        let data = self.data.as_ref()?;
+        let mut bytes = Vec::with_capacity(4 * data.len());
+        for pixel in data {
+            let color = if pixel.alpha > 0.0 {
+                (1.0 / pixel.alpha) * pixel.color
+            } else {
+                pixel.color
+            };
+            bytes.push(color.get_red());
+            bytes.push(color.get_green());
+            bytes.push(color.get_blue());
+            bytes.push((255.999 * pixel.alpha) as u8);
+        }
+        Some(bytes)
+    }
+
+    // Linear colors premultiplied by the alpha, as expected in exr files
+    fn write_exr(&self, file_name: &str) {
+        match self.data.as_ref() {
             Some(data) => {
+                exr::prelude::write_rgba_file(file_name, self.width, self.height, |x, y| {
+                    let pixel = &data[y * self.width + x];
+                    let (red, green, blue) = pixel.color.as_rgb();
+                    (red as f32, green as f32, blue as f32, pixel.alpha as f32)
+                })
+                .expect("Error writing exr file")
+            }
+            None => eprintln!("Error: call Image::compute before Image::write"),
+        }
+    }
+
+    // png or exr file, chosen by the extension
+    pub fn write(self, file_name: &str) {
+        if file_name.ends_with(".exr") {
+            return self.write_exr(file_name);
+        }
+        let (bytes, color_type) = if self.has_alpha() {
+            (self.rgba(), ColorType::Rgba)
+        } else {
+            (self.rgb(), ColorType::Rgb)
+        };
+        match bytes {
+            Some(bytes) => {
                 let file = File::create(Path::new(file_name)).expect("Error creating output file");
                 let buffer = BufWriter::new(file);
                 let mut encoder = Encoder::new(buffer, self.width as u32, self.height as u32);
-                encoder.set_color(ColorType::RGB);
+                encoder.set_color(color_type);
                 encoder.set_depth(BitDepth::Eight);
                 let mut writer = encoder.write_header().expect("Error creating png header");
                 writer
-                    .write_image_data(&data)
+                    .write_image_data(&bytes)
                     .expect("Error writing png data");
             }
             None => eprintln!("Error: call Image::compute before Image::write"),
@@ -0,0 +1,92 @@
+use super::ray::color::{Color, BLACK};
+use super::Pixel;
+use rayon::prelude::*;
+
+// Edge-avoiding à-trous wavelet filter: a 5x5 B3-spline kernel applied with
+// increasing holes, each tap weighted by how close its color, albedo and
+// normal are to the center pixel's, so that edges between objects are kept.
+
+const KERNEL: [f64; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+const ITERATIONS: u32 = 5;
+const SIGMA_COLOR: f64 = 0.6;
+const SIGMA_ALBEDO: f64 = 0.1;
+const SIGMA_NORMAL: f64 = 0.2;
+
+fn filter(
+    pixels: &[Pixel],
+    colors: &[Color],
+    width: usize,
+    height: usize,
+    step: isize,
+) -> Vec<Color> {
+    let sigma_color = SIGMA_COLOR * SIGMA_COLOR / (step * step) as f64;
+    let sigma_albedo = SIGMA_ALBEDO * SIGMA_ALBEDO;
+    let sigma_normal = SIGMA_NORMAL * SIGMA_NORMAL;
+    let mut res = vec![BLACK; colors.len()];
+    res.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        row.iter_mut().enumerate().for_each(|(x, out)| {
+            let i = y * width + x;
+            let mut sum = BLACK;
+            let mut total = 0.0;
+            for dy in -2..=2isize {
+                let sy = y as isize + dy * step;
+                if sy < 0 || sy >= height as isize {
+                    continue;
+                }
+                for dx in -2..=2isize {
+                    let sx = x as isize + dx * step;
+                    if sx < 0 || sx >= width as isize {
+                        continue;
+                    }
+                    let j = sy as usize * width + sx as usize;
+                    let w = KERNEL[dx.unsigned_abs()]
+                        * KERNEL[dy.unsigned_abs()]
+                        * (-colors[i].distance_squared(&colors[j]) / sigma_color
+                            - pixels[i].albedo.distance_squared(&pixels[j].albedo) / sigma_albedo
+                            - (pixels[i].normal - pixels[j].normal).length_squared()
+                                / sigma_normal)
+                            .exp();
+                    sum = sum + w * colors[j];
+                    total += w;
+                }
+            }
+            *out = (1.0 / total) * sum;
+        })
+    });
+    res
+}
+
+pub fn denoise(pixels: &mut [Pixel], width: usize, height: usize) {
+    let mut colors: Vec<Color> = pixels.iter().map(|pixel| pixel.color).collect();
+    for i in 0..ITERATIONS {
+        colors = filter(pixels, &colors, width, height, 1 << i);
+    }
+    for (pixel, color) in pixels.iter_mut().zip(colors) {
+        pixel.color = color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::ray::vec3::Direction;
+
+    #[test]
+    fn keeps_flat_images() {
+        let (width, height) = (7, 5);
+        let pixel = Pixel {
+            color: Color::new(0.2, 0.4, 0.6),
+            albedo: Color::new(0.5, 0.5, 0.5),
+            normal: Direction::new(0.0, 1.0, 0.0),
+            alpha: 1.0,
+        };
+        let mut pixels = vec![pixel; width * height];
+        denoise(&mut pixels, width, height);
+        for pixel in pixels {
+            let (red, green, blue) = pixel.color.as_rgb();
+            assert!((red - 0.2).abs() < 1e-12);
+            assert!((green - 0.4).abs() < 1e-12);
+            assert!((blue - 0.6).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use super::ray::vec3::{Location, Vector};
+use std::f64::consts::PI;
+
+// Path of a camera going around `center`, starting in front of it (on the
+// side of positive z) and turning once every `period` frames
+pub struct Orbit {
+    pub center: Location,
+    pub radius: f64,
+    // angle above the horizontal plane of the center, in degrees
+    pub elevation: f64,
+    // height gained at each turn, for a helix
+    pub rise: f64,
+    pub period: usize,
+}
+
+impl Orbit {
+    fn turns(&self, frame: f64) -> f64 {
+        frame / self.period as f64
+    }
+
+    pub fn location(&self, frame: f64) -> Location {
+        let angle = 2.0 * PI * self.turns(frame);
+        let (sin, cos) = (self.elevation * PI / 180.0).sin_cos();
+        self.target(frame) + self.radius * Vector::new(cos * angle.sin(), sin, cos * angle.cos())
+    }
+
+    // What the camera looks at, rising with it along a helix
+    pub fn target(&self, frame: f64) -> Location {
+        self.center + Vector::new(0.0, self.rise * self.turns(frame), 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orbit(elevation: f64, rise: f64) -> Orbit {
+        Orbit {
+            center: Location::new(1.0, 2.0, 3.0),
+            radius: 2.0,
+            elevation,
+            rise,
+            period: 8,
+        }
+    }
+
+    fn assert_location(location: Location, x: f64, y: f64, z: f64) {
+        assert!((location - Vector::new(x, y, z)).length() < 1e-9);
+    }
+
+    #[test]
+    fn starts_in_front_and_turns_once_per_period() {
+        let orbit = orbit(0.0, 0.0);
+        assert_location(orbit.location(0.0), 1.0, 2.0, 5.0);
+        // a quarter of a turn towards positive x
+        assert_location(orbit.location(2.0), 3.0, 2.0, 3.0);
+        assert_location(orbit.location(4.0), 1.0, 2.0, 1.0);
+        assert_location(orbit.location(8.0), 1.0, 2.0, 5.0);
+    }
+
+    #[test]
+    fn looks_down_from_its_elevation() {
+        let orbit = orbit(30.0, 0.0);
+        let location = orbit.location(0.0);
+        assert_location(location, 1.0, 3.0, 3.0 + 3f64.sqrt());
+        assert!(((location - orbit.target(0.0)).length() - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rises_with_its_target() {
+        let orbit = orbit(0.0, 1.5);
+        assert_location(orbit.target(16.0), 1.0, 5.0, 3.0);
+        assert_location(orbit.location(16.0), 1.0, 5.0, 5.0);
+    }
+}
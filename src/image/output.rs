@@ -0,0 +1,229 @@
+use super::Image;
+use gif::{DisposalMethod, Frame, Repeat};
+use png::{BitDepth, ColorType, Encoder};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Destination of the frames of an animation, chosen by the extension of the
+// file name: numbered ".png" or ".exr" files, or a single ".apng", ".gif" or
+// ".y4m" file ("-" for a y4m stream on the standard output)
+pub enum Output {
+    Numbered(String),
+    // with an alpha channel or not
+    Apng(png::Writer<BufWriter<File>>, bool),
+    // delay between frames in hundredths of a second, and whether the
+    // background is transparent
+    Gif(gif::Encoder<BufWriter<File>>, u16, bool),
+    Y4m(Box<dyn Write>),
+}
+
+pub const EXTENSIONS: [&str; 5] = [".png", ".exr", ".apng", ".gif", ".y4m"];
+
+fn create(file_name: &str) -> BufWriter<File> {
+    BufWriter::new(File::create(file_name).expect("Error creating output file"))
+}
+
+// The file of a frame: the run of '#' in `file_name` replaced by the frame
+// number, or the number added before the extension (".png" if there is none)
+fn frame_file_name(file_name: &str, frame: i64) -> String {
+    match file_name.find('#') {
+        Some(start) => {
+            let width = file_name[start..].chars().take_while(|&c| c == '#').count();
+            format!(
+                "{}{:0width$}{}",
+                &file_name[..start],
+                frame,
+                &file_name[start + width..],
+                width = width
+            )
+        }
+        None => {
+            let path = Path::new(file_name);
+            let (stem, extension) = match (path.file_stem(), path.extension()) {
+                (Some(stem), Some(extension)) => (stem, extension),
+                (Some(stem), None) => (stem, "png".as_ref()),
+                _ => return format!("{}_{:04}.png", file_name, frame),
+            };
+            path.with_file_name(format!(
+                "{}_{:04}.{}",
+                stem.to_string_lossy(),
+                frame,
+                extension.to_string_lossy()
+            ))
+            .to_string_lossy()
+            .into_owned()
+        }
+    }
+}
+
+// Delay between frames in hundredths of a second, the resolution of gif
+// images, at least 1 since 0 means as fast as possible
+fn gif_delay(fps: u16) -> u16 {
+    ((100.0 / fps as f64).round() as u16).max(1)
+}
+
+// BT.601 studio range, as expected by most video encoders
+fn ycbcr(rgb: &[u8]) -> (u8, u8, u8) {
+    let (r, g, b) = (
+        rgb[0] as f64 / 255.0,
+        rgb[1] as f64 / 255.0,
+        rgb[2] as f64 / 255.0,
+    );
+    (
+        (16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8,
+        (128.0 - 37.797 * r - 74.203 * g + 112.0 * b).round() as u8,
+        (128.0 + 112.0 * r - 93.786 * g - 18.214 * b).round() as u8,
+    )
+}
+
+impl Output {
+    // Y4M streams have no alpha channel, their background being black
+    pub fn new(
+        file_name: &str,
+        width: usize,
+        height: usize,
+        frames: usize,
+        fps: u16,
+        alpha: bool,
+    ) -> Self {
+        if file_name == "-" || file_name.ends_with(".y4m") {
+            let mut writer: Box<dyn Write> = if file_name == "-" {
+                Box::new(BufWriter::new(io::stdout()))
+            } else {
+                Box::new(create(file_name))
+            };
+            // full resolution chroma, progressive, square pixels
+            writeln!(
+                writer,
+                "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                width, height, fps
+            )
+            .expect("Error writing y4m header");
+            Output::Y4m(writer)
+        } else if file_name.ends_with(".apng") {
+            let mut encoder = Encoder::new(create(file_name), width as u32, height as u32);
+            encoder.set_color(if alpha {
+                ColorType::Rgba
+            } else {
+                ColorType::Rgb
+            });
+            encoder.set_depth(BitDepth::Eight);
+            encoder
+                .set_animated(frames as u32, 0)
+                .expect("Error creating apng animation");
+            encoder
+                .set_frame_delay(1, fps)
+                .expect("Error setting apng frame delay");
+            Output::Apng(
+                encoder.write_header().expect("Error creating png header"),
+                alpha,
+            )
+        } else if file_name.ends_with(".gif") {
+            if width > u16::MAX as usize || height > u16::MAX as usize {
+                panic!(
+                    "Error: gif images are at most {} pixels wide and high",
+                    u16::MAX
+                );
+            }
+            let mut encoder =
+                gif::Encoder::new(create(file_name), width as u16, height as u16, &[])
+                    .expect("Error creating gif header");
+            encoder
+                .set_repeat(Repeat::Infinite)
+                .expect("Error creating gif animation");
+            Output::Gif(encoder, gif_delay(fps), alpha)
+        } else {
+            Output::Numbered(file_name.to_string())
+        }
+    }
+
+    pub fn write(&mut self, image: Image, frame: i64) {
+        match self {
+            Output::Numbered(file_name) => {
+                image.write(&frame_file_name(file_name, frame));
+            }
+            Output::Apng(writer, alpha) => {
+                let bytes = if *alpha { image.rgba() } else { image.rgb() }
+                    .expect("Error: image not computed");
+                writer
+                    .write_image_data(&bytes)
+                    .expect("Error writing png data");
+            }
+            Output::Gif(encoder, delay, alpha) => {
+                let (width, height) = (image.width as u16, image.height as u16);
+                // speed 10 is the default of the quantizer, 1 being the best
+                let mut gif_frame = if *alpha {
+                    // gif pixels are either opaque or transparent, the
+                    // transparent ones sharing a single color of the palette
+                    let mut bytes = image.rgba().expect("Error: image not computed");
+                    for pixel in bytes.chunks_mut(4) {
+                        if pixel[3] < 128 {
+                            pixel.copy_from_slice(&[0, 0, 0, 0]);
+                        } else {
+                            pixel[3] = 255;
+                        }
+                    }
+                    let mut gif_frame = Frame::from_rgba_speed(width, height, &mut bytes, 10);
+                    // or frames would be drawn over the previous ones
+                    gif_frame.dispose = DisposalMethod::Background;
+                    gif_frame
+                } else {
+                    let bytes = image.rgb().expect("Error: image not computed");
+                    Frame::from_rgb_speed(width, height, &bytes, 10)
+                };
+                gif_frame.delay = *delay;
+                encoder
+                    .write_frame(&gif_frame)
+                    .expect("Error writing gif frame");
+            }
+            Output::Y4m(writer) => {
+                let bytes = image.rgb().expect("Error: image not computed");
+                let pixels: Vec<(u8, u8, u8)> = bytes.chunks(3).map(ycbcr).collect();
+                let mut data = Vec::with_capacity(3 * pixels.len());
+                data.extend(pixels.iter().map(|p| p.0));
+                data.extend(pixels.iter().map(|p| p.1));
+                data.extend(pixels.iter().map(|p| p.2));
+                writer
+                    .write_all(b"FRAME\n")
+                    .and_then(|_| writer.write_all(&data))
+                    .expect("Error writing y4m frame");
+            }
+        }
+    }
+
+    pub fn finish(self) {
+        match self {
+            // gif files are finished when their encoder is dropped
+            Output::Numbered(_) | Output::Gif(..) => (),
+            Output::Apng(writer, _) => writer.finish().expect("Error writing png data"),
+            Output::Y4m(mut writer) => writer.flush().expect("Error writing y4m frame"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_frame_file_names() {
+        assert_eq!(frame_file_name("frame_###.png", 7), "frame_007.png");
+        assert_eq!(frame_file_name("frame.exr", 7), "frame_0007.exr");
+        assert_eq!(frame_file_name("frame", 7), "frame_0007.png");
+        // dots in directories are not extensions
+        assert_eq!(frame_file_name("out.d/frame", 7), "out.d/frame_0007.png");
+        assert_eq!(
+            frame_file_name("out.d/frame.exr", 7),
+            "out.d/frame_0007.exr"
+        );
+    }
+
+    #[test]
+    fn keeps_gif_delays_positive() {
+        assert_eq!(gif_delay(25), 4);
+        assert_eq!(gif_delay(24), 4);
+        assert_eq!(gif_delay(200), 1);
+        assert_eq!(gif_delay(1000), 1);
+    }
+}
@@ -1,44 +1,56 @@
+pub mod animation;
+pub mod bump;
+pub mod bvh;
 pub mod color;
+pub mod environment;
+pub mod light;
+pub mod material;
+pub mod medium;
+pub mod object;
+pub mod scene;
+pub mod sky;
+pub mod texture;
+pub mod transform;
 pub mod vec3;
 
 use argh::FromArgValue;
 use color::Color;
-use serde::Deserialize;
+use environment::Environment;
+use medium::Medium;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use sky::Sky;
+use std::sync::Arc;
 use vec3::{Location, UnitDirection};
 
-#[derive(Deserialize)]
-pub struct Sphere {
-    pub center: Location,
-    radius: f64,
-    pub color: Color,
-}
-
 pub struct Ray {
     location: Location,
     direction: UnitDirection,
     pub range: f64,
     ttl: u8,
+    // when the ray is cast, in frames, for moving objects
+    pub time: f64,
+    // the one the ray goes through, if any
+    medium: Option<Arc<Medium>>,
 }
 
+#[derive(Clone)]
 pub enum Background {
     BlueGradient,
     Black,
-}
-
-impl Sphere {
-    pub fn hit(&self, ray: &Ray) -> Option<f64> {
-        let oc = ray.location - self.center;
-        let half_b = oc * ray.direction;
-        let c = oc.length_squared() - self.radius * self.radius;
-        let d = half_b * half_b - c;
-        if d > 0.0 {
-            let temp = -half_b - d.sqrt();
-            if temp > 0.0 {
-                return Some(temp);
-            }
-        }
-        None
-    }
+    Solid(Color),
+    // colors from the bottom to the top, at heights in [0, 1] in order
+    Gradient(Vec<(f64, Color)>),
+    // left out of the image, the scene being lit by the blue gradient
+    Transparent,
+    // also lights the scene, from all directions
+    Environment(Environment),
+    // daylight, with the sun
+    Sky(Box<Sky>),
+    // the sky of the default options, made by `resolved` when it is needed
+    // so that it is not made for nothing when other options are given
+    DefaultSky,
 }
 
 impl Ray {
@@ -48,38 +60,180 @@ impl Ray {
             direction,
             range: 100.0,
             ttl: 10,
+            time: 0.0,
+            medium: None,
         }
     }
 
+    pub fn with_time(self, time: f64) -> Self {
+        Ray { time, ..self }
+    }
+
+    pub fn with_medium(self, medium: Option<Arc<Medium>>) -> Self {
+        Ray { medium, ..self }
+    }
+
+    pub fn get_medium(&self) -> Option<&Medium> {
+        self.medium.as_deref()
+    }
+
     pub fn is_dead(&self) -> bool {
-        self.range <= 0.0 || self.ttl <= 0
+        self.range <= 0.0 || self.ttl == 0
     }
 
     pub fn at(&self, t: f64) -> Location {
         self.location + t * self.direction
     }
 
-    pub fn diffuse(mut self, t: f64, normal: UnitDirection) -> Self {
-        self.range -= t;
-        self.ttl -= 1;
-        self.location = self.at(t);
-        self.direction = (normal + UnitDirection::random_on_unit_sphere()).as_unit_vector();
-        self
+    pub fn get_direction(&self) -> UnitDirection {
+        self.direction
+    }
+
+    // The same ray beyond the point at `t`, which is not a bounce
+    pub fn onwards(&self, t: f64) -> Self {
+        Ray {
+            location: self.at(t),
+            range: self.range - t,
+            medium: self.medium.clone(),
+            ..*self
+        }
+    }
+
+    // The ray leaving the point at `t` in this direction
+    pub fn towards(&self, t: f64, direction: UnitDirection) -> Self {
+        Ray {
+            location: self.at(t),
+            direction,
+            range: self.range - t,
+            ttl: self.ttl - 1,
+            time: self.time,
+            medium: self.medium.clone(),
+        }
+    }
+
+    // Directions are distributed by the cosine of their angle with `normal`
+    pub fn diffuse(self, t: f64, normal: UnitDirection) -> Self {
+        self.towards(
+            t,
+            (normal + UnitDirection::random_on_unit_sphere()).as_unit_vector(),
+        )
     }
 }
 
 impl Background {
-    pub fn color(&self, ray: Ray) -> Color {
+    // The same background, made if it only stands for one
+    pub fn resolved(self) -> Self {
         match self {
-            Background::BlueGradient => {
-                let t = 0.5 * (ray.direction.get_y() + 1.0);
+            Background::DefaultSky => Background::Sky(Box::new(
+                Sky::new(45.0, 0.0, 3.0, Color::new(0.3, 0.3, 0.3))
+                    .expect("default sky options are valid"),
+            )),
+            background => background,
+        }
+    }
+
+    pub fn color(&self, direction: UnitDirection) -> Color {
+        match self {
+            Background::BlueGradient | Background::Transparent => {
+                let t = 0.5 * (direction.get_y() + 1.0);
                 Color::new(1.0 - 0.5 * t, 1.0 - 0.3 * t, 1.0)
             }
-            Background::Black => color::BLACK,
+            Background::Black | Background::DefaultSky => color::BLACK,
+            Background::Solid(color) => *color,
+            Background::Gradient(stops) => {
+                let t = 0.5 * (direction.get_y() + 1.0);
+                match stops.iter().position(|(height, _)| *height > t) {
+                    Some(0) => stops[0].1,
+                    Some(i) => {
+                        let ((t1, c1), (t2, c2)) = (stops[i - 1], stops[i]);
+                        let along = (t - t1) / (t2 - t1);
+                        (1.0 - along) * c1 + along * c2
+                    }
+                    None => stops[stops.len() - 1].1,
+                }
+            }
+            Background::Environment(environment) => environment.color(direction),
+            Background::Sky(sky) => sky.color(direction),
+        }
+    }
+
+    // Direction towards the light of backgrounds that are sampled as lights,
+    // with its probability density
+    pub fn sample(&self) -> Option<(UnitDirection, f64)> {
+        match self {
+            Background::Environment(environment) => Some(environment.sample()),
+            Background::Sky(sky) => Some(sky.sample()),
+            _ => None,
+        }
+    }
+
+    // Probability density of `sample` returning this direction
+    pub fn pdf(&self, direction: UnitDirection) -> f64 {
+        match self {
+            Background::Environment(environment) => environment.pdf(direction),
+            Background::Sky(sky) => sky.pdf(direction),
+            _ => 0.0,
+        }
+    }
+
+    pub fn with_intensity(self, intensity: f64) -> Self {
+        match self {
+            Background::Environment(environment) => {
+                Background::Environment(environment.with_intensity(intensity))
+            }
+            Background::Sky(sky) => Background::Sky(Box::new(sky.with_intensity(intensity))),
+            background => background,
         }
     }
 }
 
+// Gradient of colors separated by ':', each one optionally followed by its
+// height as "@height", evenly spaced otherwise
+fn parse_gradient(value: &str) -> Result<Background, String> {
+    let stops: Vec<(Option<f64>, Color)> = value
+        .split(':')
+        .map(|stop| {
+            let (color, height) = match stop.find('@') {
+                Some(i) => (&stop[..i], Some(&stop[i + 1..])),
+                None => (stop, None),
+            };
+            let height = height
+                .map(|height| height.trim().parse::<f64>())
+                .transpose()
+                .map_err(|e| format!("{} for the height of {}", e, stop))?;
+            Ok((height, Color::from_arg_value(color)?))
+        })
+        .collect::<Result<_, String>>()?;
+    if stops.len() < 2 {
+        return Err("a gradient needs at least two colors".to_string());
+    }
+    let last = (stops.len() - 1) as f64;
+    let stops: Vec<(f64, Color)> = if stops.iter().all(|(height, _)| height.is_none()) {
+        stops
+            .into_iter()
+            .enumerate()
+            .map(|(i, (_, color))| (i as f64 / last, color))
+            .collect()
+    } else {
+        stops
+            .into_iter()
+            .map(|(height, color)| {
+                height
+                    .map(|height| (height, color))
+                    .ok_or("either all the colors of a gradient have a height or none")
+            })
+            .collect::<Result<_, _>>()?
+    };
+    if stops
+        .iter()
+        .any(|(height, _)| !(0.0..=1.0).contains(height))
+        || stops.windows(2).any(|w| w[0].0 > w[1].0)
+    {
+        return Err("the heights of a gradient must be in [0, 1] and in order".to_string());
+    }
+    Ok(Background::Gradient(stops))
+}
+
 impl FromArgValue for Background {
     fn from_arg_value(value: &str) -> Result<Self, String> {
         let name = value.trim().to_ascii_lowercase();
@@ -87,8 +241,68 @@ impl FromArgValue for Background {
             Ok(Background::BlueGradient)
         } else if name == "black" {
             Ok(Background::Black)
+        } else if name == "transparent" {
+            Ok(Background::Transparent)
+        } else if name == "sky" {
+            Ok(Background::DefaultSky)
+        } else if name.ends_with(".hdr") || name.ends_with(".exr") {
+            Environment::load(value.trim()).map(Background::Environment)
+        } else if name.contains(':') {
+            parse_gradient(&name)
+        } else if name.starts_with('#') || name.contains(',') {
+            Color::from_arg_value(&name).map(Background::Solid)
         } else {
-            Err(format!("invalid value for background function"))
+            Err("invalid value for background function".to_string())
+        }
+    }
+}
+
+// The same values as on the command line, gradients being also given as a
+// list of colors
+impl<'de> Deserialize<'de> for Background {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(value) => Background::from_arg_value(&value),
+            Value::Array(stops) => stops
+                .iter()
+                .map(|stop| stop.as_str().ok_or("gradient colors must be strings"))
+                .collect::<Result<Vec<&str>, _>>()
+                .map_err(str::to_string)
+                .and_then(|stops| parse_gradient(&stops.join(":"))),
+            _ => Err("a background is a string or a list of colors".to_string()),
+        }
+        .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heights(value: &str) -> Vec<f64> {
+        match parse_gradient(value).unwrap() {
+            Background::Gradient(stops) => stops.iter().map(|(height, _)| *height).collect(),
+            _ => panic!("not a gradient"),
         }
     }
+
+    #[test]
+    fn spreads_gradient_stops_evenly_without_heights() {
+        assert_eq!(heights("#000000:#808080:#ffffff"), vec![0.0, 0.5, 1.0]);
+        assert_eq!(
+            heights("#000000@0:#808080@0.2:#ffffff@1"),
+            vec![0.0, 0.2, 1.0]
+        );
+    }
+
+    #[test]
+    fn rejects_unordered_or_out_of_range_stops() {
+        assert!(parse_gradient("#000000@0.5:#ffffff@0.2").is_err());
+        assert!(parse_gradient("#000000@-0.1:#ffffff@1").is_err());
+        assert!(parse_gradient("#000000@0:#ffffff@1.5").is_err());
+        // all the heights or none
+        assert!(parse_gradient("#000000@0:#808080:#ffffff@1").is_err());
+        assert!(parse_gradient("#000000").is_err());
+        assert!(parse_gradient("#000000@high:#ffffff@1").is_err());
+    }
 }
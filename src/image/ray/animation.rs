@@ -0,0 +1,236 @@
+use super::vec3::{UnitVector, Vector};
+use serde::Deserialize;
+
+#[derive(Deserialize, Copy, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // Catmull-Rom spline through the keyframes
+    Spline,
+}
+
+// Values that can be interpolated, and extrapolated for splines
+pub trait Interpolate: Clone {
+    fn lerp(&self, other: &Self, t: f64) -> Self;
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Keyframe<T> {
+    pub frame: f64,
+    pub value: T,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(try_from = "AnimationData<T>")]
+pub struct Animation<T> {
+    // sorted by frame, without duplicates
+    keyframes: Vec<Keyframe<T>>,
+    interpolation: Interpolation,
+}
+
+#[derive(Deserialize)]
+struct AnimationData<T> {
+    keyframes: Vec<Keyframe<T>>,
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+// Value of a scene file, either constant or changing from frame to frame
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Animated<T> {
+    Keyframes(Animation<T>),
+    Constant(T),
+}
+
+impl<T> std::convert::TryFrom<AnimationData<T>> for Animation<T> {
+    type Error = String;
+
+    fn try_from(data: AnimationData<T>) -> Result<Self, Self::Error> {
+        Animation::new(data.keyframes, data.interpolation)
+    }
+}
+
+impl<T> Animation<T> {
+    pub fn new(
+        mut keyframes: Vec<Keyframe<T>>,
+        interpolation: Interpolation,
+    ) -> Result<Self, String> {
+        if keyframes.is_empty() {
+            return Err("an animation needs keyframes".to_string());
+        }
+        keyframes.sort_by(|a, b| {
+            a.frame
+                .partial_cmp(&b.frame)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        if keyframes.windows(2).any(|w| w[0].frame == w[1].frame) {
+            return Err("two keyframes are at the same frame".to_string());
+        }
+        Ok(Animation {
+            keyframes,
+            interpolation,
+        })
+    }
+
+    // First and last frames
+    pub fn span(&self) -> (f64, f64) {
+        (
+            self.keyframes[0].frame,
+            self.keyframes[self.keyframes.len() - 1].frame,
+        )
+    }
+
+    // Frames of the keyframes, in order
+    pub fn frames(&self) -> Vec<f64> {
+        self.keyframes
+            .iter()
+            .map(|keyframe| keyframe.frame)
+            .collect()
+    }
+
+    // Value of the keyframe closest to `frame`
+    pub fn nearest(&self, frame: f64) -> &T {
+        let distance = |keyframe: &Keyframe<T>| (keyframe.frame - frame).abs();
+        &self
+            .keyframes
+            .iter()
+            .min_by(|a, b| {
+                distance(a)
+                    .partial_cmp(&distance(b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .expect("animations have keyframes")
+            .value
+    }
+
+    // The same animation with other values
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Animation<U> {
+        Animation {
+            keyframes: self
+                .keyframes
+                .iter()
+                .map(|keyframe| Keyframe {
+                    frame: keyframe.frame,
+                    value: f(&keyframe.value),
+                })
+                .collect(),
+            interpolation: self.interpolation,
+        }
+    }
+}
+
+impl<T: Interpolate> Animation<T> {
+    // Values are held before the first keyframe and after the last one
+    pub fn at(&self, frame: f64) -> T {
+        let keys = &self.keyframes;
+        let i = match keys.iter().position(|keyframe| keyframe.frame > frame) {
+            Some(0) => return keys[0].value.clone(),
+            Some(i) => i - 1,
+            None => return keys[keys.len() - 1].value.clone(),
+        };
+        let (t1, p1) = (keys[i].frame, &keys[i].value);
+        let (t2, p2) = (keys[i + 1].frame, &keys[i + 1].value);
+        let along = |a: f64, b: f64| (frame - a) / (b - a);
+        match self.interpolation {
+            Interpolation::Linear => p1.lerp(p2, along(t1, t2)),
+            Interpolation::Spline => {
+                // ends are extended by repeating the first and last values
+                let (t0, p0) = match i.checked_sub(1) {
+                    Some(j) => (keys[j].frame, &keys[j].value),
+                    None => (2.0 * t1 - t2, p1),
+                };
+                let (t3, p3) = match keys.get(i + 2) {
+                    Some(keyframe) => (keyframe.frame, &keyframe.value),
+                    None => (2.0 * t2 - t1, p2),
+                };
+                // Barry and Goldman's pyramid, with the frames as knots
+                let a1 = p0.lerp(p1, along(t0, t1));
+                let a2 = p1.lerp(p2, along(t1, t2));
+                let a3 = p2.lerp(p3, along(t2, t3));
+                let b1 = a1.lerp(&a2, along(t0, t2));
+                let b2 = a2.lerp(&a3, along(t1, t3));
+                b1.lerp(&b2, along(t1, t2))
+            }
+        }
+    }
+}
+
+impl<T: Interpolate> Animated<T> {
+    pub fn at(&self, frame: f64) -> T {
+        match self {
+            Animated::Keyframes(animation) => animation.at(frame),
+            Animated::Constant(value) => value.clone(),
+        }
+    }
+}
+
+impl Interpolate for f64 {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        (1.0 - t) * self + t * other
+    }
+}
+
+impl Interpolate for Vector {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        (1.0 - t) * *self + t * *other
+    }
+}
+
+impl Interpolate for UnitVector {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        ((1.0 - t) * *self + t * *other).as_unit_vector()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(interpolation: Interpolation) -> Animation<f64> {
+        let keyframes = [(0.0, 1.0), (1.0, 3.0), (3.0, -2.0), (4.0, 0.5)]
+            .iter()
+            .map(|&(frame, value)| Keyframe { frame, value })
+            .collect();
+        Animation::new(keyframes, interpolation).unwrap()
+    }
+
+    #[test]
+    fn splines_go_through_the_keyframes() {
+        let spline = animation(Interpolation::Spline);
+        for &(frame, value) in [(0.0, 1.0), (1.0, 3.0), (3.0, -2.0), (4.0, 0.5)].iter() {
+            assert!((spline.at(frame) - value).abs() < 1e-12);
+            // and are continuous there
+            assert!((spline.at(frame - 1e-9) - value).abs() < 1e-6);
+            assert!((spline.at(frame + 1e-9) - value).abs() < 1e-6);
+        }
+        // held before the first keyframe and after the last one
+        assert_eq!(spline.at(-1.0), 1.0);
+        assert_eq!(spline.at(5.0), 0.5);
+    }
+
+    #[test]
+    fn lines_go_straight_between_keyframes() {
+        let linear = animation(Interpolation::Linear);
+        assert!((linear.at(0.5) - 2.0).abs() < 1e-12);
+        assert!((linear.at(2.0) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sorts_keyframes_and_rejects_duplicates() {
+        let keyframes = |frames: &[f64]| {
+            frames
+                .iter()
+                .map(|&frame| Keyframe {
+                    frame,
+                    value: frame,
+                })
+                .collect()
+        };
+        let animation = Animation::new(keyframes(&[2.0, 0.0, 1.0]), Interpolation::Linear);
+        assert_eq!(animation.unwrap().frames(), vec![0.0, 1.0, 2.0]);
+        assert!(Animation::new(keyframes(&[1.0, 1.0]), Interpolation::Linear).is_err());
+        assert!(Animation::<f64>::new(Vec::new(), Interpolation::Linear).is_err());
+    }
+}
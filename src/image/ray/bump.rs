@@ -0,0 +1,81 @@
+use super::object::Hit;
+use super::texture::{ImageTexture, Texture};
+use super::vec3::UnitDirection;
+use serde::Deserialize;
+use std::sync::Arc;
+
+// Changes the normals of a surface to show details that are not modeled
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Bump {
+    // normals of a png image in the space of the surface, red along u, green
+    // along v and blue out of it
+    NormalMap {
+        #[serde(flatten)]
+        image: ImageTexture,
+        #[serde(default = "one")]
+        strength: f64,
+    },
+    // heights given by the brightness of a texture, `strength` scaling slopes
+    Height {
+        texture: Arc<Texture>,
+        #[serde(default = "one")]
+        strength: f64,
+    },
+}
+
+// step of the finite differences of heights
+const EPSILON: f64 = 1e-4;
+
+fn one() -> f64 {
+    1.0
+}
+
+impl Bump {
+    pub fn normal_map(image: ImageTexture, strength: f64) -> Self {
+        Bump::NormalMap { image, strength }
+    }
+
+    pub fn height(texture: Texture, strength: f64) -> Self {
+        Bump::Height {
+            texture: Arc::new(texture),
+            strength,
+        }
+    }
+
+    // Normal at this hit, facing the same side as the normal of the hit
+    pub fn normal(&self, hit: &Hit) -> UnitDirection {
+        let side = if hit.front_face { 1.0 } else { -1.0 };
+        let n = side * hit.normal;
+        // the tangent, made orthogonal to the normal
+        let t = (hit.tangent - (hit.tangent * n) * n).as_unit_vector();
+        let b = (n ^ t).as_unit_vector();
+        let res = match self {
+            Bump::NormalMap { image, strength } => {
+                let (x, y, z) = image.color(hit.u, hit.v).as_rgb();
+                let (x, y, z) = (2.0 * x - 1.0, 2.0 * y - 1.0, 2.0 * z - 1.0);
+                *strength * (x * t + y * b) + z.max(0.0) * n
+            }
+            Bump::Height { texture, strength } => {
+                let height = |du: f64, dv: f64| {
+                    let shifted = Hit {
+                        u: hit.u + du,
+                        v: hit.v + dv,
+                        point: hit.point + du * t + dv * b,
+                        ..*hit
+                    };
+                    texture.color(&shifted).luminance()
+                };
+                let h = height(0.0, 0.0);
+                let dh_u = (height(EPSILON, 0.0) - h) / EPSILON;
+                let dh_v = (height(0.0, EPSILON) - h) / EPSILON;
+                n - *strength * (dh_u * t + dh_v * b)
+            }
+        };
+        if res.length_squared() > 0.0 {
+            (side * res).as_unit_vector()
+        } else {
+            hit.normal
+        }
+    }
+}
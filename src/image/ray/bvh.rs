@@ -0,0 +1,236 @@
+use super::object::{Hit, Hittable, Object, T_MIN};
+use super::vec3::{Location, Vector};
+use super::Ray;
+
+// Bounds are padded so that flat objects still have some thickness
+const PADDING: f64 = 0.0001;
+
+#[derive(Copy, Clone)]
+pub struct Aabb {
+    min: Location,
+    max: Location,
+}
+
+enum Node {
+    Leaf(usize),
+    Branch {
+        bounds: Aabb,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+}
+
+pub struct Bvh {
+    objects: Vec<Object>,
+    root: Option<Node>,
+    unbounded: Vec<usize>,
+}
+
+fn axis(vector: &Vector, axis: usize) -> f64 {
+    match axis {
+        0 => vector.get_x(),
+        1 => vector.get_y(),
+        _ => vector.get_z(),
+    }
+}
+
+impl Aabb {
+    pub fn new(a: Location, b: Location) -> Self {
+        Aabb {
+            min: Location::new(
+                a.get_x().min(b.get_x()) - PADDING,
+                a.get_y().min(b.get_y()) - PADDING,
+                a.get_z().min(b.get_z()) - PADDING,
+            ),
+            max: Location::new(
+                a.get_x().max(b.get_x()) + PADDING,
+                a.get_y().max(b.get_y()) + PADDING,
+                a.get_z().max(b.get_z()) + PADDING,
+            ),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Aabb {
+            min: Location::new(
+                self.min.get_x().min(other.min.get_x()),
+                self.min.get_y().min(other.min.get_y()),
+                self.min.get_z().min(other.min.get_z()),
+            ),
+            max: Location::new(
+                self.max.get_x().max(other.max.get_x()),
+                self.max.get_y().max(other.max.get_y()),
+                self.max.get_z().max(other.max.get_z()),
+            ),
+        }
+    }
+
+    // Bigger by `padding` on all sides
+    pub fn padded(&self, padding: f64) -> Self {
+        let padding = Vector::new(padding, padding, padding);
+        Aabb {
+            min: self.min - padding,
+            max: self.max + padding,
+        }
+    }
+
+    pub fn corners(&self) -> [Location; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Location::new(a.get_x(), a.get_y(), a.get_z()),
+            Location::new(b.get_x(), a.get_y(), a.get_z()),
+            Location::new(a.get_x(), b.get_y(), a.get_z()),
+            Location::new(b.get_x(), b.get_y(), a.get_z()),
+            Location::new(a.get_x(), a.get_y(), b.get_z()),
+            Location::new(b.get_x(), a.get_y(), b.get_z()),
+            Location::new(a.get_x(), b.get_y(), b.get_z()),
+            Location::new(b.get_x(), b.get_y(), b.get_z()),
+        ]
+    }
+
+    pub fn centroid(&self) -> Location {
+        0.5 * (self.min + self.max)
+    }
+
+    fn longest_axis(&self) -> usize {
+        let size = self.max - self.min;
+        if size.get_x() > size.get_y() && size.get_x() > size.get_z() {
+            0
+        } else if size.get_y() > size.get_z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    fn hit(&self, ray: &Ray, t_max: f64) -> bool {
+        self.clip(ray, 0.0, t_max).is_some()
+    }
+
+    // Part of the ray in [t_min, t_max] inside the box, if any
+    pub fn clip(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let location = ray.location;
+        let direction = ray.direction.as_vector();
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for i in 0..3 {
+            let inverse = 1.0 / axis(&direction, i);
+            let mut t0 = (axis(&self.min, i) - axis(&location, i)) * inverse;
+            let mut t1 = (axis(&self.max, i) - axis(&location, i)) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+fn build(items: &mut [(usize, Aabb)]) -> Node {
+    if items.len() == 1 {
+        return Node::Leaf(items[0].0);
+    }
+    let bounds = items
+        .iter()
+        .skip(1)
+        .fold(items[0].1, |bounds, (_, item)| bounds.union(item));
+    let centroids = items.iter().skip(1).fold(
+        Aabb::new(items[0].1.centroid(), items[0].1.centroid()),
+        |centroids, (_, item)| centroids.union(&Aabb::new(item.centroid(), item.centroid())),
+    );
+    let split = centroids.longest_axis();
+    items.sort_by(|(_, a), (_, b)| {
+        axis(&a.centroid(), split)
+            .partial_cmp(&axis(&b.centroid(), split))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let (left, right) = items.split_at_mut(items.len() / 2);
+    Node::Branch {
+        bounds,
+        left: Box::new(build(left)),
+        right: Box::new(build(right)),
+    }
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Object>) -> Self {
+        Bvh::with_bounds(objects, Object::bounds)
+    }
+
+    // The same objects, only for rays between these times, moving objects
+    // being bounded around where they are then
+    pub fn during(self, start: f64, end: f64) -> Self {
+        Bvh::with_bounds(self.objects, |object| object.bounds_during(start, end))
+    }
+
+    // Whether `during` changes anything
+    pub fn has_moving_objects(&self) -> bool {
+        self.objects.iter().any(Object::is_moving)
+    }
+
+    fn with_bounds(objects: Vec<Object>, bounds: impl Fn(&Object) -> Option<Aabb>) -> Self {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            match bounds(object) {
+                Some(bounds) => bounded.push((i, bounds)),
+                None => unbounded.push(i),
+            }
+        }
+        let root = if bounded.is_empty() {
+            None
+        } else {
+            Some(build(&mut bounded))
+        };
+        Bvh {
+            objects,
+            root,
+            unbounded,
+        }
+    }
+
+    fn hit_node<'a>(&'a self, node: &Node, ray: &Ray, closest: &mut Option<(Hit, &'a Object)>) {
+        let t_max = closest.map_or(ray.range, |(hit, _)| hit.t);
+        match node {
+            Node::Leaf(i) => self.hit_object(*i, ray, t_max, closest),
+            Node::Branch {
+                bounds,
+                left,
+                right,
+            } => {
+                if bounds.hit(ray, t_max) {
+                    self.hit_node(left, ray, closest);
+                    self.hit_node(right, ray, closest);
+                }
+            }
+        }
+    }
+
+    fn hit_object<'a>(
+        &'a self,
+        i: usize,
+        ray: &Ray,
+        t_max: f64,
+        closest: &mut Option<(Hit, &'a Object)>,
+    ) {
+        if let Some(hit) = self.objects[i].hit(ray, T_MIN, t_max) {
+            *closest = Some((hit, &self.objects[i]));
+        }
+    }
+
+    pub fn hit(&self, ray: &Ray) -> Option<(Hit, &Object)> {
+        let mut closest = None;
+        for i in &self.unbounded {
+            let t_max = closest.map_or(ray.range, |(hit, _): (Hit, &Object)| hit.t);
+            self.hit_object(*i, ray, t_max, &mut closest);
+        }
+        if let Some(root) = &self.root {
+            self.hit_node(root, ray, &mut closest);
+        }
+        closest
+    }
+}
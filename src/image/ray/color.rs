@@ -1,6 +1,8 @@
+use argh::FromArgValue;
 use serde::Deserialize;
+use std::ops::{Add, Mul};
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Copy, Clone)]
 pub struct Color {
     red: f64,
     green: f64,
@@ -86,6 +88,22 @@ impl Color {
     pub fn get_blue(&self) -> u8 {
         (255.999 * self.blue) as u8
     }
+
+    pub fn as_rgb(&self) -> (f64, f64, f64) {
+        (self.red, self.green, self.blue)
+    }
+
+    // Relative luminance of linear sRGB
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
+
+    pub fn distance_squared(&self, other: &Color) -> f64 {
+        let red = self.red - other.red;
+        let green = self.green - other.green;
+        let blue = self.blue - other.blue;
+        red * red + green * green + blue * blue
+    }
 }
 
 impl SuperColor {
@@ -113,3 +131,78 @@ impl SuperColor {
         self.blue += color.blue;
     }
 }
+
+impl Default for SuperColor {
+    fn default() -> Self {
+        SuperColor::new()
+    }
+}
+
+// "#rrggbb" or "r,g,b" with components in [0, 1]
+impl FromArgValue for Color {
+    fn from_arg_value(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if let Some(hex) = value.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return Err(format!("expected #rrggbb instead of {}", value));
+            }
+            let component = |i: usize| {
+                u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+                    .map(|c| c as f64 / 255.0)
+                    .map_err(|e| format!("{} in {}", e, value))
+            };
+            Ok(Color::new(component(0)?, component(1)?, component(2)?))
+        } else {
+            let components: Vec<&str> = value
+                .trim_matches(|p| p == '(' || p == ')')
+                .split(',')
+                .collect();
+            if components.len() != 3 {
+                return Err(format!("expected #rrggbb or r,g,b instead of {}", value));
+            }
+            let component = |i: usize, name: &str| {
+                components[i]
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|e| format!("{} for {}", e, name))
+            };
+            Ok(Color::new(
+                component(0, "red")?,
+                component(1, "green")?,
+                component(2, "blue")?,
+            ))
+        }
+    }
+}
+
+impl Add<Color> for Color {
+    type Output = Color;
+
+    fn add(self, rhs: Color) -> Self::Output {
+        Color::new(
+            self.red + rhs.red,
+            self.green + rhs.green,
+            self.blue + rhs.blue,
+        )
+    }
+}
+
+impl Mul<Color> for f64 {
+    type Output = Color;
+
+    fn mul(self, rhs: Color) -> Self::Output {
+        Color::new(self * rhs.red, self * rhs.green, self * rhs.blue)
+    }
+}
+
+impl Mul<Color> for Color {
+    type Output = Color;
+
+    fn mul(self, rhs: Color) -> Self::Output {
+        Color::new(
+            self.red * rhs.red,
+            self.green * rhs.green,
+            self.blue * rhs.blue,
+        )
+    }
+}
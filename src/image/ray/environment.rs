@@ -0,0 +1,320 @@
+use super::color::Color;
+use super::vec3::UnitDirection;
+use rand::Rng;
+use std::f64::consts::PI;
+use std::fs;
+use std::sync::Arc;
+
+// Equirectangular image of the light coming from every direction, with the
+// distribution used to sample its bright parts more often
+struct Map {
+    width: usize,
+    height: usize,
+    // row by row, from the top
+    pixels: Vec<Color>,
+    // cumulative distribution of the rows, and of the pixels in each row,
+    // proportional to their luminance and the solid angle they cover
+    rows: Vec<f64>,
+    columns: Vec<Vec<f64>>,
+}
+
+// Background lighting the scene from an environment map
+#[derive(Clone)]
+pub struct Environment {
+    map: Arc<Map>,
+    // around the vertical axis, in radians
+    rotation: f64,
+    intensity: f64,
+}
+
+// Values of the runs in the new run-length encoding of Radiance files
+fn read_rle_scanline(data: &[u8], pos: &mut usize, width: usize) -> Result<Vec<[u8; 4]>, String> {
+    let mut scanline = vec![[0; 4]; width];
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *data.get(*pos).ok_or("truncated file")? as usize;
+            *pos += 1;
+            if count > 128 {
+                let value = *data.get(*pos).ok_or("truncated file")?;
+                *pos += 1;
+                for pixel in scanline.iter_mut().skip(x).take(count - 128) {
+                    pixel[channel] = value;
+                }
+                x += count - 128;
+            } else if count > 0 {
+                let values = data.get(*pos..*pos + count).ok_or("truncated file")?;
+                *pos += count;
+                for (pixel, value) in scanline.iter_mut().skip(x).zip(values) {
+                    pixel[channel] = *value;
+                }
+                x += count;
+            } else {
+                return Err("invalid run length".to_string());
+            }
+        }
+    }
+    Ok(scanline)
+}
+
+fn rgbe(pixel: [u8; 4]) -> Color {
+    if pixel[3] == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let scale = 2f64.powi(pixel[3] as i32 - 136);
+    Color::new(
+        (pixel[0] as f64 + 0.5) * scale,
+        (pixel[1] as f64 + 0.5) * scale,
+        (pixel[2] as f64 + 0.5) * scale,
+    )
+}
+
+// Radiance .hdr file, with its rows from top to bottom ("-Y height +X width")
+fn read_hdr(data: &[u8]) -> Result<(usize, usize, Vec<Color>), String> {
+    let mut pos = 0;
+    let mut line = || -> Result<String, String> {
+        let end = data[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or("truncated header")?;
+        let line = String::from_utf8_lossy(&data[pos..pos + end]).to_string();
+        pos += end + 1;
+        Ok(line)
+    };
+    if !line()?.starts_with("#?") {
+        return Err("not a Radiance file".to_string());
+    }
+    loop {
+        let header = line()?;
+        if header.is_empty() {
+            break;
+        }
+        if header.starts_with("FORMAT=") && header != "FORMAT=32-bit_rle_rgbe" {
+            return Err(format!("unsupported {}", header));
+        }
+    }
+    let resolution = line()?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match fields.as_slice() {
+        ["-Y", height, "+X", width] => (
+            height.parse::<usize>().map_err(|e| e.to_string())?,
+            width.parse::<usize>().map_err(|e| e.to_string())?,
+        ),
+        _ => return Err(format!("unsupported orientation {}", resolution)),
+    };
+    let mut pixels = Vec::with_capacity(width * height);
+    for _ in 0..height {
+        let start = data.get(pos..pos + 4).ok_or("truncated file")?;
+        if (8..32768).contains(&width)
+            && start[0] == 2
+            && start[1] == 2
+            && ((start[2] as usize) << 8 | start[3] as usize) == width
+        {
+            pos += 4;
+            let scanline = read_rle_scanline(data, &mut pos, width)?;
+            pixels.extend(scanline.into_iter().map(rgbe));
+        } else {
+            // flat scanline
+            let scanline = data.get(pos..pos + 4 * width).ok_or("truncated file")?;
+            pos += 4 * width;
+            pixels.extend(scanline.chunks(4).map(|p| rgbe([p[0], p[1], p[2], p[3]])));
+        }
+    }
+    Ok((width, height, pixels))
+}
+
+fn read_exr(file_name: &str) -> Result<(usize, usize, Vec<Color>), String> {
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        file_name,
+        |resolution, _| {
+            (
+                resolution.width(),
+                vec![Color::new(0.0, 0.0, 0.0); resolution.width() * resolution.height()],
+            )
+        },
+        |(width, pixels), position, (red, green, blue, _): (f32, f32, f32, f32)| {
+            pixels[position.y() * *width + position.x()] =
+                Color::new(red as f64, green as f64, blue as f64);
+        },
+    )
+    .map_err(|e| e.to_string())?;
+    let size = image.layer_data.size;
+    Ok((
+        size.width(),
+        size.height(),
+        image.layer_data.channel_data.pixels.1,
+    ))
+}
+
+// Cumulative distribution of `weights`, normalized, with a leading 0
+fn cumulative(weights: impl Iterator<Item = f64>) -> Vec<f64> {
+    let mut res = vec![0.0];
+    for weight in weights {
+        res.push(res[res.len() - 1] + weight);
+    }
+    let total = res[res.len() - 1];
+    for value in res.iter_mut() {
+        *value = if total > 0.0 { *value / total } else { 0.0 };
+    }
+    res
+}
+
+// Index i such that cdf[i] <= x < cdf[i + 1], and where x is in between
+fn search(cdf: &[f64], x: f64) -> (usize, f64) {
+    let i = match cdf
+        .binary_search_by(|value| value.partial_cmp(&x).unwrap_or(std::cmp::Ordering::Less))
+    {
+        Ok(i) => i,
+        Err(i) => i - 1,
+    }
+    .min(cdf.len() - 2);
+    // skip empty intervals
+    let i = (i..cdf.len() - 1)
+        .find(|&i| cdf[i + 1] > cdf[i])
+        .unwrap_or(i);
+    (i, ((x - cdf[i]) / (cdf[i + 1] - cdf[i])).clamp(0.0, 1.0))
+}
+
+impl Map {
+    fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        let weight = |x: usize, y: usize| {
+            let theta = PI * (y as f64 + 0.5) / height as f64;
+            pixels[y * width + x].luminance() * theta.sin()
+        };
+        let columns: Vec<Vec<f64>> = (0..height)
+            .map(|y| cumulative((0..width).map(|x| weight(x, y))))
+            .collect();
+        let rows = cumulative((0..height).map(|y| (0..width).map(|x| weight(x, y)).sum()));
+        Map {
+            width,
+            height,
+            pixels,
+            rows,
+            columns,
+        }
+    }
+
+    fn probability(&self, x: usize, y: usize) -> f64 {
+        (self.rows[y + 1] - self.rows[y]) * (self.columns[y][x + 1] - self.columns[y][x])
+    }
+}
+
+impl Environment {
+    pub fn load(file_name: &str) -> Result<Self, String> {
+        let (width, height, pixels) = if file_name.ends_with(".hdr") {
+            fs::read(file_name)
+                .map_err(|e| e.to_string())
+                .and_then(|data| read_hdr(&data))
+        } else if file_name.ends_with(".exr") {
+            read_exr(file_name)
+        } else {
+            Err("environment maps must be .hdr or .exr files".to_string())
+        }
+        .map_err(|e| format!("Error reading {}: {}", file_name, e))?;
+        if width == 0 || height == 0 {
+            return Err(format!("Error reading {}: empty image", file_name));
+        }
+        Ok(Environment {
+            map: Arc::new(Map::new(width, height, pixels)),
+            rotation: 0.0,
+            intensity: 1.0,
+        })
+    }
+
+    // angle in degrees, counterclockwise seen from above
+    pub fn rotated(self, angle: f64) -> Self {
+        Environment {
+            rotation: angle * PI / 180.0,
+            ..self
+        }
+    }
+
+    pub fn with_intensity(self, intensity: f64) -> Self {
+        Environment { intensity, ..self }
+    }
+
+    // Pixel seen in this direction, the center of the map being seen
+    // towards -z
+    fn pixel(&self, direction: UnitDirection) -> (usize, usize) {
+        let phi = direction.get_x().atan2(-direction.get_z()) + self.rotation;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = direction.get_y().clamp(-1.0, 1.0).acos() / PI;
+        (
+            ((u * self.map.width as f64) as usize).min(self.map.width - 1),
+            ((v * self.map.height as f64) as usize).min(self.map.height - 1),
+        )
+    }
+
+    pub fn color(&self, direction: UnitDirection) -> Color {
+        let (x, y) = self.pixel(direction);
+        self.intensity * self.map.pixels[y * self.map.width + x]
+    }
+
+    // Probability density of sampling this direction, per unit solid angle
+    pub fn pdf(&self, direction: UnitDirection) -> f64 {
+        let (x, y) = self.pixel(direction);
+        let sin_theta = (PI * (y as f64 + 0.5) / self.map.height as f64).sin();
+        let pixels = (self.map.width * self.map.height) as f64;
+        self.map.probability(x, y) * pixels / (2.0 * PI * PI * sin_theta)
+    }
+
+    // Direction towards the environment, chosen according to its luminance,
+    // with its probability density
+    pub fn sample(&self) -> (UnitDirection, f64) {
+        let mut rng = rand::thread_rng();
+        let (y, dy) = search(&self.map.rows, rng.gen::<f64>());
+        let (x, dx) = search(&self.map.columns[y], rng.gen::<f64>());
+        let u = (x as f64 + dx) / self.map.width as f64;
+        let v = (y as f64 + dy) / self.map.height as f64;
+        let phi = (u - 0.5) * 2.0 * PI - self.rotation;
+        let (sin_theta, cos_theta) = (v * PI).sin_cos();
+        let direction =
+            UnitDirection::new(sin_theta * phi.sin(), cos_theta, -sin_theta * phi.cos());
+        (direction, self.pdf(direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &[u8] = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n";
+
+    #[test]
+    fn reads_run_length_encoded_scanlines() {
+        let mut data = HEADER.to_vec();
+        data.extend_from_slice(&[2, 2, 0, 8]);
+        // red: a run of 8
+        data.extend_from_slice(&[128 + 8, 128]);
+        // green: 8 values
+        data.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        // blue: a run of 4 and 4 values
+        data.extend_from_slice(&[128 + 4, 64, 4, 1, 2, 3, 4]);
+        // exponent: a run of 8
+        data.extend_from_slice(&[128 + 8, 129]);
+        let (width, height, pixels) = read_hdr(&data).unwrap();
+        assert_eq!((width, height), (8, 1));
+        let blue = [64, 64, 64, 64, 1, 2, 3, 4];
+        let value = |byte: u32| (byte as f64 + 0.5) / 128.0;
+        for (x, pixel) in pixels.iter().enumerate() {
+            let expected = (value(128), value(16 * x as u32), value(blue[x]));
+            assert_eq!(pixel.as_rgb(), expected);
+        }
+        // runs of 0 and truncated scanlines
+        let run = HEADER.len() + 4;
+        data[run] = 0;
+        assert!(read_hdr(&data).is_err());
+        assert!(read_hdr(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn reads_flat_scanlines() {
+        let mut data = HEADER.to_vec();
+        for x in 0..8 {
+            data.extend_from_slice(&[x, 0, 0, 128]);
+        }
+        let (_, _, pixels) = read_hdr(&data).unwrap();
+        assert_eq!(pixels[3].as_rgb(), (3.5 / 256.0, 0.5 / 256.0, 0.5 / 256.0));
+        assert!(read_hdr(&data[..data.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use super::color::{Color, WHITE};
+use super::vec3::{Location, UnitDirection};
+use serde::Deserialize;
+
+// Light coming from a point or from a single direction, which is only found
+// by looking for it since bouncing rays never reach it
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Light {
+    // `intensity` being the light given off per unit of solid angle
+    Point {
+        location: Location,
+        #[serde(default = "white")]
+        color: Color,
+        #[serde(default = "one")]
+        intensity: f64,
+    },
+    // a point light given off in a cone around `direction`, fading out
+    // between the inner and outer angles (in degrees, from its axis)
+    Spot {
+        location: Location,
+        direction: UnitDirection,
+        #[serde(default = "white")]
+        color: Color,
+        #[serde(default = "one")]
+        intensity: f64,
+        #[serde(default)]
+        inner_angle: f64,
+        #[serde(default = "outer_angle")]
+        outer_angle: f64,
+    },
+    // as the sun, going along `direction`, `intensity` being the light
+    // received per unit of area facing it
+    Directional {
+        direction: UnitDirection,
+        #[serde(default = "white")]
+        color: Color,
+        #[serde(default = "one")]
+        intensity: f64,
+    },
+}
+
+fn white() -> Color {
+    WHITE
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn outer_angle() -> f64 {
+    45.0
+}
+
+impl Light {
+    // Direction from `point` towards the light, its distance and the light
+    // received from it per unit of area facing it, if any
+    pub fn sample(&self, point: Location) -> Option<(UnitDirection, f64, Color)> {
+        match self {
+            Light::Point {
+                location,
+                color,
+                intensity,
+            } => {
+                let offset = *location - point;
+                let distance_squared = offset.length_squared();
+                Some((
+                    offset.as_unit_vector(),
+                    distance_squared.sqrt(),
+                    (intensity / distance_squared) * *color,
+                ))
+            }
+            Light::Spot {
+                location,
+                direction,
+                color,
+                intensity,
+                inner_angle,
+                outer_angle,
+            } => {
+                let offset = *location - point;
+                let distance_squared = offset.length_squared();
+                let towards = offset.as_unit_vector();
+                let (inner, outer) = (
+                    inner_angle.to_radians().cos(),
+                    outer_angle.to_radians().cos(),
+                );
+                // smooth between the angles, as in glTF
+                let t =
+                    ((-towards * *direction - outer) / (inner - outer).max(1e-6)).clamp(0.0, 1.0);
+                if t <= 0.0 {
+                    return None;
+                }
+                Some((
+                    towards,
+                    distance_squared.sqrt(),
+                    (t * t * intensity / distance_squared) * *color,
+                ))
+            }
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => Some((-*direction, f64::INFINITY, *intensity * *color)),
+        }
+    }
+}
@@ -0,0 +1,354 @@
+mod fresnel;
+mod microfacet;
+pub mod principled;
+
+use super::color::{Color, BLACK, WHITE};
+use super::object::{Frame, Hit};
+use super::vec3::{Direction, Location, UnitDirection, Vector};
+use microfacet::Ggx;
+use principled::Principled;
+use rand::Rng;
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+// How the surface of an object scatters light, the color of the object
+// tinting it
+#[derive(Deserialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Material {
+    // matte, of the color of the object
+    #[default]
+    Diffuse,
+    Conductor(Conductor),
+    // glass, water..., the color of the object tinting the light going through
+    Dielectric {
+        #[serde(default = "glass")]
+        ior: f64,
+        #[serde(default)]
+        roughness: f64,
+    },
+    // all of the above and more, in layers
+    Principled(Principled),
+    // invisible, only bounding the medium inside an object
+    #[serde(skip)]
+    Boundary,
+}
+
+// Metal, reflecting the color of the object at normal incidence unless it is
+// a named metal or its complex index of refraction is given
+#[derive(Deserialize)]
+#[serde(try_from = "ConductorData")]
+pub struct Conductor {
+    // eta and k, for red, green and blue
+    ior: Option<(Color, Color)>,
+    roughness: f64,
+}
+
+#[derive(Deserialize)]
+struct ConductorData {
+    #[serde(default)]
+    metal: Option<Metal>,
+    #[serde(default)]
+    eta: Option<Color>,
+    #[serde(default)]
+    k: Option<Color>,
+    #[serde(default)]
+    roughness: f64,
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Metal {
+    Gold,
+    Copper,
+    #[serde(alias = "aluminum")]
+    Aluminium,
+    Silver,
+}
+
+// Direction scattered by a surface
+pub struct Sample {
+    pub direction: UnitDirection,
+    // light scattered along the incoming ray per unit of light coming from
+    // `direction`, divided by the probability density of `direction`
+    pub weight: Color,
+    // None for directions chosen among a few (e.g. the reflection of a mirror)
+    pub pdf: Option<f64>,
+}
+
+fn glass() -> f64 {
+    1.5
+}
+
+impl Metal {
+    // Complex index of refraction at 650, 550 and 450 nm
+    pub fn ior(self) -> (Color, Color) {
+        match self {
+            Metal::Gold => (
+                Color::new(0.143, 0.374, 1.442),
+                Color::new(3.983, 2.385, 1.603),
+            ),
+            Metal::Copper => (
+                Color::new(0.200, 0.924, 1.102),
+                Color::new(3.912, 2.452, 2.142),
+            ),
+            Metal::Aluminium => (
+                Color::new(1.657, 0.880, 0.521),
+                Color::new(9.224, 6.270, 4.837),
+            ),
+            Metal::Silver => (
+                Color::new(0.155, 0.117, 0.138),
+                Color::new(4.828, 3.122, 2.147),
+            ),
+        }
+    }
+}
+
+impl std::convert::TryFrom<ConductorData> for Conductor {
+    type Error = String;
+
+    fn try_from(data: ConductorData) -> Result<Self, Self::Error> {
+        let ior = match (data.metal, data.eta, data.k) {
+            (Some(metal), None, None) => Some(metal.ior()),
+            (None, Some(eta), Some(k)) => Some((eta, k)),
+            (None, None, None) => None,
+            (Some(_), _, _) => return Err("a metal cannot have an eta or k".to_string()),
+            _ => return Err("eta and k go together".to_string()),
+        };
+        Ok(Conductor {
+            ior,
+            roughness: data.roughness,
+        })
+    }
+}
+
+impl Conductor {
+    // Of the color of the object at normal incidence
+    pub fn new(roughness: f64) -> Self {
+        Conductor {
+            ior: None,
+            roughness,
+        }
+    }
+
+    pub fn metal(metal: Metal, roughness: f64) -> Self {
+        Conductor {
+            ior: Some(metal.ior()),
+            roughness,
+        }
+    }
+
+    fn fresnel(&self, color: Color, cos_i: f64) -> Color {
+        match self.ior {
+            Some((eta, k)) => color * fresnel::conductor(cos_i, eta, k),
+            None => fresnel::schlick(cos_i, color),
+        }
+    }
+}
+
+// The direction reflected by a mirror
+fn reflect(w: Direction, normal: Direction) -> Direction {
+    2.0 * (w * normal) * normal - w
+}
+
+// The direction refracted through a surface, `eta` being the ratio of the
+// index of refraction of the other side to the one of the side of `w`
+fn refract(w: Direction, normal: Direction, eta: f64) -> Option<Direction> {
+    let cos_i = w * normal;
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-(1.0 / eta) * w + (cos_i / eta - cos_t) * normal)
+}
+
+// Directions distributed by their cosine with the normal
+fn cosine_direction() -> Direction {
+    (Vector::new(0.0, 0.0, 1.0) + UnitDirection::random_on_unit_sphere())
+        .as_unit_vector()
+        .as_vector()
+}
+
+// In the space of the surface, the reflection off the microfacets of a rough
+// surface and the probability density of `glossy_direction` returning `wi`
+fn glossy(ggx: &Ggx, wo: Direction, wi: Direction, fresnel: impl Fn(f64) -> Color) -> (Color, f64) {
+    if wi.get_z() <= 0.0 {
+        return (BLACK, 0.0);
+    }
+    let h = (wo + wi).as_unit_vector().as_vector();
+    (
+        (ggx.d(h) * ggx.g(wo, wi) / (4.0 * wo.get_z())) * fresnel(wo * h),
+        ggx.pdf(wo, h) / (4.0 * (wo * h)),
+    )
+}
+
+fn glossy_direction(ggx: &Ggx, wo: Direction) -> Direction {
+    reflect(wo, ggx.sample(wo))
+}
+
+// The same for the reflection and refraction of a rough dielectric, the light
+// going through being tinted
+fn rough_dielectric(
+    ggx: &Ggx,
+    eta: f64,
+    tint: Color,
+    wo: Direction,
+    wi: Direction,
+) -> (Color, f64) {
+    if wi.get_z() > 0.0 {
+        // reflected with the probability of the share of the light reflected
+        let (value, pdf) = glossy(ggx, wo, wi, |cos| fresnel::dielectric(cos, eta) * WHITE);
+        let h = (wo + wi).as_unit_vector().as_vector();
+        return (value, fresnel::dielectric(wo * h, eta) * pdf);
+    }
+    // the normal of the microfacet between both directions
+    let h = -(wo + eta * wi);
+    let h = if h.get_z() < 0.0 { -h } else { h };
+    let h = h.as_unit_vector().as_vector();
+    let (cos_o, cos_i) = (wo * h, wi * h);
+    if cos_o <= 0.0 || cos_i >= 0.0 {
+        return (BLACK, 0.0);
+    }
+    let f = fresnel::dielectric(cos_o, eta);
+    let denominator = cos_o + eta * cos_i;
+    let jacobian = eta * eta * -cos_i / (denominator * denominator);
+    (
+        ((1.0 - f) * ggx.d(h) * ggx.g(wo, wi) * cos_o * jacobian / wo.get_z()) * tint,
+        (1.0 - f) * ggx.pdf(wo, h) * jacobian,
+    )
+}
+
+// Reflected or refracted depending on the share of the light reflected by the
+// microfacet
+fn rough_dielectric_direction(ggx: &Ggx, eta: f64, wo: Direction) -> Option<Direction> {
+    let h = ggx.sample(wo);
+    if rand::thread_rng().gen::<f64>() < fresnel::dielectric(wo * h, eta) {
+        Some(reflect(wo, h))
+    } else {
+        refract(wo, h, eta)
+    }
+}
+
+impl Material {
+    // Light scattered towards `wo` per unit of light coming from `wi`, times
+    // the cosine of `wi` with the normal, and the probability density of
+    // `sample` returning `wi`
+    pub fn eval(
+        &self,
+        color: Color,
+        hit: &Hit,
+        wo: UnitDirection,
+        wi: UnitDirection,
+    ) -> (Color, f64) {
+        let frame = Frame::new(Location::new(0.0, 0.0, 0.0), hit.normal);
+        let (wo, wi) = (frame.to_local_direction(wo), frame.to_local_direction(wi));
+        if wo.get_z() <= 0.0 {
+            return (BLACK, 0.0);
+        }
+        match self {
+            Material::Diffuse => {
+                if wi.get_z() > 0.0 {
+                    ((wi.get_z() / PI) * color, wi.get_z() / PI)
+                } else {
+                    (BLACK, 0.0)
+                }
+            }
+            Material::Conductor(conductor) => {
+                let ggx = Ggx::new(conductor.roughness);
+                if ggx.is_smooth() {
+                    return (BLACK, 0.0);
+                }
+                glossy(&ggx, wo, wi, |cos| conductor.fresnel(color, cos))
+            }
+            Material::Dielectric { ior, roughness } => {
+                let ggx = Ggx::new(*roughness);
+                if ggx.is_smooth() {
+                    return (BLACK, 0.0);
+                }
+                let eta = if hit.front_face { *ior } else { 1.0 / ior };
+                rough_dielectric(&ggx, eta, color, wo, wi)
+            }
+            Material::Principled(principled) => {
+                principled.at(hit).eval(color, principled.eta(hit), wo, wi)
+            }
+            Material::Boundary => (BLACK, 0.0),
+        }
+    }
+
+    // Light given off by the surface
+    pub fn emission(&self, hit: &Hit) -> Color {
+        match self {
+            Material::Principled(principled) => principled.emission(hit),
+            _ => BLACK,
+        }
+    }
+
+    // Direction from which light is scattered towards `wo`, with its weight
+    pub fn sample(&self, color: Color, hit: &Hit, wo: UnitDirection) -> Option<Sample> {
+        let frame = Frame::new(Location::new(0.0, 0.0, 0.0), hit.normal);
+        let local = frame.to_local_direction(wo);
+        if local.get_z() <= 0.0 {
+            return None;
+        }
+        let mirror = Vector::new(-local.get_x(), -local.get_y(), local.get_z());
+        let direction = match self {
+            Material::Diffuse => {
+                let direction = cosine_direction();
+                return Some(Sample {
+                    direction: frame.to_world_direction(direction),
+                    weight: color,
+                    pdf: Some(direction.get_z().max(0.0) / PI),
+                });
+            }
+            Material::Conductor(conductor) => {
+                let ggx = Ggx::new(conductor.roughness);
+                if ggx.is_smooth() {
+                    return Some(Sample {
+                        direction: frame.to_world_direction(mirror),
+                        weight: conductor.fresnel(color, local.get_z()),
+                        pdf: None,
+                    });
+                }
+                glossy_direction(&ggx, local)
+            }
+            Material::Dielectric { ior, roughness } => {
+                let ggx = Ggx::new(*roughness);
+                let eta = if hit.front_face { *ior } else { 1.0 / ior };
+                if ggx.is_smooth() {
+                    let normal = Vector::new(0.0, 0.0, 1.0);
+                    let reflected = rand::thread_rng().gen::<f64>();
+                    let (direction, weight) = if reflected < fresnel::dielectric(local.get_z(), eta)
+                    {
+                        (mirror, WHITE)
+                    } else {
+                        (refract(local, normal, eta)?, color)
+                    };
+                    return Some(Sample {
+                        direction: frame.to_world_direction(direction),
+                        weight,
+                        pdf: None,
+                    });
+                }
+                rough_dielectric_direction(&ggx, eta, local)?
+            }
+            Material::Principled(principled) => {
+                principled
+                    .at(hit)
+                    .direction(color, principled.eta(hit), local)?
+            }
+            Material::Boundary => return None,
+        };
+        let direction = frame.to_world_direction(direction);
+        let (value, pdf) = self.eval(color, hit, wo, direction);
+        if pdf > 0.0 {
+            Some(Sample {
+                direction,
+                weight: (1.0 / pdf) * value,
+                pdf: Some(pdf),
+            })
+        } else {
+            None
+        }
+    }
+}
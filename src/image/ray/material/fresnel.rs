@@ -0,0 +1,48 @@
+use super::super::color::Color;
+
+// Share of the light reflected by a dielectric, `eta` being the ratio of the
+// index of refraction of the other side to the one of the side of the light
+pub fn dielectric(cos_i: f64, eta: f64) -> f64 {
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let s = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let p = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (s * s + p * p)
+}
+
+// Share of the light reflected by a conductor of complex index of refraction
+// eta + ik, for each channel
+pub fn conductor(cos_i: f64, eta: Color, k: Color) -> Color {
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_i * cos_i;
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2.0 * cos_i * a;
+        let s = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let p = s * (t3 - t4) / (t3 + t4);
+        0.5 * (s + p)
+    };
+    let (eta, k) = (eta.as_rgb(), k.as_rgb());
+    Color::new(
+        channel(eta.0, k.0),
+        channel(eta.1, k.1),
+        channel(eta.2, k.2),
+    )
+}
+
+// Schlick's approximation, from the reflectance at normal incidence
+pub fn schlick(cos_i: f64, f0: Color) -> Color {
+    let f0 = f0.as_rgb();
+    let t = (1.0 - cos_i).clamp(0.0, 1.0).powi(5);
+    let channel = |f0: f64| f0 + (1.0 - f0) * t;
+    Color::new(channel(f0.0), channel(f0.1), channel(f0.2))
+}
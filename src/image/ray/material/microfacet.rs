@@ -0,0 +1,85 @@
+use super::super::vec3::{Direction, Vector};
+use rand::Rng;
+use std::f64::consts::PI;
+
+// Trowbridge-Reitz (GGX) distribution of the normals of the microfacets of a
+// rough surface, with Smith's masking and shadowing, in the space of the
+// surface whose normal is z
+#[derive(Copy, Clone)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    // `roughness` in [0, 1], squared as usual for a more even progression
+    pub fn new(roughness: f64) -> Self {
+        Ggx {
+            alpha: roughness * roughness,
+        }
+    }
+
+    // Surfaces this smooth are rendered as perfect mirrors
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < 1e-3
+    }
+
+    // Density of the microfacets with this normal
+    pub fn d(&self, h: Direction) -> f64 {
+        if h.get_z() <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = h.get_z() * h.get_z() * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    fn lambda(&self, w: Direction) -> f64 {
+        let cos2 = w.get_z() * w.get_z();
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    // Share of the microfacets visible from `w`
+    pub fn g1(&self, w: Direction) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Share of the microfacets visible from both directions (height-correlated)
+    pub fn g(&self, wo: Direction, wi: Direction) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Probability density of `sample` returning `h`
+    pub fn pdf(&self, wo: Direction, h: Direction) -> f64 {
+        self.g1(wo) * (wo * h).max(0.0) * self.d(h) / wo.get_z()
+    }
+
+    // Normal of a microfacet visible from `wo` (Heitz, "Sampling the GGX
+    // Distribution of Visible Normals", 2018)
+    pub fn sample(&self, wo: Direction) -> Direction {
+        let mut rng = rand::thread_rng();
+        let v = Vector::new(self.alpha * wo.get_x(), self.alpha * wo.get_y(), wo.get_z())
+            .as_unit_vector()
+            .as_vector();
+        let length2 = v.get_x() * v.get_x() + v.get_y() * v.get_y();
+        let t1 = if length2 > 0.0 {
+            Vector::new(-v.get_y(), v.get_x(), 0.0) / length2.sqrt()
+        } else {
+            Vector::new(1.0, 0.0, 0.0)
+        };
+        let t2 = v ^ t1;
+        let r = rng.gen::<f64>().sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.get_z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+        Vector::new(
+            self.alpha * n.get_x(),
+            self.alpha * n.get_y(),
+            n.get_z().max(1e-6),
+        )
+        .as_unit_vector()
+        .as_vector()
+    }
+}
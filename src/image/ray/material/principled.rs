@@ -0,0 +1,319 @@
+use super::super::color::{Color, BLACK, WHITE};
+use super::super::object::Hit;
+use super::super::texture::Texture;
+use super::super::vec3::Direction;
+use super::microfacet::Ggx;
+use super::{
+    cosine_direction, fresnel, glossy, glossy_direction, rough_dielectric,
+    rough_dielectric_direction,
+};
+use rand::Rng;
+use serde::Deserialize;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Layers of the "principled" material of Burley ("Physically Based Shading at
+// Disney", 2012), as in Blender and glTF: a clear coat over a metal, or over a
+// dielectric that is glossy, diffuse with some sheen, or transmissive. The base
+// color is the color of the object.
+#[derive(Deserialize)]
+pub struct Principled {
+    #[serde(default)]
+    metallic: f64,
+    #[serde(default = "half")]
+    roughness: f64,
+    // reflectance at normal incidence of dielectrics, 0.5 being 4%
+    #[serde(default = "half")]
+    specular: f64,
+    // light reflected towards the edges, as by fabrics
+    #[serde(default)]
+    sheen: f64,
+    // sheen of the base color rather than white
+    #[serde(default = "half")]
+    sheen_tint: f64,
+    #[serde(default)]
+    clearcoat: f64,
+    #[serde(default = "clearcoat_roughness")]
+    clearcoat_roughness: f64,
+    // share of the light going through dielectrics
+    #[serde(default)]
+    transmission: f64,
+    #[serde(default = "ior")]
+    ior: f64,
+    #[serde(default = "black")]
+    emission: Color,
+    #[serde(default = "one")]
+    emission_strength: f64,
+    // scaling the metallic (blue) and roughness (green) values from point to
+    // point, as in glTF
+    #[serde(default)]
+    metallic_roughness: Option<Arc<Texture>>,
+    // scaling the emission from point to point
+    #[serde(default)]
+    emission_texture: Option<Arc<Texture>>,
+}
+
+// perfect mirrors could not be sampled along with the other lobes
+const MIN_ROUGHNESS: f64 = 0.04;
+
+fn half() -> f64 {
+    0.5
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn clearcoat_roughness() -> f64 {
+    0.03
+}
+
+fn ior() -> f64 {
+    1.45
+}
+
+fn black() -> Color {
+    BLACK
+}
+
+fn mix(a: Color, b: Color, t: f64) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+// Weights of the lobes for light scattered towards `wo`
+struct Lobes {
+    clearcoat: f64,
+    specular: f64,
+    diffuse: f64,
+    transmission: f64,
+}
+
+impl Lobes {
+    fn total(&self) -> f64 {
+        self.clearcoat + self.specular + self.diffuse + self.transmission
+    }
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Principled {
+            metallic: 0.0,
+            roughness: half(),
+            specular: half(),
+            sheen: 0.0,
+            sheen_tint: half(),
+            clearcoat: 0.0,
+            clearcoat_roughness: clearcoat_roughness(),
+            transmission: 0.0,
+            ior: ior(),
+            emission: BLACK,
+            emission_strength: 1.0,
+            metallic_roughness: None,
+            emission_texture: None,
+        }
+    }
+}
+
+impl Principled {
+    pub fn with_metallic(self, metallic: f64) -> Self {
+        Principled { metallic, ..self }
+    }
+
+    pub fn with_roughness(self, roughness: f64) -> Self {
+        Principled { roughness, ..self }
+    }
+
+    pub fn with_specular(self, specular: f64) -> Self {
+        Principled { specular, ..self }
+    }
+
+    pub fn with_sheen(self, sheen: f64, sheen_tint: f64) -> Self {
+        Principled {
+            sheen,
+            sheen_tint,
+            ..self
+        }
+    }
+
+    pub fn with_clearcoat(self, clearcoat: f64, clearcoat_roughness: f64) -> Self {
+        Principled {
+            clearcoat,
+            clearcoat_roughness,
+            ..self
+        }
+    }
+
+    pub fn with_transmission(self, transmission: f64, ior: f64) -> Self {
+        Principled {
+            transmission,
+            ior,
+            ..self
+        }
+    }
+
+    pub fn with_emission(self, emission: Color, emission_strength: f64) -> Self {
+        Principled {
+            emission,
+            emission_strength,
+            ..self
+        }
+    }
+
+    pub fn with_metallic_roughness(self, texture: Texture) -> Self {
+        Principled {
+            metallic_roughness: Some(Arc::new(texture)),
+            ..self
+        }
+    }
+
+    pub fn with_emission_texture(self, texture: Texture) -> Self {
+        Principled {
+            emission_texture: Some(Arc::new(texture)),
+            ..self
+        }
+    }
+
+    pub fn emission(&self, hit: &Hit) -> Color {
+        let emission = self.emission_strength * self.emission;
+        match &self.emission_texture {
+            Some(texture) => emission * texture.color(hit),
+            None => emission,
+        }
+    }
+
+    // With the values of its textures at this hit
+    pub(super) fn at(&self, hit: &Hit) -> Principled {
+        let (metallic, roughness) = match &self.metallic_roughness {
+            Some(texture) => {
+                let (_, green, blue) = texture.color(hit).as_rgb();
+                (blue * self.metallic, green * self.roughness)
+            }
+            None => (self.metallic, self.roughness),
+        };
+        Principled {
+            metallic,
+            roughness,
+            metallic_roughness: None,
+            emission_texture: None,
+            ..*self
+        }
+    }
+
+    // Ratio of the index of refraction of the other side to the one of the
+    // side of the hit
+    pub(super) fn eta(&self, hit: &Hit) -> f64 {
+        if hit.front_face {
+            self.ior
+        } else {
+            1.0 / self.ior
+        }
+    }
+
+    fn ggx(&self) -> Ggx {
+        Ggx::new(self.roughness.max(MIN_ROUGHNESS))
+    }
+
+    fn clearcoat_ggx(&self) -> Ggx {
+        Ggx::new(self.clearcoat_roughness.max(MIN_ROUGHNESS))
+    }
+
+    // Reflectance at normal incidence of the specular lobe
+    fn f0(&self, color: Color) -> Color {
+        mix((0.08 * self.specular) * WHITE, color, self.metallic)
+    }
+
+    // Share of the light reflected by the clear coat, which the layers below
+    // don't get
+    fn clearcoat_fresnel(&self, cos: f64) -> f64 {
+        self.clearcoat * fresnel::schlick(cos, 0.04 * WHITE).luminance()
+    }
+
+    // The lobes are sampled in proportion to their weights seen from `wo`
+    fn lobes(&self, color: Color, wo: Direction) -> Lobes {
+        let cos = wo.get_z();
+        let base = 1.0 - self.clearcoat_fresnel(cos);
+        let dielectric = 1.0 - self.metallic;
+        let specular = fresnel::schlick(cos, self.f0(color)).luminance();
+        Lobes {
+            clearcoat: self.clearcoat_fresnel(cos),
+            specular: base * (1.0 - dielectric * self.transmission) * specular,
+            diffuse: base
+                * dielectric
+                * (1.0 - self.transmission)
+                * (1.0 - specular)
+                * (color.luminance() + self.sheen).max(1e-3),
+            transmission: base * dielectric * self.transmission,
+        }
+    }
+
+    // As `Material::eval`, in the space of the surface, `eta` being the ratio
+    // of the index of refraction of the other side to the one of `wo`
+    pub(super) fn eval(
+        &self,
+        color: Color,
+        eta: f64,
+        wo: Direction,
+        wi: Direction,
+    ) -> (Color, f64) {
+        let lobes = self.lobes(color, wo);
+        let total = lobes.total();
+        if total <= 0.0 {
+            return (BLACK, 0.0);
+        }
+        let mut value = BLACK;
+        let mut pdf = 0.0;
+        let (clearcoat_value, clearcoat_pdf) = glossy(&self.clearcoat_ggx(), wo, wi, |cos| {
+            self.clearcoat_fresnel(cos) * WHITE
+        });
+        value = value + clearcoat_value;
+        pdf += lobes.clearcoat * clearcoat_pdf;
+        let base = 1.0 - self.clearcoat_fresnel(wo.get_z());
+        let dielectric = 1.0 - self.metallic;
+        let ggx = self.ggx();
+        let f0 = self.f0(color);
+        let (specular_value, specular_pdf) = glossy(&ggx, wo, wi, |cos| fresnel::schlick(cos, f0));
+        value = value + (base * (1.0 - dielectric * self.transmission)) * specular_value;
+        pdf += lobes.specular * specular_pdf;
+        if wi.get_z() > 0.0 {
+            let f = fresnel::schlick(wo.get_z(), f0).luminance();
+            let h = (wo + wi).as_unit_vector().as_vector();
+            let tint = if color.luminance() > 0.0 {
+                (1.0 / color.luminance()) * color
+            } else {
+                WHITE
+            };
+            let sheen =
+                (self.sheen * (1.0 - wi * h).max(0.0).powi(5)) * mix(WHITE, tint, self.sheen_tint);
+            let diffuse = (1.0 / PI) * color + sheen;
+            value = value
+                + (base * dielectric * (1.0 - self.transmission) * (1.0 - f) * wi.get_z())
+                    * diffuse;
+            pdf += lobes.diffuse * wi.get_z() / PI;
+        }
+        if self.transmission > 0.0 {
+            let (transmission_value, transmission_pdf) = rough_dielectric(&ggx, eta, color, wo, wi);
+            value = value + (base * dielectric * self.transmission) * transmission_value;
+            pdf += lobes.transmission * transmission_pdf;
+        }
+        (value, pdf / total)
+    }
+
+    // A direction sampled from one of the lobes
+    pub(super) fn direction(&self, color: Color, eta: f64, wo: Direction) -> Option<Direction> {
+        let lobes = self.lobes(color, wo);
+        let mut choice = rand::thread_rng().gen::<f64>() * lobes.total();
+        choice -= lobes.clearcoat;
+        if choice < 0.0 {
+            return Some(glossy_direction(&self.clearcoat_ggx(), wo));
+        }
+        choice -= lobes.specular;
+        if choice < 0.0 {
+            return Some(glossy_direction(&self.ggx(), wo));
+        }
+        choice -= lobes.diffuse;
+        if choice < 0.0 {
+            return Some(cosine_direction());
+        }
+        rough_dielectric_direction(&self.ggx(), eta, wo)
+    }
+}
@@ -0,0 +1,176 @@
+pub mod grid;
+
+use super::color::{Color, WHITE};
+use super::vec3::UnitDirection;
+use super::Ray;
+use grid::Grid;
+use rand::Rng;
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+// Participating medium (fog, smoke, murky water...), filling the scene or the
+// inside of an object
+#[derive(Deserialize)]
+pub struct Medium {
+    // per unit of distance, where the density is 1
+    #[serde(default)]
+    absorption: f64,
+    #[serde(default)]
+    scattering: f64,
+    // of the scattered light
+    #[serde(default = "white")]
+    color: Color,
+    // Henyey and Greenstein's g, in (-1, 1), positive when light is mostly
+    // scattered forward
+    #[serde(default)]
+    anisotropy: f64,
+    // scaling absorption and scattering from point to point, 1 everywhere
+    // by default
+    #[serde(default)]
+    density: Option<Grid>,
+}
+
+fn white() -> Color {
+    WHITE
+}
+
+impl Medium {
+    pub fn new(absorption: f64, scattering: f64) -> Self {
+        Medium {
+            absorption,
+            scattering,
+            color: WHITE,
+            anisotropy: 0.0,
+            density: None,
+        }
+    }
+
+    pub fn with_color(self, color: Color) -> Self {
+        Medium { color, ..self }
+    }
+
+    pub fn with_anisotropy(self, anisotropy: f64) -> Self {
+        Medium { anisotropy, ..self }
+    }
+
+    pub fn with_density(self, density: Grid) -> Self {
+        Medium {
+            density: Some(density),
+            ..self
+        }
+    }
+
+    fn extinction(&self) -> f64 {
+        self.absorption + self.scattering
+    }
+
+    // Share of the light going through the ray up to `distance`, estimated
+    // by ratio tracking in grids
+    pub fn transmittance(&self, ray: &Ray, distance: f64) -> f64 {
+        let extinction = self.extinction();
+        let grid = match &self.density {
+            Some(grid) => grid,
+            None => return (-extinction * distance).exp(),
+        };
+        let mut rng = rand::thread_rng();
+        let mut res = 1.0;
+        grid.traverse(
+            ray.location,
+            ray.direction.as_vector(),
+            distance,
+            |t0, t1, majorant| {
+                let majorant = majorant * extinction;
+                let mut t = t0;
+                if majorant <= 0.0 {
+                    return true;
+                }
+                loop {
+                    t -= (1.0 - rng.gen::<f64>()).ln() / majorant;
+                    if t >= t1 {
+                        return res > 0.0;
+                    }
+                    res *= 1.0 - extinction * grid.density(ray.at(t)) / majorant;
+                }
+            },
+        );
+        res
+    }
+
+    // Distance travelled by light along the ray before colliding with the
+    // medium, if it is less than `distance` (free-flight sampling, by delta
+    // tracking in grids)
+    pub fn sample_distance(&self, ray: &Ray, distance: f64) -> Option<f64> {
+        let extinction = self.extinction();
+        if extinction <= 0.0 {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        let grid = match &self.density {
+            Some(grid) => grid,
+            None => {
+                let t = -(1.0 - rng.gen::<f64>()).ln() / extinction;
+                return if t < distance { Some(t) } else { None };
+            }
+        };
+        // collisions with a medium as dense as the densest point of each
+        // block, the real ones being in proportion to the actual density
+        let mut res = None;
+        grid.traverse(
+            ray.location,
+            ray.direction.as_vector(),
+            distance,
+            |t0, t1, majorant| {
+                let majorant = majorant * extinction;
+                let mut t = t0;
+                if majorant <= 0.0 {
+                    return true;
+                }
+                loop {
+                    t -= (1.0 - rng.gen::<f64>()).ln() / majorant;
+                    if t >= t1 {
+                        return true;
+                    }
+                    if rng.gen::<f64>() * majorant < extinction * grid.density(ray.at(t)) {
+                        res = Some(t);
+                        return false;
+                    }
+                }
+            },
+        );
+        res
+    }
+
+    // Light scattered per unit of light colliding with the medium
+    pub fn albedo(&self) -> Color {
+        (self.scattering / self.extinction()) * self.color
+    }
+
+    // Probability density of light going along `direction` being scattered
+    // along `scattered` (Henyey-Greenstein phase function)
+    pub fn phase(&self, direction: UnitDirection, scattered: UnitDirection) -> f64 {
+        let g = self.anisotropy;
+        let d = 1.0 + g * g - 2.0 * g * (direction * scattered);
+        (1.0 - g * g) / (4.0 * PI * d * d.sqrt())
+    }
+
+    // Direction of light going along `direction` once scattered, with its
+    // probability density
+    pub fn sample_phase(&self, direction: UnitDirection) -> (UnitDirection, f64) {
+        let mut rng = rand::thread_rng();
+        let g = self.anisotropy;
+        let u = rng.gen::<f64>();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        let (a, b) = direction.orthonormal_basis();
+        let scattered =
+            (sin_theta * phi.cos() * a + sin_theta * phi.sin() * b + cos_theta * direction)
+                .as_unit_vector();
+        (scattered, self.phase(direction, scattered))
+    }
+}
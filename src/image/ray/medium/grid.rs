@@ -0,0 +1,413 @@
+use super::super::vec3::{Direction, Location};
+use serde::Deserialize;
+use std::fs;
+
+// Densities of a medium on a grid of voxels filling a box of the scene, as
+// simulated for smoke or clouds
+#[derive(Deserialize)]
+#[serde(try_from = "GridData")]
+pub struct Grid {
+    // voxels along x, y and z
+    size: [usize; 3],
+    // x varying the fastest, then y
+    densities: Vec<f32>,
+    min: Location,
+    max: Location,
+    // greatest density in each block of BLOCK voxels along each axis, once
+    // interpolated, for the tracking to skip empty and thin parts
+    blocks: [usize; 3],
+    majorants: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct GridData {
+    file: String,
+    // corners of the box
+    min: Location,
+    max: Location,
+}
+
+const BLOCK: usize = 8;
+
+impl std::convert::TryFrom<GridData> for Grid {
+    type Error = String;
+
+    fn try_from(data: GridData) -> Result<Self, Self::Error> {
+        Grid::load(&data.file, data.min, data.max)
+    }
+}
+
+fn to_array(vector: Location) -> [f64; 3] {
+    [vector.get_x(), vector.get_y(), vector.get_z()]
+}
+
+// Number of voxels of a grid of this size, which comes from the header of a
+// file and may not fit
+fn voxel_count(size: [usize; 3]) -> Result<usize, String> {
+    size[0]
+        .checked_mul(size[1])
+        .and_then(|count| count.checked_mul(size[2]))
+        .ok_or_else(|| format!("grid of {}x{}x{} too big", size[0], size[1], size[2]))
+}
+
+// Text of the header of a NumPy file following `'key':`
+fn npy_value<'a>(header: &'a str, key: &str) -> Result<&'a str, String> {
+    let key = format!("'{}':", key);
+    header
+        .find(&key)
+        .map(|i| header[i + key.len()..].trim_start())
+        .ok_or_else(|| format!("no {} in the header", key))
+}
+
+// Array of 3 dimensions of a NumPy .npy file, as [x][y][z], integers being
+// scaled to [0, 1]
+fn read_npy(data: &[u8]) -> Result<([usize; 3], Vec<f32>), String> {
+    if data.len() < 10 || &data[..6] != b"\x93NUMPY" {
+        return Err("not a NumPy file".to_string());
+    }
+    let (length, start) = match data[6] {
+        1 => (u16::from_le_bytes([data[8], data[9]]) as usize, 10),
+        2 | 3 if data.len() >= 12 => (
+            u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize,
+            12,
+        ),
+        version => return Err(format!("unsupported version {}", version)),
+    };
+    let header = data
+        .get(start..start + length)
+        .ok_or("truncated header")
+        .and_then(|header| std::str::from_utf8(header).map_err(|_| "invalid header"))?;
+    let descr = npy_value(header, "descr")?;
+    let descr = descr
+        .get(1..)
+        .and_then(|descr| descr.split('\'').next())
+        .ok_or("invalid descr")?;
+    let fortran_order = npy_value(header, "fortran_order")?.starts_with("True");
+    let shape = npy_value(header, "shape")?;
+    let shape = shape
+        .get(1..shape.find(')').ok_or("invalid shape")?)
+        .ok_or("invalid shape")?
+        .split(',')
+        .map(str::trim)
+        .filter(|dimension| !dimension.is_empty())
+        .map(|dimension| dimension.parse::<usize>().map_err(|e| e.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let size = match shape.as_slice() {
+        [x, y, z] => [*x, *y, *z],
+        _ => return Err(format!("{} dimensions instead of 3", shape.len())),
+    };
+    let values = &data[start + length..];
+    let count = voxel_count(size)?;
+    let value: fn(&[u8]) -> f32 = match descr {
+        "<f4" | "=f4" => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        "<f8" | "=f8" => {
+            |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32
+        }
+        "|u1" => |b| b[0] as f32 / 255.0,
+        "<u2" | "=u2" => |b| u16::from_le_bytes([b[0], b[1]]) as f32 / 65535.0,
+        _ => return Err(format!("unsupported type {}", descr)),
+    };
+    let width: usize = descr[2..].parse().map_err(|_| "invalid descr")?;
+    if count
+        .checked_mul(width)
+        .is_none_or(|bytes| values.len() < bytes)
+    {
+        return Err("truncated data".to_string());
+    }
+    let values: Vec<f32> = values.chunks(width).take(count).map(value).collect();
+    if fortran_order {
+        return Ok((size, values));
+    }
+    // z varies the fastest in C order
+    let mut densities = vec![0.0; count];
+    for (i, value) in values.into_iter().enumerate() {
+        let (x, y, z) = (i / (size[1] * size[2]), i / size[2] % size[1], i % size[2]);
+        densities[x + size[0] * (y + size[1] * z)] = value;
+    }
+    Ok((size, densities))
+}
+
+// Own format: "RVOL", the size along x, y and z as 32 bits integers, then the
+// densities as 32 bits floats, x varying the fastest, then y, all in little
+// endian
+fn read_rvol(data: &[u8]) -> Result<([usize; 3], Vec<f32>), String> {
+    if data.len() < 16 || &data[..4] != b"RVOL" {
+        return Err("not a voxel file".to_string());
+    }
+    let integer =
+        |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]) as usize;
+    let size = [integer(4), integer(8), integer(12)];
+    let count = voxel_count(size)?;
+    let values = &data[16..];
+    if count
+        .checked_mul(4)
+        .is_none_or(|bytes| values.len() < bytes)
+    {
+        return Err("truncated data".to_string());
+    }
+    let densities = values
+        .chunks(4)
+        .take(count)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    Ok((size, densities))
+}
+
+impl Grid {
+    pub fn new(
+        size: [usize; 3],
+        densities: Vec<f32>,
+        min: Location,
+        max: Location,
+    ) -> Result<Self, String> {
+        if size.contains(&0) || densities.len() != voxel_count(size)? {
+            return Err(format!(
+                "{} densities for a grid of {}x{}x{}",
+                densities.len(),
+                size[0],
+                size[1],
+                size[2]
+            ));
+        }
+        let (a, b) = (to_array(min), to_array(max));
+        if (0..3).any(|i| a[i] >= b[i]) {
+            return Err("the min corner of a grid must be below its max corner".to_string());
+        }
+        let densities: Vec<f32> = densities.into_iter().map(|d| d.max(0.0)).collect();
+        let blocks = [
+            size[0].div_ceil(BLOCK),
+            size[1].div_ceil(BLOCK),
+            size[2].div_ceil(BLOCK),
+        ];
+        let mut res = Grid {
+            size,
+            densities,
+            min,
+            max,
+            blocks,
+            majorants: Vec::with_capacity(blocks[0] * blocks[1] * blocks[2]),
+        };
+        for z in 0..blocks[2] {
+            for y in 0..blocks[1] {
+                for x in 0..blocks[0] {
+                    res.majorants.push(res.block_majorant([x, y, z]));
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    // A .npy file or a .rvol file filling the box between both corners
+    pub fn load(file_name: &str, min: Location, max: Location) -> Result<Self, String> {
+        let data = fs::read(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+        let (size, densities) = if file_name.ends_with(".npy") {
+            read_npy(&data)
+        } else if file_name.ends_with(".rvol") {
+            read_rvol(&data)
+        } else {
+            Err("unsupported format, expected .npy or .rvol".to_string())
+        }
+        .map_err(|e| format!("{}: {}", file_name, e))?;
+        Grid::new(size, densities, min, max)
+    }
+
+    // The voxel at these indices, the ones on the faces outside of the grid
+    fn voxel(&self, x: i64, y: i64, z: i64) -> f32 {
+        let clamp = |i: i64, axis: usize| i.clamp(0, self.size[axis] as i64 - 1) as usize;
+        let (x, y, z) = (clamp(x, 0), clamp(y, 1), clamp(z, 2));
+        self.densities[x + self.size[0] * (y + self.size[1] * z)]
+    }
+
+    // Interpolating between voxels also uses the ones around the block
+    fn block_majorant(&self, block: [usize; 3]) -> f32 {
+        let range = |axis: usize| {
+            let start = (BLOCK * block[axis]) as i64;
+            start - 1..=(start + BLOCK as i64).min(self.size[axis] as i64)
+        };
+        let mut res: f32 = 0.0;
+        for z in range(2) {
+            for y in range(1) {
+                for x in range(0) {
+                    res = res.max(self.voxel(x, y, z));
+                }
+            }
+        }
+        res
+    }
+
+    // Position in the grid, in voxels from the min corner
+    fn position(&self, point: Location) -> [f64; 3] {
+        let (p, a, b) = (to_array(point), to_array(self.min), to_array(self.max));
+        let mut res = [0.0; 3];
+        for i in 0..3 {
+            res[i] = (p[i] - a[i]) / (b[i] - a[i]) * self.size[i] as f64;
+        }
+        res
+    }
+
+    // Density at this point, interpolated between the centers of the voxels,
+    // 0 outside of the grid
+    pub fn density(&self, point: Location) -> f64 {
+        let position = self.position(point);
+        if (0..3).any(|i| !(0.0..=self.size[i] as f64).contains(&position[i])) {
+            return 0.0;
+        }
+        let mut base = [0; 3];
+        let mut fraction = [0.0; 3];
+        for i in 0..3 {
+            let p = position[i] - 0.5;
+            base[i] = p.floor() as i64;
+            fraction[i] = p - p.floor();
+        }
+        let mut res = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = base;
+            for i in 0..3 {
+                if corner >> i & 1 == 1 {
+                    weight *= fraction[i];
+                    index[i] += 1;
+                } else {
+                    weight *= 1.0 - fraction[i];
+                }
+            }
+            res += weight * self.voxel(index[0], index[1], index[2]) as f64;
+        }
+        res
+    }
+
+    // Calls `f` with the parts of the ray up to `distance` going through the
+    // blocks of the grid, from `t0` to `t1`, and the greatest density there,
+    // in order until it returns false
+    pub fn traverse<F: FnMut(f64, f64, f64) -> bool>(
+        &self,
+        origin: Location,
+        direction: Direction,
+        distance: f64,
+        mut f: F,
+    ) {
+        // in blocks from the min corner
+        let (a, b) = (to_array(self.min), to_array(self.max));
+        let (o, d) = (to_array(origin), to_array(direction));
+        let mut origin = [0.0; 3];
+        let mut direction = [0.0; 3];
+        let (mut t, mut end) = (0.0_f64, distance);
+        for i in 0..3 {
+            let scale = self.size[i] as f64 / ((b[i] - a[i]) * BLOCK as f64);
+            origin[i] = (o[i] - a[i]) * scale;
+            direction[i] = d[i] * scale;
+            let extent = self.size[i] as f64 / BLOCK as f64;
+            if direction[i] == 0.0 {
+                if !(0.0..=extent).contains(&origin[i]) {
+                    return;
+                }
+            } else {
+                let t1 = -origin[i] / direction[i];
+                let t2 = (extent - origin[i]) / direction[i];
+                t = t.max(t1.min(t2));
+                end = end.min(t1.max(t2));
+            }
+        }
+        if t >= end {
+            return;
+        }
+        let mut block = [0; 3];
+        let mut step = [0; 3];
+        let mut next = [f64::INFINITY; 3];
+        let mut delta = [f64::INFINITY; 3];
+        for i in 0..3 {
+            let p = origin[i] + t * direction[i];
+            block[i] = (p.floor() as i64).clamp(0, self.blocks[i] as i64 - 1);
+            if direction[i] > 0.0 {
+                step[i] = 1;
+                next[i] = ((block[i] + 1) as f64 - origin[i]) / direction[i];
+                delta[i] = 1.0 / direction[i];
+            } else if direction[i] < 0.0 {
+                step[i] = -1;
+                next[i] = (block[i] as f64 - origin[i]) / direction[i];
+                delta[i] = -1.0 / direction[i];
+            }
+        }
+        loop {
+            let axis = if next[0] < next[1] {
+                if next[0] < next[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if next[1] < next[2] {
+                1
+            } else {
+                2
+            };
+            let exit = next[axis].min(end);
+            let index = block[0] as usize
+                + self.blocks[0] * (block[1] as usize + self.blocks[1] * block[2] as usize);
+            if exit > t && !f(t, exit, self.majorants[index] as f64) {
+                return;
+            }
+            if exit >= end {
+                return;
+            }
+            t = exit;
+            block[axis] += step[axis];
+            if block[axis] < 0 || block[axis] >= self.blocks[axis] as i64 {
+                return;
+            }
+            next[axis] += delta[axis];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rvol(size: [u32; 3], densities: &[f32]) -> Vec<u8> {
+        let mut res = b"RVOL".to_vec();
+        for n in size.iter() {
+            res.extend_from_slice(&n.to_le_bytes());
+        }
+        for density in densities {
+            res.extend_from_slice(&density.to_le_bytes());
+        }
+        res
+    }
+
+    fn npy(descr: &str, shape: &str, values: &[u8]) -> Vec<u8> {
+        let header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}\n",
+            descr, shape
+        );
+        let mut res = b"\x93NUMPY\x01\x00".to_vec();
+        res.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        res.extend_from_slice(header.as_bytes());
+        res.extend_from_slice(values);
+        res
+    }
+
+    #[test]
+    fn reads_rvol_files() {
+        let (size, densities) = read_rvol(&rvol([2, 1, 1], &[0.25, 0.5])).unwrap();
+        assert_eq!(size, [2, 1, 1]);
+        assert_eq!(densities, vec![0.25, 0.5]);
+        assert!(read_rvol(&rvol([2, 2, 1], &[0.25, 0.5])).is_err());
+    }
+
+    #[test]
+    fn reads_npy_files_in_c_order() {
+        // z varies the fastest in the file, x in the grid
+        let (size, densities) = read_npy(&npy("|u1", "(2, 1, 2)", &[0, 51, 102, 255])).unwrap();
+        assert_eq!(size, [2, 1, 2]);
+        assert_eq!(densities, vec![0.0, 0.4, 0.2, 1.0]);
+    }
+
+    #[test]
+    fn rejects_sizes_that_overflow() {
+        assert!(read_rvol(&rvol([u32::MAX, u32::MAX, u32::MAX], &[1.0])).is_err());
+        let huge = format!("({}, {}, 2)", usize::MAX / 2 + 1, 1);
+        assert!(read_npy(&npy("<f4", &huge, &[0; 8])).is_err());
+        let wrapping = format!("({}, 4, 1)", usize::MAX / 4 + 1);
+        assert!(read_npy(&npy("|u1", &wrapping, &[0; 8])).is_err());
+    }
+}
@@ -0,0 +1,566 @@
+pub mod capsule;
+pub mod cone;
+pub mod csg;
+pub mod cuboid;
+pub mod cylinder;
+pub mod disk;
+pub mod heightfield;
+pub mod mesh;
+pub mod plane;
+mod polynomial;
+pub mod quad;
+pub mod sdf;
+pub mod sphere;
+pub mod torus;
+
+use super::bump::Bump;
+use super::bvh::Aabb;
+use super::color::Color;
+use super::material::Material;
+use super::medium::Medium;
+use super::texture::Texture;
+use super::transform::{Motion, Transform};
+use super::vec3::{Direction, Location, UnitDirection, Vector};
+use super::Ray;
+use capsule::Capsule;
+use cone::Cone;
+use csg::Csg;
+use cuboid::Cuboid;
+use cylinder::Cylinder;
+use disk::Disk;
+use heightfield::Heightfield;
+use mesh::Mesh;
+use plane::Plane;
+use quad::Quad;
+use sdf::Sdf;
+use serde::Deserialize;
+use sphere::Sphere;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use torus::Torus;
+
+// Hits closer than this are ignored, so that rays bouncing off a surface
+// don't hit it again because of floating point errors.
+pub const T_MIN: f64 = 0.0001;
+
+#[derive(Copy, Clone)]
+pub struct Hit {
+    pub t: f64,
+    // faces the ray, i.e. points inside the object when hit from the inside
+    pub normal: UnitDirection,
+    pub front_face: bool,
+    // texture coordinates, in [0, 1] for bounded objects
+    pub u: f64,
+    pub v: f64,
+    // in the space of the object, for solid textures
+    pub point: Location,
+    // direction in which u increases, for normal maps
+    pub tangent: UnitDirection,
+    // of the vertices around the hit, for meshes with vertex colors
+    pub color: Option<Color>,
+}
+
+pub trait Hittable {
+    // Closest hit with t in [t_min, t_max]
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit>;
+
+    // None for unbounded objects (e.g. infinite planes)
+    fn bounds(&self) -> Option<Aabb>;
+}
+
+impl Hit {
+    // `normal` points outside the object, until the hit is oriented with
+    // `Hit::facing`
+    fn new(t: f64, normal: UnitDirection, u: f64, v: f64) -> Self {
+        Hit {
+            t,
+            normal,
+            front_face: true,
+            u,
+            v,
+            // set by `Shape::hit`
+            point: Location::new(0.0, 0.0, 0.0),
+            // any direction along the surface, for shapes that don't set it
+            tangent: normal.orthonormal_basis().0,
+            color: None,
+        }
+    }
+
+    // Ignored when it is along the normal (e.g. at the poles of a sphere)
+    fn with_tangent(self, tangent: Direction) -> Self {
+        if (tangent ^ self.normal).length_squared() < 1e-12 {
+            self
+        } else {
+            Hit {
+                tangent: tangent.as_unit_vector(),
+                ..self
+            }
+        }
+    }
+
+    fn facing(self, ray: &Ray) -> Self {
+        let front_face = self.normal * ray.direction < 0.0;
+        Hit {
+            normal: if front_face {
+                self.normal
+            } else {
+                -self.normal
+            },
+            front_face,
+            ..self
+        }
+    }
+}
+
+// Part of a ray inside a solid, the ray enters it at `enter` and exits it at
+// `exit` (normals always point outside the solid)
+#[derive(Copy, Clone)]
+pub struct Interval {
+    pub enter: Hit,
+    pub exit: Hit,
+}
+
+// Closed objects, whose intervals are reported along the whole line of the
+// ray (including behind its origin)
+pub trait Solid {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval>;
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Shape {
+    Sphere(Sphere),
+    Plane(Plane),
+    #[serde(alias = "rectangle")]
+    Quad(Quad),
+    Disk(Disk),
+    #[serde(rename = "box")]
+    Cuboid(Cuboid),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Capsule(Capsule),
+    Torus(Torus),
+    Csg(Csg),
+    Sdf(Sdf),
+    Heightfield(Heightfield),
+    Mesh(Mesh),
+}
+
+// The shape is shared between the instances of an object
+#[derive(Clone)]
+pub struct Object {
+    shape: Arc<Shape>,
+    texture: Arc<Texture>,
+    material: Arc<Material>,
+    bump: Option<Arc<Bump>>,
+    // inside the object, which must be closed
+    medium: Option<Arc<Medium>>,
+    transform: Option<Transform>,
+    // transforms from the object to the scene, for moving objects
+    motion: Vec<Motion>,
+}
+
+impl Hittable for Shape {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let hit = match self {
+            Shape::Sphere(sphere) => sphere.hit(ray, t_min, t_max),
+            Shape::Plane(plane) => plane.hit(ray, t_min, t_max),
+            Shape::Quad(quad) => quad.hit(ray, t_min, t_max),
+            Shape::Disk(disk) => disk.hit(ray, t_min, t_max),
+            Shape::Cuboid(cuboid) => cuboid.hit(ray, t_min, t_max),
+            Shape::Cylinder(cylinder) => cylinder.hit(ray, t_min, t_max),
+            Shape::Cone(cone) => cone.hit(ray, t_min, t_max),
+            Shape::Capsule(capsule) => capsule.hit(ray, t_min, t_max),
+            Shape::Torus(torus) => torus.hit(ray, t_min, t_max),
+            Shape::Csg(csg) => csg.hit(ray, t_min, t_max),
+            Shape::Sdf(sdf) => sdf.hit(ray, t_min, t_max),
+            Shape::Heightfield(heightfield) => heightfield.hit(ray, t_min, t_max),
+            Shape::Mesh(mesh) => mesh.hit(ray, t_min, t_max),
+        }?;
+        Some(Hit {
+            point: ray.at(hit.t),
+            ..hit
+        })
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Shape::Sphere(sphere) => sphere.bounds(),
+            Shape::Plane(plane) => plane.bounds(),
+            Shape::Quad(quad) => quad.bounds(),
+            Shape::Disk(disk) => disk.bounds(),
+            Shape::Cuboid(cuboid) => cuboid.bounds(),
+            Shape::Cylinder(cylinder) => cylinder.bounds(),
+            Shape::Cone(cone) => cone.bounds(),
+            Shape::Capsule(capsule) => capsule.bounds(),
+            Shape::Torus(torus) => torus.bounds(),
+            Shape::Csg(csg) => csg.bounds(),
+            Shape::Sdf(sdf) => sdf.bounds(),
+            Shape::Heightfield(heightfield) => heightfield.bounds(),
+            Shape::Mesh(mesh) => mesh.bounds(),
+        }
+    }
+}
+
+impl Shape {
+    pub fn has_colors(&self) -> bool {
+        match self {
+            Shape::Mesh(mesh) => mesh.has_colors(),
+            _ => false,
+        }
+    }
+}
+
+impl Hittable for Object {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        match &self.transform_at(ray.time) {
+            Some(transform) => {
+                let (local, scale) = transform.inverse_ray(ray);
+                let hit = self.shape.hit(&local, t_min * scale, t_max * scale)?;
+                let hit = self.bumped(&local, hit);
+                Some(Hit {
+                    t: hit.t / scale,
+                    normal: transform.normal(hit.normal),
+                    tangent: transform
+                        .direction(hit.tangent.as_vector())
+                        .as_unit_vector(),
+                    ..hit
+                })
+            }
+            None => self
+                .shape
+                .hit(ray, t_min, t_max)
+                .map(|hit| self.bumped(ray, hit)),
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let (start, end) = self
+            .motion
+            .iter()
+            .filter_map(Motion::span)
+            .fold((0.0, 0.0), |(start, end): (f64, f64), (first, last)| {
+                (start.min(first), end.max(last))
+            });
+        self.bounds_during(start, end)
+    }
+}
+
+impl Object {
+    pub fn new(shape: Arc<Shape>, texture: Arc<Texture>, transform: Option<Transform>) -> Self {
+        Object {
+            shape,
+            texture,
+            material: Arc::new(Material::default()),
+            bump: None,
+            medium: None,
+            transform,
+            motion: Vec::new(),
+        }
+    }
+
+    pub fn with_material(self, material: Arc<Material>) -> Self {
+        Object { material, ..self }
+    }
+
+    pub fn with_bump(self, bump: Option<Arc<Bump>>) -> Self {
+        Object { bump, ..self }
+    }
+
+    // `motion` goes from the object to the scene
+    pub fn animated(shape: Arc<Shape>, texture: Arc<Texture>, motion: Vec<Motion>) -> Self {
+        if motion.iter().all(|motion| motion.span().is_none()) {
+            let transform = motion
+                .iter()
+                .map(|motion| motion.at(0.0))
+                .reduce(|res, transform| res.then(&transform));
+            Object::new(shape, texture, transform)
+        } else {
+            Object {
+                shape,
+                texture,
+                material: Arc::new(Material::default()),
+                bump: None,
+                medium: None,
+                transform: None,
+                motion,
+            }
+        }
+    }
+
+    // Color of the surface at this hit
+    pub fn color(&self, hit: &Hit) -> Color {
+        self.texture.color(hit)
+    }
+
+    pub fn with_medium(self, medium: Option<Arc<Medium>>) -> Self {
+        Object { medium, ..self }
+    }
+
+    pub fn get_material(&self) -> &Material {
+        &self.material
+    }
+
+    pub fn get_medium(&self) -> Option<Arc<Medium>> {
+        self.medium.clone()
+    }
+
+    // Whether light goes straight through the surface
+    pub fn is_boundary(&self) -> bool {
+        matches!(*self.material, Material::Boundary)
+    }
+
+    // The hit with the normal changed by the bump map, unless the new one
+    // faces away from the ray
+    fn bumped(&self, ray: &Ray, hit: Hit) -> Hit {
+        match &self.bump {
+            Some(bump) => {
+                let normal = bump.normal(&hit);
+                if normal * ray.direction < 0.0 {
+                    Hit { normal, ..hit }
+                } else {
+                    hit
+                }
+            }
+            None => hit,
+        }
+    }
+
+    pub fn is_moving(&self) -> bool {
+        !self.motion.is_empty()
+    }
+
+    pub fn transform_at(&self, time: f64) -> Option<Transform> {
+        if self.motion.is_empty() {
+            self.transform
+        } else {
+            self.motion
+                .iter()
+                .map(|motion| motion.at(time))
+                .reduce(|res, transform| res.then(&transform))
+        }
+    }
+
+    // Bounds of the places it goes through between these times
+    pub fn bounds_during(&self, start: f64, end: f64) -> Option<Aabb> {
+        const STEPS_PER_FRAME: f64 = 16.0;
+        // largest move of a corner between two positions, relative to the
+        // size of the object, and shortest time between two positions
+        const TOLERANCE: f64 = 1.0 / 16.0;
+        const MIN_STEP: f64 = 1.0 / 4096.0;
+        let bounds = self.shape.bounds()?;
+        if self.motion.is_empty() {
+            return self.bounds_at(start);
+        }
+        let corners = |time: f64| {
+            let transform = self
+                .transform_at(time)
+                .expect("moving objects have a transform");
+            (
+                time,
+                bounds.corners().map(|corner| transform.location(corner)),
+            )
+        };
+        let moved = |a: &[Location; 8], b: &[Location; 8]| {
+            (0..8).map(|i| (b[i] - a[i]).length()).fold(0.0, f64::max)
+        };
+        let steps = ((end - start) * STEPS_PER_FRAME).ceil().max(1.0) as usize;
+        // the next positions to reach are on top
+        let mut pending: Vec<(f64, [Location; 8])> = (1..=steps)
+            .rev()
+            .map(|i| corners(start + (end - start) * i as f64 / steps as f64))
+            .collect();
+        let mut positions = vec![corners(start)];
+        let tolerance = TOLERANCE * (positions[0].1[7] - positions[0].1[0]).length();
+        let mut padding: f64 = 0.0;
+        // positions are added half way between two others until no corner
+        // moves more than the tolerance from one to the next. The path of a
+        // corner is smooth, so at that scale it is about straight and stays
+        // within that distance of the positions on each side of it
+        while let Some(&next) = pending.last() {
+            let previous = positions[positions.len() - 1];
+            let middle = corners(0.5 * (previous.0 + next.0));
+            let distance = moved(&previous.1, &middle.1).max(moved(&middle.1, &next.1));
+            if distance <= tolerance || next.0 - previous.0 <= MIN_STEP {
+                padding = padding.max(distance);
+                positions.push(middle);
+                positions.push(next);
+                pending.pop();
+            } else {
+                pending.push(middle);
+            }
+        }
+        let res = positions
+            .iter()
+            .flat_map(|(_, corners)| corners.iter())
+            .map(|corner| Aabb::new(*corner, *corner))
+            .reduce(|res, bounds| res.union(&bounds))?;
+        Some(res.padded(padding))
+    }
+
+    pub fn bounds_at(&self, time: f64) -> Option<Aabb> {
+        let bounds = self.shape.bounds()?;
+        Some(match self.transform_at(time) {
+            Some(transform) => transform.bounds(&bounds),
+            None => bounds,
+        })
+    }
+}
+
+// Orthonormal frame used by objects defined around an axis, whose local z
+// coordinate is along that axis
+pub(crate) struct Frame {
+    origin: Location,
+    x: UnitDirection,
+    y: UnitDirection,
+    z: UnitDirection,
+}
+
+impl Frame {
+    pub(crate) fn new(origin: Location, z: UnitDirection) -> Self {
+        let (x, y) = z.orthonormal_basis();
+        Frame { origin, x, y, z }
+    }
+
+    fn to_local(&self, location: Location) -> Vector {
+        let v = location - self.origin;
+        Vector::new(v * self.x, v * self.y, v * self.z)
+    }
+
+    pub(crate) fn to_local_direction(&self, direction: UnitDirection) -> Vector {
+        Vector::new(direction * self.x, direction * self.y, direction * self.z)
+    }
+
+    pub(crate) fn to_world_direction(&self, direction: Direction) -> UnitDirection {
+        (direction.get_x() * self.x + direction.get_y() * self.y + direction.get_z() * self.z)
+            .as_unit_vector()
+    }
+
+    // Direction in which `azimuth` increases at this local point
+    fn tangent(&self, local: Vector) -> Direction {
+        -local.get_y() * self.x + local.get_x() * self.y
+    }
+}
+
+// Angle around the local z axis, mapped to [0, 1]
+fn azimuth(local: Vector) -> f64 {
+    (local.get_y().atan2(local.get_x()) + PI) / (2.0 * PI)
+}
+
+// Closest of the candidate hits with t in [t_min, t_max]
+fn closest(ray: &Ray, hits: Vec<Hit>, t_min: f64, t_max: f64) -> Option<Hit> {
+    hits.into_iter()
+        .filter(|hit| (t_min..=t_max).contains(&hit.t))
+        .min_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|hit| hit.facing(ray))
+}
+
+// Pairs up the crossings of a ray with the surface of a closed object, each
+// entering or exiting it depending on its normal (crossings tangent to the
+// surface, found once or twice, and the second one of a crossing on an edge
+// between two faces don't change whether the ray is inside)
+fn intervals(mut hits: Vec<Hit>, direction: UnitDirection) -> Vec<Interval> {
+    hits.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(std::cmp::Ordering::Equal));
+    let mut res = Vec::new();
+    let mut enter = None;
+    for hit in hits {
+        let cos = hit.normal * direction;
+        if cos < -1e-6 && enter.is_none() {
+            enter = Some(hit);
+        } else if cos > 1e-6 {
+            if let Some(enter) = enter.take() {
+                res.push(Interval { enter, exit: hit });
+            }
+        }
+    }
+    res
+}
+
+// Intersection of a ray with the plane going through `point` with the given
+// normal, whose front face is the side the normal points to
+fn hit_plane(
+    ray: &Ray,
+    point: Location,
+    normal: UnitDirection,
+    t_min: f64,
+    t_max: f64,
+) -> Option<Hit> {
+    let denominator = normal * ray.direction;
+    if denominator.abs() < 1e-12 {
+        return None;
+    }
+    let t = (point - ray.location) * normal / denominator;
+    if (t_min..=t_max).contains(&t) {
+        Some(Hit::new(t, normal, 0.0, 0.0).facing(ray))
+    } else {
+        None
+    }
+}
+
+fn disk_bounds(center: Location, normal: UnitDirection, radius: f64) -> Aabb {
+    let extent = |n: f64| radius * (1.0 - n * n).max(0.0).sqrt();
+    let half = Vector::new(
+        extent(normal.get_x()),
+        extent(normal.get_y()),
+        extent(normal.get_z()),
+    );
+    Aabb::new(center - half, center + half)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::animation::{Animation, Interpolation, Keyframe};
+    use super::*;
+    use std::convert::TryFrom;
+
+    // Closest hit of the ray going from `from` towards `towards`
+    pub fn hit(
+        shape: &impl Hittable,
+        from: (f64, f64, f64),
+        towards: (f64, f64, f64),
+    ) -> Option<Hit> {
+        let ray = Ray::new(
+            Location::new(from.0, from.1, from.2),
+            UnitDirection::new(towards.0, towards.1, towards.2),
+        );
+        shape.hit(&ray, 0.0, f64::INFINITY)
+    }
+
+    pub fn assert_hit(hit: Option<Hit>, t: f64, normal: (f64, f64, f64)) {
+        let hit = hit.expect("no hit");
+        assert!((hit.t - t).abs() < 1e-9, "t = {}", hit.t);
+        let normal = Vector::new(normal.0, normal.1, normal.2).as_unit_vector();
+        assert!((hit.normal.as_vector() - normal.as_vector()).length() < 1e-9);
+    }
+
+    #[test]
+    fn bounds_turns_faster_than_the_steps() {
+        // 170 degrees every 64th of a frame around the y axis
+        let axis = UnitDirection::new(0.0, 1.0, 0.0);
+        let keyframes = (0..=8)
+            .map(|i| Keyframe {
+                frame: i as f64 / 64.0,
+                value: Transform::rotation(axis, 170.0 * i as f64),
+            })
+            .collect();
+        let animation = Animation::new(keyframes, Interpolation::Linear).unwrap();
+        let center = Location::new(1.0, 0.0, 0.0);
+        let object = Object::animated(
+            Arc::new(Shape::Sphere(Sphere::new(center, 0.1))),
+            Arc::new(Texture::Constant {
+                color: Color::new(1.0, 1.0, 1.0),
+            }),
+            vec![Motion::try_from(&animation).unwrap()],
+        );
+        let corners = object.bounds_during(0.0, 0.125).unwrap().corners();
+        let (min, max) = (corners[0], corners[7]);
+        for i in 0..=1000 {
+            let moved = object
+                .transform_at(0.125 * i as f64 / 1000.0)
+                .unwrap()
+                .location(center);
+            assert!(moved.get_x() - 0.1 >= min.get_x() && moved.get_x() + 0.1 <= max.get_x());
+            assert!(moved.get_z() - 0.1 >= min.get_z() && moved.get_z() + 0.1 <= max.get_z());
+        }
+    }
+}
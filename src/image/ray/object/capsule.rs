@@ -0,0 +1,179 @@
+use super::super::bvh::Aabb;
+use super::super::vec3::{Location, Vector};
+use super::super::Ray;
+use super::polynomial::solve_quadratic;
+use super::{azimuth, closest, intervals, Frame, Hit, Hittable, Interval, Solid};
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+// Cylinder whose axis goes from `start` to `end`, capped by half spheres
+#[derive(Deserialize)]
+#[serde(try_from = "CapsuleData")]
+pub struct Capsule {
+    start: Location,
+    end: Location,
+    radius: f64,
+}
+
+#[derive(Deserialize)]
+struct CapsuleData {
+    start: Location,
+    end: Location,
+    radius: f64,
+}
+
+impl std::convert::TryFrom<CapsuleData> for Capsule {
+    type Error = String;
+
+    fn try_from(data: CapsuleData) -> Result<Self, Self::Error> {
+        Capsule::new(data.start, data.end, data.radius)
+    }
+}
+
+impl Capsule {
+    pub fn new(start: Location, end: Location, radius: f64) -> Result<Self, String> {
+        if (end - start).length_squared() == 0.0 {
+            return Err("the start and end of a capsule must be different".to_string());
+        }
+        if radius <= 0.0 {
+            return Err("the radius of a capsule must be positive".to_string());
+        }
+        Ok(Capsule { start, end, radius })
+    }
+
+    // Position along the outline of the capsule, from the tip of `start` (0)
+    // to the tip of `end` (1)
+    fn outline(&self, height: f64, p: Vector) -> f64 {
+        let r = self.radius;
+        let s = if p.get_z() < 0.0 {
+            r * (-p.get_z() / r).min(1.0).acos()
+        } else if p.get_z() > height {
+            0.5 * PI * r + height + r * ((p.get_z() - height) / r).min(1.0).asin()
+        } else {
+            0.5 * PI * r + p.get_z()
+        };
+        s / (PI * r + height)
+    }
+
+    // All the crossings of the ray's line with the surface
+    fn hits(&self, ray: &Ray) -> Vec<Hit> {
+        let height = (self.end - self.start).length();
+        let frame = Frame::new(self.start, (self.end - self.start).as_unit_vector());
+        let o = frame.to_local(ray.location);
+        let d = frame.to_local_direction(ray.direction);
+        let r2 = self.radius * self.radius;
+        let mut hits = Vec::with_capacity(6);
+        let a = d.get_x() * d.get_x() + d.get_y() * d.get_y();
+        if a > 1e-12 {
+            let b = 2.0 * (o.get_x() * d.get_x() + o.get_y() * d.get_y());
+            let c = o.get_x() * o.get_x() + o.get_y() * o.get_y() - r2;
+            for t in solve_quadratic(a, b, c) {
+                let p = o + t * d;
+                if (0.0..=height).contains(&p.get_z()) {
+                    hits.push(
+                        Hit::new(
+                            t,
+                            frame.to_world_direction(Vector::new(p.get_x(), p.get_y(), 0.0)),
+                            azimuth(p),
+                            self.outline(height, p),
+                        )
+                        .with_tangent(frame.tangent(p)),
+                    );
+                }
+            }
+        }
+        for z in [0.0, height].iter() {
+            let center = Vector::new(0.0, 0.0, *z);
+            let oc = o - center;
+            for t in solve_quadratic(1.0, 2.0 * (oc * d), oc.length_squared() - r2) {
+                let p = o + t * d;
+                let outside = if *z == 0.0 {
+                    p.get_z() <= 0.0
+                } else {
+                    p.get_z() >= height
+                };
+                if outside {
+                    hits.push(
+                        Hit::new(
+                            t,
+                            frame.to_world_direction(p - center),
+                            azimuth(p),
+                            self.outline(height, p),
+                        )
+                        .with_tangent(frame.tangent(p)),
+                    );
+                }
+            }
+        }
+        hits
+    }
+}
+
+impl Hittable for Capsule {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        closest(ray, self.hits(ray), t_min, t_max)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let radius = Vector::new(self.radius, self.radius, self.radius);
+        Some(
+            Aabb::new(self.start - radius, self.start + radius)
+                .union(&Aabb::new(self.end - radius, self.end + radius)),
+        )
+    }
+}
+
+impl Solid for Capsule {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        intervals(self.hits(ray), ray.direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{assert_hit, hit};
+    use super::*;
+
+    fn capsule() -> Capsule {
+        Capsule::new(
+            Location::new(0.0, 0.0, 0.0),
+            Location::new(0.0, 2.0, 0.0),
+            1.0,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn hits_the_side_and_the_ends() {
+        assert_hit(
+            hit(&capsule(), (0.0, 1.0, 5.0), (0.0, 0.0, -1.0)),
+            4.0,
+            (0.0, 0.0, 1.0),
+        );
+        assert_hit(
+            hit(&capsule(), (0.0, 5.0, 0.0), (0.0, -1.0, 0.0)),
+            2.0,
+            (0.0, 1.0, 0.0),
+        );
+        assert_hit(
+            hit(&capsule(), (0.0, -5.0, 0.0), (0.0, 1.0, 0.0)),
+            4.0,
+            (0.0, -1.0, 0.0),
+        );
+        // past the end of the side, on the round end
+        let y = 2.0 + 0.5f64.sqrt();
+        assert_hit(
+            hit(&capsule(), (0.0, y, 5.0), (0.0, 0.0, -1.0)),
+            5.0 - 0.5f64.sqrt(),
+            (0.0, 1.0, 1.0),
+        );
+        assert!(hit(&capsule(), (0.0, 3.5, 5.0), (0.0, 0.0, -1.0)).is_none());
+    }
+
+    #[test]
+    fn rejects_degenerate_capsules() {
+        let start = Location::new(0.0, 0.0, 0.0);
+        assert!(Capsule::new(start, start, 1.0).is_err());
+        assert!(Capsule::new(start, Location::new(0.0, 1.0, 0.0), 0.0).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use super::super::bvh::Aabb;
+use super::super::vec3::{Location, Vector};
+use super::super::Ray;
+use super::polynomial::solve_quadratic;
+use super::{azimuth, closest, disk_bounds, intervals, Frame, Hit, Hittable, Interval, Solid};
+use serde::Deserialize;
+
+// Cone capped at its base, of the given radius at the base
+#[derive(Deserialize)]
+#[serde(try_from = "ConeData")]
+pub struct Cone {
+    base: Location,
+    apex: Location,
+    radius: f64,
+}
+
+#[derive(Deserialize)]
+struct ConeData {
+    base: Location,
+    apex: Location,
+    radius: f64,
+}
+
+impl std::convert::TryFrom<ConeData> for Cone {
+    type Error = String;
+
+    fn try_from(data: ConeData) -> Result<Self, Self::Error> {
+        Cone::new(data.base, data.apex, data.radius)
+    }
+}
+
+impl Cone {
+    pub fn new(base: Location, apex: Location, radius: f64) -> Result<Self, String> {
+        if (apex - base).length_squared() == 0.0 {
+            return Err("the base and apex of a cone must be different".to_string());
+        }
+        if radius <= 0.0 {
+            return Err("the radius of a cone must be positive".to_string());
+        }
+        Ok(Cone { base, apex, radius })
+    }
+
+    // All the crossings of the ray's line with the surface
+    fn hits(&self, ray: &Ray) -> Vec<Hit> {
+        let height = (self.apex - self.base).length();
+        let frame = Frame::new(self.base, (self.apex - self.base).as_unit_vector());
+        let o = frame.to_local(ray.location);
+        let d = frame.to_local_direction(ray.direction);
+        // x^2 + y^2 = (k (height - z))^2
+        let k = self.radius / height;
+        let k2 = k * k;
+        let h = height - o.get_z();
+        let mut hits = Vec::with_capacity(3);
+        let a = d.get_x() * d.get_x() + d.get_y() * d.get_y() - k2 * d.get_z() * d.get_z();
+        let b = 2.0 * (o.get_x() * d.get_x() + o.get_y() * d.get_y() + k2 * h * d.get_z());
+        let c = o.get_x() * o.get_x() + o.get_y() * o.get_y() - k2 * h * h;
+        let roots = if a.abs() > 1e-12 {
+            solve_quadratic(a, b, c)
+        } else if b.abs() > 1e-12 {
+            vec![-c / b]
+        } else {
+            Vec::new()
+        };
+        for t in roots {
+            let p = o + t * d;
+            if (0.0..=height).contains(&p.get_z()) {
+                let r = (p.get_x() * p.get_x() + p.get_y() * p.get_y()).sqrt();
+                hits.push(
+                    Hit::new(
+                        t,
+                        frame.to_world_direction(Vector::new(p.get_x(), p.get_y(), k * r)),
+                        azimuth(p),
+                        p.get_z() / height,
+                    )
+                    .with_tangent(frame.tangent(p)),
+                );
+            }
+        }
+        if d.get_z().abs() > 1e-12 {
+            let t = -o.get_z() / d.get_z();
+            let p = o + t * d;
+            if p.get_x() * p.get_x() + p.get_y() * p.get_y() <= self.radius * self.radius {
+                hits.push(
+                    Hit::new(
+                        t,
+                        frame.to_world_direction(Vector::new(0.0, 0.0, -1.0)),
+                        0.5 + p.get_x() / (2.0 * self.radius),
+                        0.5 + p.get_y() / (2.0 * self.radius),
+                    )
+                    .with_tangent(frame.x.as_vector()),
+                );
+            }
+        }
+        hits
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        closest(ray, self.hits(ray), t_min, t_max)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let axis = (self.apex - self.base).as_unit_vector();
+        Some(disk_bounds(self.base, axis, self.radius).union(&Aabb::new(self.apex, self.apex)))
+    }
+}
+
+impl Solid for Cone {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        intervals(self.hits(ray), ray.direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{assert_hit, hit};
+    use super::*;
+
+    fn cone() -> Cone {
+        Cone::new(
+            Location::new(0.0, 0.0, 0.0),
+            Location::new(0.0, 1.0, 0.0),
+            1.0,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn hits_the_side_and_the_base() {
+        // half way up, where the radius is 0.5
+        assert_hit(
+            hit(&cone(), (0.0, 0.5, 5.0), (0.0, 0.0, -1.0)),
+            4.5,
+            (0.0, 1.0, 1.0),
+        );
+        assert_hit(
+            hit(&cone(), (0.0, -5.0, 0.0), (0.0, 1.0, 0.0)),
+            5.0,
+            (0.0, -1.0, 0.0),
+        );
+        assert!(hit(&cone(), (0.0, 1.5, 5.0), (0.0, 0.0, -1.0)).is_none());
+        // the other nappe of the double cone is not part of it
+        assert!(hit(&cone(), (0.0, 1.5, -5.0), (0.0, 0.0, 1.0)).is_none());
+    }
+
+    #[test]
+    fn hits_rays_parallel_to_the_side() {
+        // the quadratic is then linear, crossing the side once
+        assert_hit(
+            hit(&cone(), (0.0, 2.0, -1.5), (0.0, -1.0, 1.0)),
+            1.25 * 2f64.sqrt(),
+            (0.0, 1.0, -1.0),
+        );
+    }
+
+    #[test]
+    fn rejects_degenerate_cones() {
+        let base = Location::new(0.0, 0.0, 0.0);
+        assert!(Cone::new(base, base, 1.0).is_err());
+        assert!(Cone::new(base, Location::new(0.0, 1.0, 0.0), -1.0).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use super::super::bvh::Aabb;
+use super::super::Ray;
+use super::capsule::Capsule;
+use super::cone::Cone;
+use super::cuboid::Cuboid;
+use super::cylinder::Cylinder;
+use super::sphere::Sphere;
+use super::torus::Torus;
+use super::{Hit, Hittable, Interval, Solid};
+use serde::Deserialize;
+
+#[derive(Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+// Closed objects that can be combined
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SolidShape {
+    Sphere(Sphere),
+    #[serde(rename = "box")]
+    Cuboid(Cuboid),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Capsule(Capsule),
+    Torus(Torus),
+    Csg(Csg),
+}
+
+#[derive(Deserialize)]
+pub struct Csg {
+    operation: Operation,
+    left: Box<SolidShape>,
+    right: Box<SolidShape>,
+}
+
+impl Csg {
+    pub fn new(operation: Operation, left: SolidShape, right: SolidShape) -> Self {
+        Csg {
+            operation,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+}
+
+impl Operation {
+    fn contains(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            Operation::Union => in_left || in_right,
+            Operation::Intersection => in_left && in_right,
+            Operation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+impl Solid for SolidShape {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        match self {
+            SolidShape::Sphere(sphere) => sphere.intervals(ray),
+            SolidShape::Cuboid(cuboid) => cuboid.intervals(ray),
+            SolidShape::Cylinder(cylinder) => cylinder.intervals(ray),
+            SolidShape::Cone(cone) => cone.intervals(ray),
+            SolidShape::Capsule(capsule) => capsule.intervals(ray),
+            SolidShape::Torus(torus) => torus.intervals(ray),
+            SolidShape::Csg(csg) => csg.intervals(ray),
+        }
+    }
+}
+
+impl SolidShape {
+    fn bounds(&self) -> Option<Aabb> {
+        match self {
+            SolidShape::Sphere(sphere) => sphere.bounds(),
+            SolidShape::Cuboid(cuboid) => cuboid.bounds(),
+            SolidShape::Cylinder(cylinder) => cylinder.bounds(),
+            SolidShape::Cone(cone) => cone.bounds(),
+            SolidShape::Capsule(capsule) => capsule.bounds(),
+            SolidShape::Torus(torus) => torus.bounds(),
+            SolidShape::Csg(csg) => csg.bounds(),
+        }
+    }
+}
+
+impl Solid for Csg {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        // walk through the boundaries of both sides in order, keeping track of
+        // whether the ray is inside each of them
+        let mut boundaries: Vec<(Hit, bool, bool)> = Vec::new();
+        for (intervals, right) in [
+            (self.left.intervals(ray), false),
+            (self.right.intervals(ray), true),
+        ]
+        .iter()
+        {
+            for interval in intervals {
+                boundaries.push((interval.enter, *right, true));
+                boundaries.push((interval.exit, *right, false));
+            }
+        }
+        boundaries.sort_by(|(a, _, _), (b, _, _)| {
+            a.t.partial_cmp(&b.t).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let mut res = Vec::new();
+        let mut in_left = false;
+        let mut in_right = false;
+        let mut enter = None;
+        for (hit, right, entering) in boundaries {
+            let was_inside = self.operation.contains(in_left, in_right);
+            if right {
+                in_right = entering;
+            } else {
+                in_left = entering;
+            }
+            let inside = self.operation.contains(in_left, in_right);
+            // the inside of the right side is the outside of a difference
+            let hit = if right && self.operation == Operation::Difference {
+                Hit {
+                    normal: -hit.normal,
+                    ..hit
+                }
+            } else {
+                hit
+            };
+            if inside && !was_inside {
+                enter = Some(hit);
+            } else if was_inside && !inside {
+                if let Some(enter) = enter.take() {
+                    res.push(Interval { enter, exit: hit });
+                }
+            }
+        }
+        res
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        self.intervals(ray)
+            .into_iter()
+            .flat_map(|interval| vec![interval.enter, interval.exit])
+            .find(|hit| (t_min..=t_max).contains(&hit.t))
+            .map(|hit| hit.facing(ray))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        match self.operation {
+            Operation::Union => Some(self.left.bounds()?.union(&self.right.bounds()?)),
+            Operation::Intersection | Operation::Difference => self.left.bounds(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::ray::vec3::{Location, UnitDirection, Vector};
+
+    fn big_box() -> SolidShape {
+        SolidShape::Cuboid(
+            Cuboid::new(
+                Location::new(0.0, 0.0, 0.0),
+                Vector::new(8.0, 8.0, 8.0),
+                UnitDirection::new(1.0, 0.0, 0.0),
+                UnitDirection::new(0.0, 1.0, 0.0),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn torus() -> SolidShape {
+        SolidShape::Torus(Torus::new(
+            Location::new(0.0, 0.0, 0.0),
+            UnitDirection::new(0.0, 1.0, 0.0),
+            2.0,
+            0.5,
+        ))
+    }
+
+    // Along x from x = -5
+    fn intervals(solid: &SolidShape, y: f64, z: f64) -> Vec<(f64, f64)> {
+        let ray = Ray::new(Location::new(-5.0, y, z), UnitDirection::new(1.0, 0.0, 0.0));
+        solid
+            .intervals(&ray)
+            .iter()
+            .map(|interval| (interval.enter.t, interval.exit.t))
+            .collect()
+    }
+
+    fn assert_intervals(intervals: Vec<(f64, f64)>, expected: &[(f64, f64)]) {
+        assert_eq!(intervals.len(), expected.len(), "{:?}", intervals);
+        for ((enter, exit), (expected_enter, expected_exit)) in intervals.iter().zip(expected) {
+            assert!((enter - expected_enter).abs() < 1e-6, "{:?}", intervals);
+            assert!((exit - expected_exit).abs() < 1e-6, "{:?}", intervals);
+        }
+    }
+
+    #[test]
+    fn ignores_rays_tangent_to_a_sphere() {
+        let sphere = SolidShape::Sphere(Sphere::new(Location::new(0.0, 0.0, 0.0), 1.0));
+        assert_intervals(intervals(&sphere, 1.0, 0.0), &[]);
+        let difference = Csg::new(Operation::Difference, big_box(), sphere);
+        assert_intervals(
+            intervals(&SolidShape::Csg(difference), 1.0, 0.0),
+            &[(1.0, 9.0)],
+        );
+    }
+
+    #[test]
+    fn ignores_rays_tangent_to_the_outside_of_a_torus() {
+        assert_intervals(intervals(&torus(), 0.0, 2.5), &[]);
+        assert_intervals(intervals(&torus(), 0.5, 2.0), &[]);
+        let difference = Csg::new(Operation::Difference, big_box(), torus());
+        assert_intervals(
+            intervals(&SolidShape::Csg(difference), 0.0, 2.5),
+            &[(1.0, 9.0)],
+        );
+    }
+
+    #[test]
+    fn keeps_rays_tangent_to_the_inside_of_a_torus() {
+        // touching the inner side of the tube between crossing it at x = -2
+        // and x = 2
+        assert_intervals(intervals(&torus(), 0.0, 1.5), &[(3.0, 7.0)]);
+        let intersection = Csg::new(Operation::Intersection, big_box(), torus());
+        assert_intervals(
+            intervals(&SolidShape::Csg(intersection), 0.0, 1.5),
+            &[(3.0, 7.0)],
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use super::super::bvh::Aabb;
+use super::super::vec3::{Location, UnitDirection, Vector};
+use super::super::Ray;
+use super::{closest, Hit, Hittable, Interval, Solid};
+use serde::Deserialize;
+
+// Box of dimensions `size` centered on `center`, oriented along `x_axis` and
+// `y_axis` (axis-aligned by default)
+#[derive(Deserialize)]
+#[serde(try_from = "CuboidData")]
+pub struct Cuboid {
+    center: Location,
+    size: Vector,
+    x_axis: UnitDirection,
+    y_axis: UnitDirection,
+}
+
+#[derive(Deserialize)]
+struct CuboidData {
+    center: Location,
+    size: Vector,
+    #[serde(default = "default_x_axis")]
+    x_axis: UnitDirection,
+    #[serde(default = "default_y_axis")]
+    y_axis: UnitDirection,
+}
+
+fn default_x_axis() -> UnitDirection {
+    UnitDirection::new(1.0, 0.0, 0.0)
+}
+
+fn default_y_axis() -> UnitDirection {
+    UnitDirection::new(0.0, 1.0, 0.0)
+}
+
+impl std::convert::TryFrom<CuboidData> for Cuboid {
+    type Error = String;
+
+    fn try_from(data: CuboidData) -> Result<Self, Self::Error> {
+        Cuboid::new(data.center, data.size, data.x_axis, data.y_axis)
+    }
+}
+
+impl Cuboid {
+    pub fn new(
+        center: Location,
+        size: Vector,
+        x_axis: UnitDirection,
+        y_axis: UnitDirection,
+    ) -> Result<Self, String> {
+        if !(size.get_x() > 0.0 && size.get_y() > 0.0 && size.get_z() > 0.0) {
+            return Err("the size of a cuboid must be positive".to_string());
+        }
+        // the third axis is undefined otherwise
+        let cross = (x_axis ^ y_axis).length_squared();
+        if cross.is_nan() || cross <= 1e-12 {
+            return Err("the axes of a cuboid must not be parallel".to_string());
+        }
+        Ok(Cuboid {
+            center,
+            size,
+            x_axis,
+            y_axis,
+        })
+    }
+
+    fn axes(&self) -> [UnitDirection; 3] {
+        let z = (self.x_axis ^ self.y_axis).as_unit_vector();
+        let y = (z ^ self.x_axis).as_unit_vector();
+        [self.x_axis, y, z]
+    }
+
+    fn half_size(&self) -> [f64; 3] {
+        [
+            0.5 * self.size.get_x(),
+            0.5 * self.size.get_y(),
+            0.5 * self.size.get_z(),
+        ]
+    }
+}
+
+impl Cuboid {
+    fn hit_at(&self, ray: &Ray, t: f64, face: usize, normal: UnitDirection) -> Hit {
+        let axes = self.axes();
+        let half_size = self.half_size();
+        let p = ray.at(t) - self.center;
+        let coordinate = |i: usize| 0.5 + p * axes[i] / (2.0 * half_size[i]);
+        Hit::new(
+            t,
+            normal,
+            coordinate((face + 1) % 3),
+            coordinate((face + 2) % 3),
+        )
+        .with_tangent(axes[(face + 1) % 3].as_vector())
+    }
+
+    // Part of the ray's line inside the box
+    fn interval(&self, ray: &Ray) -> Option<Interval> {
+        let axes = self.axes();
+        let half_size = self.half_size();
+        let oc = ray.location - self.center;
+        let mut near = (f64::NEG_INFINITY, 0, axes[0]);
+        let mut far = (f64::INFINITY, 0, axes[0]);
+        for (i, (axis, half)) in axes.iter().zip(half_size.iter()).enumerate() {
+            let origin = oc * *axis;
+            let direction = ray.direction * *axis;
+            if direction.abs() < 1e-12 {
+                if origin.abs() > *half {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (-half - origin) / direction;
+            let t1 = (half - origin) / direction;
+            let (t0, t1, normal) = if t0 < t1 {
+                (t0, t1, -*axis)
+            } else {
+                (t1, t0, *axis)
+            };
+            if t0 > near.0 {
+                near = (t0, i, normal);
+            }
+            if t1 < far.0 {
+                far = (t1, i, -normal);
+            }
+            if near.0 > far.0 {
+                return None;
+            }
+        }
+        Some(Interval {
+            enter: self.hit_at(ray, near.0, near.1, near.2),
+            exit: self.hit_at(ray, far.0, far.1, far.2),
+        })
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let interval = self.interval(ray)?;
+        closest(ray, vec![interval.enter, interval.exit], t_min, t_max)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let axes = self.axes();
+        let half_size = self.half_size();
+        let extent = |component: fn(&UnitDirection) -> f64| {
+            axes.iter()
+                .zip(half_size.iter())
+                .map(|(axis, half)| component(axis).abs() * half)
+                .sum()
+        };
+        let half = Vector::new(
+            extent(UnitDirection::get_x),
+            extent(UnitDirection::get_y),
+            extent(UnitDirection::get_z),
+        );
+        Some(Aabb::new(self.center - half, self.center + half))
+    }
+}
+
+impl Solid for Cuboid {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        self.interval(ray).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_degenerate_boxes() {
+        let center = Location::new(0.0, 0.0, 0.0);
+        let x = UnitDirection::new(1.0, 0.0, 0.0);
+        let y = UnitDirection::new(0.0, 1.0, 0.0);
+        assert!(Cuboid::new(center, Vector::new(1.0, 0.0, 1.0), x, y).is_err());
+        assert!(Cuboid::new(center, Vector::new(1.0, -1.0, 1.0), x, y).is_err());
+        assert!(Cuboid::new(center, Vector::new(1.0, 1.0, 1.0), x, -x).is_err());
+        assert!(Cuboid::new(center, Vector::new(1.0, 1.0, 1.0), x, y).is_ok());
+        assert!(serde_json::from_str::<Cuboid>(
+            r#"{"center": {"x": 0, "y": 0, "z": 0},
+                "size": {"x": 1, "y": 1, "z": 1},
+                "x_axis": {"x": 0, "y": 1, "z": 0}}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use super::super::bvh::Aabb;
+use super::super::vec3::{Location, Vector};
+use super::super::Ray;
+use super::polynomial::solve_quadratic;
+use super::{azimuth, closest, disk_bounds, intervals, Frame, Hit, Hittable, Interval, Solid};
+use serde::Deserialize;
+
+// Capped cylinder whose axis goes from `start` to `end`
+#[derive(Deserialize)]
+#[serde(try_from = "CylinderData")]
+pub struct Cylinder {
+    start: Location,
+    end: Location,
+    radius: f64,
+}
+
+#[derive(Deserialize)]
+struct CylinderData {
+    start: Location,
+    end: Location,
+    radius: f64,
+}
+
+impl std::convert::TryFrom<CylinderData> for Cylinder {
+    type Error = String;
+
+    fn try_from(data: CylinderData) -> Result<Self, Self::Error> {
+        Cylinder::new(data.start, data.end, data.radius)
+    }
+}
+
+impl Cylinder {
+    pub fn new(start: Location, end: Location, radius: f64) -> Result<Self, String> {
+        if (end - start).length_squared() == 0.0 {
+            return Err("the start and end of a cylinder must be different".to_string());
+        }
+        if radius <= 0.0 {
+            return Err("the radius of a cylinder must be positive".to_string());
+        }
+        Ok(Cylinder { start, end, radius })
+    }
+
+    // All the crossings of the ray's line with the surface
+    fn hits(&self, ray: &Ray) -> Vec<Hit> {
+        let height = (self.end - self.start).length();
+        let frame = Frame::new(self.start, (self.end - self.start).as_unit_vector());
+        let o = frame.to_local(ray.location);
+        let d = frame.to_local_direction(ray.direction);
+        let mut hits = Vec::with_capacity(4);
+        let a = d.get_x() * d.get_x() + d.get_y() * d.get_y();
+        if a > 1e-12 {
+            let b = 2.0 * (o.get_x() * d.get_x() + o.get_y() * d.get_y());
+            let c = o.get_x() * o.get_x() + o.get_y() * o.get_y() - self.radius * self.radius;
+            for t in solve_quadratic(a, b, c) {
+                let p = o + t * d;
+                if (0.0..=height).contains(&p.get_z()) {
+                    hits.push(
+                        Hit::new(
+                            t,
+                            frame.to_world_direction(Vector::new(p.get_x(), p.get_y(), 0.0)),
+                            azimuth(p),
+                            p.get_z() / height,
+                        )
+                        .with_tangent(frame.tangent(p)),
+                    );
+                }
+            }
+        }
+        if d.get_z().abs() > 1e-12 {
+            for (z, side) in [(0.0, -1.0), (height, 1.0)].iter() {
+                let t = (z - o.get_z()) / d.get_z();
+                let p = o + t * d;
+                if p.get_x() * p.get_x() + p.get_y() * p.get_y() <= self.radius * self.radius {
+                    hits.push(
+                        Hit::new(
+                            t,
+                            frame.to_world_direction(Vector::new(0.0, 0.0, *side)),
+                            0.5 + p.get_x() / (2.0 * self.radius),
+                            0.5 + p.get_y() / (2.0 * self.radius),
+                        )
+                        .with_tangent(frame.x.as_vector()),
+                    );
+                }
+            }
+        }
+        hits
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        closest(ray, self.hits(ray), t_min, t_max)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let axis = (self.end - self.start).as_unit_vector();
+        Some(
+            disk_bounds(self.start, axis, self.radius).union(&disk_bounds(
+                self.end,
+                axis,
+                self.radius,
+            )),
+        )
+    }
+}
+
+impl Solid for Cylinder {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        intervals(self.hits(ray), ray.direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{assert_hit, hit};
+    use super::*;
+
+    fn cylinder() -> Cylinder {
+        Cylinder::new(
+            Location::new(0.0, 0.0, 0.0),
+            Location::new(0.0, 2.0, 0.0),
+            1.0,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn hits_the_side_and_the_caps() {
+        assert_hit(
+            hit(&cylinder(), (0.0, 1.0, 5.0), (0.0, 0.0, -1.0)),
+            4.0,
+            (0.0, 0.0, 1.0),
+        );
+        assert_hit(
+            hit(&cylinder(), (0.0, 5.0, 0.5), (0.0, -1.0, 0.0)),
+            3.0,
+            (0.0, 1.0, 0.0),
+        );
+        assert_hit(
+            hit(&cylinder(), (0.5, -5.0, 0.0), (0.0, 1.0, 0.0)),
+            5.0,
+            (0.0, -1.0, 0.0),
+        );
+        assert!(hit(&cylinder(), (0.0, 3.0, 5.0), (0.0, 0.0, -1.0)).is_none());
+        assert!(hit(&cylinder(), (2.0, 1.0, 5.0), (0.0, 0.0, -1.0)).is_none());
+    }
+
+    #[test]
+    fn faces_rays_from_the_inside() {
+        let hit = hit(&cylinder(), (0.0, 1.0, 0.0), (1.0, 0.0, 0.0));
+        assert!(!hit.unwrap().front_face);
+        assert_hit(hit, 1.0, (-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn rejects_degenerate_cylinders() {
+        let start = Location::new(0.0, 0.0, 0.0);
+        assert!(Cylinder::new(start, start, 1.0).is_err());
+        assert!(Cylinder::new(start, Location::new(0.0, 1.0, 0.0), 0.0).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use super::super::bvh::Aabb;
+use super::super::vec3::{Location, UnitDirection};
+use super::super::Ray;
+use super::{disk_bounds, hit_plane, Frame, Hit, Hittable};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(try_from = "DiskData")]
+pub struct Disk {
+    center: Location,
+    normal: UnitDirection,
+    radius: f64,
+}
+
+#[derive(Deserialize)]
+struct DiskData {
+    center: Location,
+    normal: UnitDirection,
+    radius: f64,
+}
+
+impl std::convert::TryFrom<DiskData> for Disk {
+    type Error = String;
+
+    fn try_from(data: DiskData) -> Result<Self, Self::Error> {
+        Disk::new(data.center, data.normal, data.radius)
+    }
+}
+
+impl Disk {
+    pub fn new(center: Location, normal: UnitDirection, radius: f64) -> Result<Self, String> {
+        if radius <= 0.0 {
+            return Err("the radius of a disk must be positive".to_string());
+        }
+        Ok(Disk {
+            center,
+            normal,
+            radius,
+        })
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let hit = hit_plane(ray, self.center, self.normal, t_min, t_max)?;
+        let frame = Frame::new(self.center, self.normal);
+        let local = frame.to_local(ray.at(hit.t));
+        if local.length_squared() <= self.radius * self.radius {
+            Some(
+                Hit {
+                    u: 0.5 + local.get_x() / (2.0 * self.radius),
+                    v: 0.5 + local.get_y() / (2.0 * self.radius),
+                    ..hit
+                }
+                .with_tangent(frame.x.as_vector()),
+            )
+        } else {
+            None
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(disk_bounds(self.center, self.normal, self.radius))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_radii_that_are_not_positive() {
+        let center = Location::new(0.0, 0.0, 0.0);
+        let normal = UnitDirection::new(0.0, 1.0, 0.0);
+        assert!(Disk::new(center, normal, 0.0).is_err());
+        assert!(Disk::new(center, normal, -1.0).is_err());
+        assert!(serde_json::from_str::<Disk>(
+            r#"{"center": {"x": 0, "y": 0, "z": 0},
+                "normal": {"x": 0, "y": 1, "z": 0},
+                "radius": 0}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,358 @@
+use super::super::bvh::Aabb;
+use super::super::color::Color;
+use super::super::vec3::{Location, Vector};
+use super::super::Ray;
+use super::{Hit, Hittable};
+use png::{BitDepth, ColorType, Decoder, Transformations};
+use serde::Deserialize;
+use std::fs::File;
+
+// Terrain whose heights are the brightness of a png image, seen from above
+// with its top towards -z, filling a box of dimensions `size` whose bottom is
+// centered on `center`, white reaching its top. Cells between four pixels are
+// split in two triangles, found by going down a tree of the smallest and
+// greatest heights of blocks of cells.
+#[derive(Deserialize)]
+#[serde(try_from = "HeightfieldData")]
+pub struct Heightfield {
+    // corner of the box
+    min: Location,
+    size: Vector,
+    // pixels along x and z
+    width: usize,
+    depth: usize,
+    // in [0, 1], row by row
+    heights: Vec<f64>,
+    // the first level has a block per cell, each of the next ones a block per
+    // two by two blocks of the previous one, up to a single block
+    levels: Vec<Level>,
+}
+
+#[derive(Deserialize)]
+struct HeightfieldData {
+    file: String,
+    center: Location,
+    size: Vector,
+}
+
+struct Level {
+    width: usize,
+    depth: usize,
+    // of each block, row by row
+    min: Vec<f64>,
+    max: Vec<f64>,
+}
+
+impl std::convert::TryFrom<HeightfieldData> for Heightfield {
+    type Error = String;
+
+    fn try_from(data: HeightfieldData) -> Result<Self, Self::Error> {
+        let (width, depth, heights) = read_heights(&data.file)?;
+        Heightfield::new(width, depth, heights, data.center, data.size)
+    }
+}
+
+// Brightness of the pixels of a png file, in [0, 1], keeping 16 bits per
+// channel
+fn read_heights(file_name: &str) -> Result<(usize, usize, Vec<f64>), String> {
+    let file = File::open(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+    let mut decoder = Decoder::new(file);
+    decoder.set_transformations(Transformations::EXPAND);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| format!("{}: {}", file_name, e))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|e| format!("{}: {}", file_name, e))?;
+    let channels = match info.color_type {
+        ColorType::Grayscale => 1,
+        ColorType::GrayscaleAlpha => 2,
+        ColorType::Rgb => 3,
+        ColorType::Rgba => 4,
+        ColorType::Indexed => return Err(format!("{}: unsupported palette", file_name)),
+    };
+    let bytes = if info.bit_depth == BitDepth::Sixteen {
+        2
+    } else {
+        1
+    };
+    let heights = buffer[..info.buffer_size()]
+        .chunks(channels * bytes)
+        .map(|pixel| {
+            let value = |i: usize| {
+                if bytes == 2 {
+                    u16::from_be_bytes([pixel[2 * i], pixel[2 * i + 1]]) as f64 / 65535.0
+                } else {
+                    pixel[i] as f64 / 255.0
+                }
+            };
+            if channels < 3 {
+                value(0)
+            } else {
+                Color::new(value(0), value(1), value(2)).luminance()
+            }
+        })
+        .collect();
+    Ok((info.width as usize, info.height as usize, heights))
+}
+
+impl Heightfield {
+    // `heights` in [0, 1], row by row from the top of the image
+    pub fn new(
+        width: usize,
+        depth: usize,
+        heights: Vec<f64>,
+        center: Location,
+        size: Vector,
+    ) -> Result<Self, String> {
+        if width < 2 || depth < 2 || heights.len() != width * depth {
+            return Err(format!(
+                "{} heights for a heightfield of {}x{}",
+                heights.len(),
+                width,
+                depth
+            ));
+        }
+        if size.get_x() <= 0.0 || size.get_y() <= 0.0 || size.get_z() <= 0.0 {
+            return Err("the size of a heightfield must be positive".to_string());
+        }
+        let mut cells = Level {
+            width: width - 1,
+            depth: depth - 1,
+            min: Vec::with_capacity((width - 1) * (depth - 1)),
+            max: Vec::with_capacity((width - 1) * (depth - 1)),
+        };
+        for j in 0..depth - 1 {
+            for i in 0..width - 1 {
+                let corners = [
+                    heights[j * width + i],
+                    heights[j * width + i + 1],
+                    heights[(j + 1) * width + i],
+                    heights[(j + 1) * width + i + 1],
+                ];
+                cells
+                    .min
+                    .push(corners.iter().copied().fold(f64::INFINITY, f64::min));
+                cells.max.push(corners.iter().copied().fold(0.0, f64::max));
+            }
+        }
+        let mut levels = vec![cells];
+        while let Some(level) = levels.last().filter(|level| level.width * level.depth > 1) {
+            let mut next = Level {
+                width: level.width.div_ceil(2),
+                depth: level.depth.div_ceil(2),
+                min: Vec::new(),
+                max: Vec::new(),
+            };
+            for j in 0..next.depth {
+                for i in 0..next.width {
+                    let blocks = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                        .iter()
+                        .map(|(di, dj)| (2 * i + di, 2 * j + dj))
+                        .filter(|(i, j)| *i < level.width && *j < level.depth)
+                        .map(|(i, j)| j * level.width + i);
+                    let (min, max) = blocks.fold((f64::INFINITY, 0.0), |(min, max), k| {
+                        (level.min[k].min(min), level.max[k].max(max))
+                    });
+                    next.min.push(min);
+                    next.max.push(max);
+                }
+            }
+            levels.push(next);
+        }
+        Ok(Heightfield {
+            min: center - 0.5 * Vector::new(size.get_x(), 0.0, size.get_z()),
+            size,
+            width,
+            depth,
+            heights,
+            levels,
+        })
+    }
+
+    pub fn load(file_name: &str, center: Location, size: Vector) -> Result<Self, String> {
+        let (width, depth, heights) = read_heights(file_name)?;
+        Heightfield::new(width, depth, heights, center, size)
+    }
+
+    fn height(&self, i: usize, j: usize) -> f64 {
+        self.heights[j * self.width + i]
+    }
+
+    // Normal in the scene of a normal in pixels and heights in [0, 1]
+    // (orthogonal to the same slopes)
+    fn to_world_normal(&self, normal: Vector) -> Vector {
+        Vector::new(
+            normal.get_x() * self.size.get_y() * (self.width - 1) as f64 / self.size.get_x(),
+            normal.get_y(),
+            normal.get_z() * self.size.get_y() * (self.depth - 1) as f64 / self.size.get_z(),
+        )
+    }
+
+    // Normal at a pixel, in pixels, from the heights around it
+    fn pixel_normal(&self, i: usize, j: usize) -> Vector {
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.width - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.depth - 1));
+        Vector::new(
+            -(self.height(i1, j) - self.height(i0, j)) / (i1 - i0) as f64,
+            1.0,
+            -(self.height(i, j1) - self.height(i, j0)) / (j1 - j0) as f64,
+        )
+    }
+
+    // Closest crossing in [t_min, t_max] of the local ray with the two
+    // triangles of a cell
+    fn hit_cell(
+        &self,
+        o: [f64; 3],
+        d: [f64; 3],
+        (i, j): (usize, usize),
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<Hit> {
+        let h00 = self.height(i, j);
+        let h10 = self.height(i + 1, j);
+        let h01 = self.height(i, j + 1);
+        let h11 = self.height(i + 1, j + 1);
+        let (x, z) = (o[0] - i as f64, o[2] - j as f64);
+        // planes h00 + a x + b z, below and above the diagonal
+        [(h10 - h00, h11 - h10, true), (h11 - h01, h01 - h00, false)]
+            .iter()
+            .filter_map(|&(a, b, below)| {
+                let denominator = d[1] - a * d[0] - b * d[2];
+                if denominator.abs() < 1e-12 {
+                    return None;
+                }
+                let t = (h00 + a * x + b * z - o[1]) / denominator;
+                let (fx, fz) = (x + t * d[0], z + t * d[2]);
+                let inside = (0.0..=1.0).contains(&fx)
+                    && (0.0..=1.0).contains(&fz)
+                    && if below { fz <= fx } else { fz >= fx };
+                if (t_min..=t_max).contains(&t) && inside {
+                    Some((t, fx, fz, Vector::new(-a, 1.0, -b)))
+                } else {
+                    None
+                }
+            })
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(t, fx, fz, geometric)| {
+                // normals of the pixels interpolated over the cell, unless
+                // the result is on the other side of the surface
+                let smooth = (1.0 - fz)
+                    * ((1.0 - fx) * self.pixel_normal(i, j) + fx * self.pixel_normal(i + 1, j))
+                    + fz * ((1.0 - fx) * self.pixel_normal(i, j + 1)
+                        + fx * self.pixel_normal(i + 1, j + 1));
+                let direction = Vector::new(d[0], d[1], d[2]);
+                let normal = if (smooth * direction) * (geometric * direction) > 0.0 {
+                    self.to_world_normal(smooth)
+                } else {
+                    self.to_world_normal(geometric)
+                };
+                let (u, v) = (i as f64 + fx, j as f64 + fz);
+                Hit::new(
+                    t,
+                    normal.as_unit_vector(),
+                    u / (self.width - 1) as f64,
+                    1.0 - v / (self.depth - 1) as f64,
+                )
+                .with_tangent(Vector::new(1.0, 0.0, 0.0))
+            })
+    }
+
+    // Closest hit in the block of a level, t_max being lowered to the hits
+    // found
+    fn hit_block(
+        &self,
+        o: [f64; 3],
+        d: [f64; 3],
+        level: usize,
+        (i, j): (usize, usize),
+        t_min: f64,
+        t_max: &mut f64,
+    ) -> Option<Hit> {
+        let blocks = &self.levels[level];
+        let k = j * blocks.width + i;
+        let cells = 1 << level;
+        let low = [(i * cells) as f64, blocks.min[k], (j * cells) as f64];
+        let high = [
+            ((i + 1) * cells).min(self.width - 1) as f64,
+            blocks.max[k],
+            ((j + 1) * cells).min(self.depth - 1) as f64,
+        ];
+        let (mut start, mut end) = (t_min, *t_max);
+        for axis in 0..3 {
+            if d[axis] == 0.0 {
+                if o[axis] < low[axis] || o[axis] > high[axis] {
+                    return None;
+                }
+            } else {
+                let t0 = (low[axis] - o[axis]) / d[axis];
+                let t1 = (high[axis] - o[axis]) / d[axis];
+                start = start.max(t0.min(t1));
+                end = end.min(t0.max(t1));
+            }
+        }
+        if start > end {
+            return None;
+        }
+        if level == 0 {
+            let hit = self.hit_cell(o, d, (i, j), t_min, *t_max)?;
+            *t_max = hit.t;
+            return Some(hit);
+        }
+        // the children on the side the ray comes from first
+        let children = &self.levels[level - 1];
+        let xs = if d[0] < 0.0 { [1, 0] } else { [0, 1] };
+        let zs = if d[2] < 0.0 { [1, 0] } else { [0, 1] };
+        let mut res = None;
+        for dj in zs {
+            for di in xs {
+                let child = (2 * i + di, 2 * j + dj);
+                if child.0 < children.width && child.1 < children.depth {
+                    if let Some(hit) = self.hit_block(o, d, level - 1, child, t_min, t_max) {
+                        res = Some(hit);
+                    }
+                }
+            }
+        }
+        res
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        // in pixels and heights in [0, 1], t staying the same
+        let scale = [
+            (self.width - 1) as f64 / self.size.get_x(),
+            1.0 / self.size.get_y(),
+            (self.depth - 1) as f64 / self.size.get_z(),
+        ];
+        let offset = ray.location - self.min;
+        let o = [
+            offset.get_x() * scale[0],
+            offset.get_y() * scale[1],
+            offset.get_z() * scale[2],
+        ];
+        let d = [
+            ray.direction.get_x() * scale[0],
+            ray.direction.get_y() * scale[1],
+            ray.direction.get_z() * scale[2],
+        ];
+        let mut t_max = t_max;
+        self.hit_block(o, d, self.levels.len() - 1, (0, 0), t_min, &mut t_max)
+            .map(|hit| hit.facing(ray))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let top = &self.levels[self.levels.len() - 1];
+        let (low, high) = (
+            self.size.get_y() * top.min[0],
+            self.size.get_y() * top.max[0],
+        );
+        Some(Aabb::new(
+            self.min + Vector::new(0.0, low, 0.0),
+            self.min + Vector::new(self.size.get_x(), high, self.size.get_z()),
+        ))
+    }
+}
@@ -0,0 +1,405 @@
+mod ply;
+mod stl;
+
+use super::super::bvh::Aabb;
+use super::super::color::Color;
+use super::super::vec3::{Location, Vector};
+use super::super::Ray;
+use super::{Hit, Hittable};
+use serde::Deserialize;
+
+// Triangles sharing their vertices, with a tree of boxes around them to find
+// the ones a ray may hit
+#[derive(Deserialize)]
+#[serde(try_from = "MeshData")]
+pub struct Mesh {
+    vertices: Vec<Location>,
+    // per vertex, if any
+    normals: Vec<Vector>,
+    colors: Vec<Color>,
+    uvs: Vec<(f64, f64)>,
+    // indices of the vertices of each triangle, counterclockwise seen from
+    // the outside
+    triangles: Vec<[u32; 3]>,
+    nodes: Vec<Node>,
+}
+
+#[derive(Deserialize)]
+struct MeshData {
+    file: String,
+    // normals interpolated between the vertices
+    #[serde(default)]
+    smooth: bool,
+}
+
+// Box around some triangles, either the ones from `start` to `start + count`
+// or the ones of its children, which are the next node and the one at `start`
+struct Node {
+    min: [f64; 3],
+    max: [f64; 3],
+    start: u32,
+    count: u32,
+    // along which the children are split
+    axis: u8,
+}
+
+// triangles per leaf of the tree
+const LEAF_SIZE: usize = 4;
+
+impl std::convert::TryFrom<MeshData> for Mesh {
+    type Error = String;
+
+    fn try_from(data: MeshData) -> Result<Self, Self::Error> {
+        let mesh = Mesh::load(&data.file)?;
+        Ok(if data.smooth { mesh.smoothed() } else { mesh })
+    }
+}
+
+fn to_array(vector: Vector) -> [f64; 3] {
+    [vector.get_x(), vector.get_y(), vector.get_z()]
+}
+
+// Builds the node of the triangles in `order`, whose bounds and centroids are
+// given, and its descendants after it
+fn build(
+    nodes: &mut Vec<Node>,
+    order: &mut [u32],
+    offset: usize,
+    bounds: &[([f64; 3], [f64; 3])],
+    centroids: &[[f64; 3]],
+) {
+    let (mut min, mut max) = ([f64::INFINITY; 3], [f64::NEG_INFINITY; 3]);
+    let (mut low, mut high) = ([f64::INFINITY; 3], [f64::NEG_INFINITY; 3]);
+    for &i in order.iter() {
+        let (a, b) = bounds[i as usize];
+        let c = centroids[i as usize];
+        for axis in 0..3 {
+            min[axis] = min[axis].min(a[axis]);
+            max[axis] = max[axis].max(b[axis]);
+            low[axis] = low[axis].min(c[axis]);
+            high[axis] = high[axis].max(c[axis]);
+        }
+    }
+    let index = nodes.len();
+    nodes.push(Node {
+        min,
+        max,
+        start: offset as u32,
+        count: order.len() as u32,
+        axis: 0,
+    });
+    if order.len() <= LEAF_SIZE {
+        return;
+    }
+    // halves of the triangles along the longest axis of their centroids
+    let axis = (0..3).fold(0, |res, axis| {
+        if high[axis] - low[axis] > high[res] - low[res] {
+            axis
+        } else {
+            res
+        }
+    });
+    let middle = order.len() / 2;
+    order.select_nth_unstable_by(middle, |a, b| {
+        centroids[*a as usize][axis]
+            .partial_cmp(&centroids[*b as usize][axis])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let (left, right) = order.split_at_mut(middle);
+    build(nodes, left, offset, bounds, centroids);
+    let second = nodes.len();
+    build(nodes, right, offset + middle, bounds, centroids);
+    nodes[index] = Node {
+        start: second as u32,
+        count: 0,
+        axis: axis as u8,
+        ..nodes[index]
+    };
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Location>, triangles: Vec<[u32; 3]>) -> Result<Self, String> {
+        if triangles.is_empty() {
+            return Err("a mesh needs at least one triangle".to_string());
+        }
+        if triangles
+            .iter()
+            .flatten()
+            .any(|i| *i as usize >= vertices.len())
+        {
+            return Err("a triangle refers to a missing vertex".to_string());
+        }
+        let bounds: Vec<([f64; 3], [f64; 3])> = triangles
+            .iter()
+            .map(|triangle| {
+                let (mut min, mut max) = ([f64::INFINITY; 3], [f64::NEG_INFINITY; 3]);
+                for &i in triangle {
+                    let p = to_array(vertices[i as usize]);
+                    for axis in 0..3 {
+                        min[axis] = min[axis].min(p[axis]);
+                        max[axis] = max[axis].max(p[axis]);
+                    }
+                }
+                (min, max)
+            })
+            .collect();
+        let centroids: Vec<[f64; 3]> = bounds
+            .iter()
+            .map(|(min, max)| [0, 1, 2].map(|axis| 0.5 * (min[axis] + max[axis])))
+            .collect();
+        let mut order: Vec<u32> = (0..triangles.len() as u32).collect();
+        let mut nodes = Vec::with_capacity(2 * triangles.len() / LEAF_SIZE + 1);
+        build(&mut nodes, &mut order, 0, &bounds, &centroids);
+        // the triangles of each leaf next to each other
+        let triangles = order.iter().map(|i| triangles[*i as usize]).collect();
+        Ok(Mesh {
+            vertices,
+            normals: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            triangles,
+            nodes,
+        })
+    }
+
+    // A .ply (ascii or binary) or .stl (ascii or binary) file
+    pub fn load(file_name: &str) -> Result<Self, String> {
+        let data = std::fs::read(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+        let name = file_name.to_ascii_lowercase();
+        if name.ends_with(".ply") {
+            ply::read(&data)
+        } else if name.ends_with(".stl") {
+            stl::read(&data)
+        } else {
+            Err("unsupported format, expected .ply or .stl".to_string())
+        }
+        .map_err(|e| format!("{}: {}", file_name, e))
+    }
+
+    // Attributes of the vertices are either missing (empty) or given for
+    // each of them
+    fn check_count(&self, count: usize, name: &str) -> Result<(), String> {
+        if count == 0 || count == self.vertices.len() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} {} for {} vertices",
+                count,
+                name,
+                self.vertices.len()
+            ))
+        }
+    }
+
+    pub fn with_normals(self, normals: Vec<Vector>) -> Result<Self, String> {
+        self.check_count(normals.len(), "normals")?;
+        Ok(Mesh { normals, ..self })
+    }
+
+    pub fn with_colors(self, colors: Vec<Color>) -> Result<Self, String> {
+        self.check_count(colors.len(), "colors")?;
+        Ok(Mesh { colors, ..self })
+    }
+
+    pub fn with_uvs(self, uvs: Vec<(f64, f64)>) -> Result<Self, String> {
+        self.check_count(uvs.len(), "texture coordinates")?;
+        Ok(Mesh { uvs, ..self })
+    }
+
+    // With normals at the vertices, averaged over the triangles around them
+    // (weighted by their area) unless they were given
+    pub fn smoothed(self) -> Self {
+        if !self.normals.is_empty() {
+            return self;
+        }
+        let mut normals = vec![Vector::new(0.0, 0.0, 0.0); self.vertices.len()];
+        for triangle in &self.triangles {
+            let [a, b, c] = triangle.map(|i| self.vertices[i as usize]);
+            let normal = (b - a) ^ (c - a);
+            for &i in triangle {
+                normals[i as usize] = normals[i as usize] + normal;
+            }
+        }
+        Mesh { normals, ..self }
+    }
+
+    pub fn has_colors(&self) -> bool {
+        !self.colors.is_empty()
+    }
+
+    // Crossing of the ray with a triangle, with the barycentric coordinates
+    // of its second and third vertices
+    fn hit_triangle(&self, ray: &Ray, k: usize, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let [a, b, c] = self.triangles[k].map(|i| self.vertices[i as usize]);
+        let (e1, e2) = (b - a, c - a);
+        let p = ray.direction ^ e2;
+        let determinant = e1 * p;
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+        let s = ray.location - a;
+        let u = s * p * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s ^ e1;
+        let v = ray.direction * q * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = e2 * q * inverse;
+        if (t_min..=t_max).contains(&t) {
+            Some((t, u, v))
+        } else {
+            None
+        }
+    }
+
+    fn hit_at(&self, ray: &Ray, t: f64, k: usize, u: f64, v: f64) -> Hit {
+        let [i, j, l] = self.triangles[k].map(|i| i as usize);
+        let w = 1.0 - u - v;
+        let (a, b, c) = (self.vertices[i], self.vertices[j], self.vertices[l]);
+        let geometric = (b - a) ^ (c - a);
+        // interpolated normals on the same side as the triangle
+        let normal = if self.normals.is_empty() {
+            geometric
+        } else {
+            let smooth = w * self.normals[i] + u * self.normals[j] + v * self.normals[l];
+            if (smooth * ray.direction) * (geometric * ray.direction) > 0.0 {
+                smooth
+            } else {
+                geometric
+            }
+        };
+        let (tu, tv, tangent) = if self.uvs.is_empty() {
+            (u, v, b - a)
+        } else {
+            let (p, q, r) = (self.uvs[i], self.uvs[j], self.uvs[l]);
+            // along which u increases, from the texture coordinates of the
+            // vertices
+            let (du1, dv1, du2, dv2) = (q.0 - p.0, q.1 - p.1, r.0 - p.0, r.1 - p.1);
+            let determinant = du1 * dv2 - du2 * dv1;
+            let tangent = if determinant.abs() > 1e-12 {
+                (1.0 / determinant) * (dv2 * (b - a) - dv1 * (c - a))
+            } else {
+                b - a
+            };
+            (
+                w * p.0 + u * q.0 + v * r.0,
+                w * p.1 + u * q.1 + v * r.1,
+                tangent,
+            )
+        };
+        let hit = Hit::new(t, normal.as_unit_vector(), tu, tv).with_tangent(tangent);
+        if self.colors.is_empty() {
+            hit
+        } else {
+            Hit {
+                color: Some(w * self.colors[i] + u * self.colors[j] + v * self.colors[l]),
+                ..hit
+            }
+        }
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let o = to_array(ray.location);
+        let d = to_array(ray.direction.as_vector());
+        let inverse = d.map(|d| 1.0 / d);
+        let mut t_max = t_max;
+        let mut closest = None;
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            let (mut start, mut end) = (t_min, t_max);
+            for axis in 0..3 {
+                let t0 = (node.min[axis] - o[axis]) * inverse[axis];
+                let t1 = (node.max[axis] - o[axis]) * inverse[axis];
+                start = start.max(t0.min(t1));
+                end = end.min(t0.max(t1));
+            }
+            if start > end {
+                continue;
+            }
+            if node.count > 0 {
+                let first = node.start as usize;
+                for k in first..first + node.count as usize {
+                    if let Some((t, u, v)) = self.hit_triangle(ray, k, t_min, t_max) {
+                        t_max = t;
+                        closest = Some((t, k, u, v));
+                    }
+                }
+            } else if d[node.axis as usize] < 0.0 {
+                // the child on the side the ray comes from first
+                stack.push(i + 1);
+                stack.push(node.start as usize);
+            } else {
+                stack.push(node.start as usize);
+                stack.push(i + 1);
+            }
+        }
+        closest.map(|(t, k, u, v)| self.hit_at(ray, t, k, u, v).facing(ray))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let root = &self.nodes[0];
+        Some(Aabb::new(
+            Location::new(root.min[0], root.min[1], root.min[2]),
+            Location::new(root.max[0], root.max[1], root.max[2]),
+        ))
+    }
+}
+
+// Fixtures shared by the tests of the file formats
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A square cut in 2 triangles
+    pub const SQUARE: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+    pub const TRIANGLES: [[u32; 3]; 2] = [[0, 1, 2], [0, 2, 3]];
+
+    pub fn assert_square(mesh: &Mesh) {
+        assert_eq!(mesh.vertices.len(), 4);
+        for (vertex, corner) in mesh.vertices.iter().zip(SQUARE.iter()) {
+            let corner = Location::new(corner[0] as f64, corner[1] as f64, corner[2] as f64);
+            assert!((*vertex - corner).length() < 1e-9);
+        }
+        assert_eq!(mesh.triangles, TRIANGLES.to_vec());
+    }
+
+    #[test]
+    fn rejects_attributes_missing_for_some_vertices() {
+        let vertices: Vec<Location> = SQUARE
+            .iter()
+            .map(|p| Location::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        let mesh = || Mesh::new(vertices.clone(), TRIANGLES.to_vec()).unwrap();
+        let normal = Vector::new(0.0, 0.0, 1.0);
+        assert!(mesh().with_normals(vec![normal; 3]).is_err());
+        assert!(mesh().with_normals(vec![normal; 4]).is_ok());
+        assert!(mesh().with_normals(Vec::new()).is_ok());
+        assert!(mesh()
+            .with_colors(vec![Color::new(1.0, 1.0, 1.0); 5])
+            .is_err());
+        assert!(mesh().with_uvs(vec![(0.0, 0.0); 1]).is_err());
+    }
+
+    // Every part of `data` up to `length` bytes fails to be read
+    pub fn assert_rejects_truncated(
+        read: fn(&[u8]) -> Result<Mesh, String>,
+        data: &[u8],
+        length: usize,
+    ) {
+        for length in 0..length {
+            assert!(read(&data[..length]).is_err(), "{} bytes", length);
+        }
+    }
+}
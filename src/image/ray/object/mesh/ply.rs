@@ -0,0 +1,382 @@
+use super::super::super::color::Color;
+use super::super::super::vec3::{Location, Vector};
+use super::Mesh;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+enum Property {
+    Scalar(Scalar, String),
+    // the type of its length, then of its items
+    List(Scalar, Scalar, String),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// Values of the body of the file, in order
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    format: Format,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(format!("unknown type {}", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // Colors stored as integers go up to their greatest value, others to 1
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::U8 => 1.0 / 255.0,
+            Scalar::U16 => 1.0 / 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+impl Reader<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, String> {
+        if self.format == Format::Ascii {
+            let start = self.pos
+                + self.data[self.pos..]
+                    .iter()
+                    .position(|b| !b.is_ascii_whitespace())
+                    .ok_or("truncated data")?;
+            let end = start
+                + self.data[start..]
+                    .iter()
+                    .position(|b| b.is_ascii_whitespace())
+                    .unwrap_or(self.data.len() - start);
+            self.pos = end;
+            let token = std::str::from_utf8(&self.data[start..end]).map_err(|e| e.to_string())?;
+            return token
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number {}", token));
+        }
+        let size = scalar.size();
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + size)
+            .ok_or("truncated data")?;
+        self.pos += size;
+        let mut b = [0; 8];
+        b[..size].copy_from_slice(bytes);
+        if self.format == Format::BigEndian {
+            b[..size].reverse();
+        }
+        Ok(match scalar {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(b),
+        })
+    }
+}
+
+// Header of the file, and where its body starts
+fn read_header(data: &[u8]) -> Result<(Format, Vec<Element>, usize), String> {
+    const END: &[u8] = b"end_header";
+    let end = data
+        .windows(END.len())
+        .position(|window| window == END)
+        .ok_or("not a PLY file")?;
+    let body = end
+        + END.len()
+        + data[end + END.len()..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or("truncated header")?
+        + 1;
+    let header = String::from_utf8_lossy(&data[..end]);
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err("not a PLY file".to_string());
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", kind, _] => {
+                format = Some(match *kind {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(format!("unknown format {}", kind)),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("invalid count {}", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", length, item, name] => elements
+                .last_mut()
+                .ok_or("property outside of an element")?
+                .properties
+                .push(Property::List(
+                    Scalar::parse(length)?,
+                    Scalar::parse(item)?,
+                    name.to_string(),
+                )),
+            ["property", kind, name] => elements
+                .last_mut()
+                .ok_or("property outside of an element")?
+                .properties
+                .push(Property::Scalar(Scalar::parse(kind)?, name.to_string())),
+            _ => (),
+        }
+    }
+    Ok((format.ok_or("no format")?, elements, body))
+}
+
+// Position of the scalar property with one of these names
+fn find(element: &Element, names: &[&str]) -> Option<(usize, Scalar)> {
+    element
+        .properties
+        .iter()
+        .enumerate()
+        .find_map(|(i, property)| match property {
+            Property::Scalar(scalar, name) if names.contains(&name.as_str()) => Some((i, *scalar)),
+            _ => None,
+        })
+}
+
+// Vertices with their position and optionally their normal, color and
+// texture coordinates, and faces as lists of vertices (cut in triangles)
+pub fn read(data: &[u8]) -> Result<Mesh, String> {
+    let (format, elements, body) = read_header(data)?;
+    let mut reader = Reader {
+        data,
+        pos: body,
+        format,
+    };
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut uvs = Vec::new();
+    let mut triangles = Vec::new();
+    let mut values = Vec::new();
+    for element in &elements {
+        let position = [
+            find(element, &["x"]),
+            find(element, &["y"]),
+            find(element, &["z"]),
+        ];
+        let normal = [
+            find(element, &["nx"]),
+            find(element, &["ny"]),
+            find(element, &["nz"]),
+        ];
+        let color = [
+            find(element, &["red", "r", "diffuse_red"]),
+            find(element, &["green", "g", "diffuse_green"]),
+            find(element, &["blue", "b", "diffuse_blue"]),
+        ];
+        let uv = [
+            find(element, &["u", "s", "texture_u", "texture_s"]),
+            find(element, &["v", "t", "texture_v", "texture_t"]),
+        ];
+        let indices = element.properties.iter().position(|property| {
+            matches!(property, Property::List(_, _, name)
+                if name == "vertex_indices" || name == "vertex_index")
+        });
+        for _ in 0..element.count {
+            values.clear();
+            let mut face = Vec::new();
+            for (i, property) in element.properties.iter().enumerate() {
+                match property {
+                    Property::Scalar(scalar, _) => values.push(reader.read(*scalar)?),
+                    Property::List(length, item, _) => {
+                        values.push(0.0);
+                        let length = reader.read(*length)? as usize;
+                        for _ in 0..length {
+                            let value = reader.read(*item)?;
+                            if Some(i) == indices {
+                                face.push(value as u32);
+                            }
+                        }
+                    }
+                }
+            }
+            match element.name.as_str() {
+                "vertex" => {
+                    let get = |property: Option<(usize, Scalar)>| property.map(|(i, _)| values[i]);
+                    match position.map(get) {
+                        [Some(x), Some(y), Some(z)] => vertices.push(Location::new(x, y, z)),
+                        _ => return Err("vertices without x, y and z".to_string()),
+                    }
+                    if let [Some(x), Some(y), Some(z)] = normal.map(get) {
+                        normals.push(Vector::new(x, y, z));
+                    }
+                    if let [Some((r, scalar)), Some((g, _)), Some((b, _))] = color {
+                        let scale = scalar.color_scale();
+                        colors.push(Color::new(
+                            scale * values[r],
+                            scale * values[g],
+                            scale * values[b],
+                        ));
+                    }
+                    if let [Some(u), Some(v)] = uv.map(get) {
+                        uvs.push((u, v));
+                    }
+                }
+                "face" => {
+                    for k in 1..face.len().saturating_sub(1) {
+                        triangles.push([face[0], face[k], face[k + 1]]);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    Mesh::new(vertices, triangles)?
+        .with_normals(normals)?
+        .with_colors(colors)?
+        .with_uvs(uvs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{assert_rejects_truncated, assert_square, SQUARE};
+    use super::*;
+
+    // The square as a single face of 4 vertices, with colored corners
+    const COLORS: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+
+    fn header(format: &str) -> Vec<u8> {
+        format!(
+            "ply\nformat {} 1.0\ncomment square\nelement vertex 4\nproperty float x\n\
+             property float y\nproperty float z\nproperty uchar red\nproperty uchar green\n\
+             property uchar blue\nelement face 1\nproperty list uchar int vertex_indices\n\
+             end_header\n",
+            format
+        )
+        .into_bytes()
+    }
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let mut res = header(if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        });
+        for (corner, color) in SQUARE.iter().zip(COLORS.iter()) {
+            for x in corner {
+                res.extend_from_slice(&if big_endian {
+                    x.to_be_bytes()
+                } else {
+                    x.to_le_bytes()
+                });
+            }
+            res.extend_from_slice(color);
+        }
+        res.push(4);
+        for i in 0..4i32 {
+            res.extend_from_slice(&if big_endian {
+                i.to_be_bytes()
+            } else {
+                i.to_le_bytes()
+            });
+        }
+        res
+    }
+
+    fn ascii() -> Vec<u8> {
+        let mut res = header("ascii");
+        for (corner, color) in SQUARE.iter().zip(COLORS.iter()) {
+            res.extend_from_slice(
+                format!(
+                    "{} {} {} {} {} {}\n",
+                    corner[0], corner[1], corner[2], color[0], color[1], color[2]
+                )
+                .as_bytes(),
+            );
+        }
+        res.extend_from_slice(b"4 0 1 2 3\n");
+        res
+    }
+
+    // the face of 4 vertices is cut in the 2 triangles of the square
+    fn assert_colored_square(mesh: &Mesh) {
+        assert_square(mesh);
+        assert_eq!(mesh.colors.len(), 4);
+        for (color, expected) in mesh.colors.iter().zip(COLORS.iter()) {
+            assert_eq!(
+                [color.get_red(), color.get_green(), color.get_blue()],
+                *expected
+            );
+        }
+    }
+
+    #[test]
+    fn reads_little_endian_files() {
+        assert_colored_square(&read(&binary(false)).unwrap());
+    }
+
+    #[test]
+    fn reads_big_endian_files() {
+        assert_colored_square(&read(&binary(true)).unwrap());
+    }
+
+    #[test]
+    fn reads_ascii_files() {
+        assert_colored_square(&read(&ascii()).unwrap());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        for data in [binary(false), binary(true)].iter() {
+            assert_rejects_truncated(read, data, data.len());
+        }
+        // the end of the face is optional in ascii files
+        let data = ascii();
+        assert_rejects_truncated(read, &data, data.len() - b"4 0 1 2 3\n".len());
+    }
+}
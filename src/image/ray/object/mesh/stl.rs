@@ -0,0 +1,155 @@
+use super::super::super::vec3::Location;
+use super::Mesh;
+use std::collections::HashMap;
+
+// Triangles as the coordinates of their vertices, with vertices at the same
+// place merged so that they can be smoothed
+pub fn read(data: &[u8]) -> Result<Mesh, String> {
+    let corners = if is_binary(data) {
+        read_binary(data)
+    } else if data.starts_with(b"solid") {
+        read_ascii(data)?
+    } else {
+        return Err("not an STL file".to_string());
+    };
+    let mut vertices = Vec::new();
+    let mut indices = HashMap::new();
+    let corners: Vec<u32> = corners
+        .iter()
+        .map(|corner| {
+            *indices.entry(corner.map(f32::to_bits)).or_insert_with(|| {
+                vertices.push(Location::new(
+                    corner[0] as f64,
+                    corner[1] as f64,
+                    corner[2] as f64,
+                ));
+                vertices.len() as u32 - 1
+            })
+        })
+        .collect();
+    let triangles = corners
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .collect();
+    Mesh::new(vertices, triangles)
+}
+
+// An 80 bytes header (which may start with "solid" too), the number of
+// triangles and 50 bytes per triangle
+fn is_binary(data: &[u8]) -> bool {
+    data.len() >= 84
+        && data.len() as u64
+            == 84 + 50 * u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as u64
+}
+
+fn read_binary(data: &[u8]) -> Vec<[f32; 3]> {
+    let float = |bytes: &[u8]| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    data[84..]
+        .chunks_exact(50)
+        .flat_map(|triangle| {
+            // after the normal, ignored since it is often wrong
+            (0..3).map(move |i| {
+                let corner = &triangle[12 + 12 * i..24 + 12 * i];
+                [
+                    float(&corner[0..4]),
+                    float(&corner[4..8]),
+                    float(&corner[8..12]),
+                ]
+            })
+        })
+        .collect()
+}
+
+fn read_ascii(data: &[u8]) -> Result<Vec<[f32; 3]>, String> {
+    let text = String::from_utf8_lossy(data);
+    let mut words = text.split_whitespace();
+    let mut corners = Vec::new();
+    while let Some(word) = words.next() {
+        if word == "vertex" {
+            let mut corner = [0.0; 3];
+            for coordinate in &mut corner {
+                let word = words.next().ok_or("truncated vertex")?;
+                *coordinate = word
+                    .parse()
+                    .map_err(|_| format!("invalid number {}", word))?;
+            }
+            corners.push(corner);
+        }
+    }
+    if corners.len() % 3 != 0 {
+        return Err("facets need 3 vertices".to_string());
+    }
+    Ok(corners)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{assert_rejects_truncated, assert_square, SQUARE, TRIANGLES};
+    use super::*;
+
+    // The corners of the triangles of the square, each one repeated in the
+    // triangles it belongs to
+    fn corners() -> Vec<[f32; 3]> {
+        TRIANGLES
+            .iter()
+            .flatten()
+            .map(|&i| SQUARE[i as usize])
+            .collect()
+    }
+
+    fn binary(header: &[u8]) -> Vec<u8> {
+        let mut res = header.to_vec();
+        res.resize(80, b' ');
+        res.extend_from_slice(&2u32.to_le_bytes());
+        for triangle in corners().chunks(3) {
+            // a wrong normal, which is ignored
+            res.extend_from_slice(&[0; 12]);
+            for x in triangle.iter().flatten() {
+                res.extend_from_slice(&x.to_le_bytes());
+            }
+            res.extend_from_slice(&[0; 2]);
+        }
+        res
+    }
+
+    fn ascii() -> Vec<u8> {
+        let mut res = "solid square\n".to_string();
+        for triangle in corners().chunks(3) {
+            res += "facet normal 0 0 1\nouter loop\n";
+            for corner in triangle {
+                res += &format!("vertex {} {} {}\n", corner[0], corner[1], corner[2]);
+            }
+            res += "endloop\nendfacet\n";
+        }
+        res += "endsolid square\n";
+        res.into_bytes()
+    }
+
+    // the corners at the same place are merged
+    #[test]
+    fn reads_binary_files() {
+        assert_square(&read(&binary(b"square")).unwrap());
+    }
+
+    #[test]
+    fn reads_binary_files_whose_header_starts_with_solid() {
+        assert_square(&read(&binary(b"solid square")).unwrap());
+    }
+
+    #[test]
+    fn reads_ascii_files() {
+        assert_square(&read(&ascii()).unwrap());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        for data in [binary(b"square"), binary(b"solid square")].iter() {
+            assert_rejects_truncated(read, data, data.len());
+        }
+        let data = ascii();
+        let text = String::from_utf8_lossy(&data);
+        // in the middle of the last vertex
+        let length = text.rfind("vertex").unwrap() + "vertex 0 1".len();
+        assert!(read(&data[..length]).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use super::super::bvh::Aabb;
+use super::super::vec3::{Location, UnitDirection};
+use super::super::Ray;
+use super::{hit_plane, Frame, Hit, Hittable};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct Plane {
+    point: Location,
+    normal: UnitDirection,
+}
+
+impl Plane {
+    pub fn new(point: Location, normal: UnitDirection) -> Self {
+        Plane { point, normal }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let hit = hit_plane(ray, self.point, self.normal, t_min, t_max)?;
+        let frame = Frame::new(self.point, self.normal);
+        let local = frame.to_local(ray.at(hit.t));
+        Some(
+            Hit {
+                u: local.get_x(),
+                v: local.get_y(),
+                ..hit
+            }
+            .with_tangent(frame.x.as_vector()),
+        )
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+}
@@ -0,0 +1,170 @@
+// Real roots of polynomials of degree 2 to 4, in no particular order
+// (after Jochen Schwarze, "Cubic and Quartic Roots", Graphics Gems, 1990)
+
+const EPSILON: f64 = 1e-9;
+
+fn is_zero(x: f64) -> bool {
+    x.abs() < EPSILON
+}
+
+// a x^2 + b x + c = 0
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let p = b / (2.0 * a);
+    let q = c / a;
+    let d = p * p - q;
+    if d < 0.0 {
+        Vec::new()
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![sqrt_d - p, -sqrt_d - p]
+    }
+}
+
+// x^3 + a x^2 + b x + c = 0
+fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c) / 2.0;
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+    // the discriminant is compared exactly, since the coefficients can be tiny
+    let mut res = if d == 0.0 {
+        let u = (-q).cbrt();
+        vec![2.0 * u, -u]
+    } else if d < 0.0 {
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + std::f64::consts::PI / 3.0).cos(),
+            -t * (phi - std::f64::consts::PI / 3.0).cos(),
+        ]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+    for root in res.iter_mut() {
+        *root -= a / 3.0;
+    }
+    res
+}
+
+// x^4 + a x^3 + b x^2 + c x + d = 0
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + c;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * c / 4.0 + d;
+    // compared exactly as well, a tiny r still moving the roots a lot
+    let mut res = if r == 0.0 {
+        let mut res = solve_cubic(0.0, p, q);
+        res.push(0.0);
+        res
+    } else {
+        let z = solve_cubic(-p / 2.0, -r, r * p / 2.0 - q * q / 8.0)[0];
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if is_zero(u) {
+            0.0
+        } else if u > 0.0 {
+            u.sqrt()
+        } else {
+            return Vec::new();
+        };
+        let v = if is_zero(v) {
+            0.0
+        } else if v > 0.0 {
+            v.sqrt()
+        } else {
+            return Vec::new();
+        };
+        let mut res = solve_quadratic(1.0, if q < 0.0 { -v } else { v }, z - u);
+        res.append(&mut solve_quadratic(
+            1.0,
+            if q < 0.0 { v } else { -v },
+            z + u,
+        ));
+        res
+    };
+    for root in res.iter_mut() {
+        *root -= a / 4.0;
+        // polish with Newton's method, the closed form loses a lot of precision
+        for _ in 0..2 {
+            let x = *root;
+            let f = (((x + a) * x + b) * x + c) * x + d;
+            let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+            if df != 0.0 {
+                *root -= f / df;
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every root is close to one of the expected ones and the other way
+    // around, repeated roots being found once or more
+    fn assert_roots(roots: Vec<f64>, expected: &[f64], tolerance: f64) {
+        let near = |x: f64, y: f64| (x - y).abs() <= tolerance * y.abs().max(1.0);
+        for root in &roots {
+            assert!(
+                expected.iter().any(|x| near(*root, *x)),
+                "{:?} instead of {:?}",
+                roots,
+                expected
+            );
+        }
+        for x in expected {
+            assert!(
+                roots.iter().any(|root| near(*root, *x)),
+                "{:?} instead of {:?}",
+                roots,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn quadratic() {
+        assert_roots(solve_quadratic(1.0, -3.0, 2.0), &[1.0, 2.0], 1e-12);
+        assert_roots(solve_quadratic(2.0, 0.0, -8.0), &[-2.0, 2.0], 1e-12);
+        assert_roots(solve_quadratic(1.0, 0.0, 1.0), &[], 0.0);
+        // a double root, even with a discriminant rounded to zero
+        assert_roots(solve_quadratic(1.0, -2.0, 1.0), &[1.0], 1e-12);
+        assert_roots(solve_quadratic(1e-12, -3e-12, 2e-12), &[1.0, 2.0], 1e-9);
+    }
+
+    #[test]
+    fn cubic() {
+        assert_roots(solve_cubic(-6.0, 11.0, -6.0), &[1.0, 2.0, 3.0], 1e-9);
+        assert_roots(solve_cubic(0.0, 0.0, -8.0), &[2.0], 1e-12);
+        // (x - 1)^2 (x + 2)
+        assert_roots(solve_cubic(0.0, -3.0, 2.0), &[-2.0, 1.0], 1e-9);
+        assert_roots(solve_cubic(0.0, 0.0, 0.0), &[0.0], 1e-12);
+        // (x - 1e-3) (x - 2e-3) (x + 3e-3)
+        assert_roots(solve_cubic(0.0, -7e-6, 6e-9), &[1e-3, 2e-3, -3e-3], 1e-9);
+    }
+
+    #[test]
+    fn quartic() {
+        assert_roots(
+            solve_quartic(-10.0, 35.0, -50.0, 24.0),
+            &[1.0, 2.0, 3.0, 4.0],
+            1e-9,
+        );
+        assert_roots(solve_quartic(0.0, 0.0, 0.0, 1.0), &[], 0.0);
+        // (x - 1)^2 (x - 3)^2, only found to the square root of the precision
+        assert_roots(solve_quartic(-8.0, 22.0, -24.0, 9.0), &[1.0, 3.0], 1e-6);
+        // (x^2 - 1) (x^2 + 1)
+        assert_roots(solve_quartic(0.0, 0.0, 0.0, -1.0), &[-1.0, 1.0], 1e-9);
+        // (x^2 - 1e-6) (x^2 - 4e-6)
+        assert_roots(
+            solve_quartic(0.0, -5e-6, 0.0, 4e-12),
+            &[-2e-3, -1e-3, 1e-3, 2e-3],
+            1e-9,
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use super::super::bvh::Aabb;
+use super::super::vec3::{Direction, Location};
+use super::super::Ray;
+use super::{hit_plane, Hit, Hittable};
+use serde::Deserialize;
+
+// Parallelogram spanned by the edges `u` and `v` starting at `corner`
+#[derive(Deserialize)]
+#[serde(try_from = "QuadData")]
+pub struct Quad {
+    corner: Location,
+    u: Direction,
+    v: Direction,
+}
+
+#[derive(Deserialize)]
+struct QuadData {
+    corner: Location,
+    u: Direction,
+    v: Direction,
+}
+
+impl std::convert::TryFrom<QuadData> for Quad {
+    type Error = String;
+
+    fn try_from(data: QuadData) -> Result<Self, Self::Error> {
+        Quad::new(data.corner, data.u, data.v)
+    }
+}
+
+impl Quad {
+    pub fn new(corner: Location, u: Direction, v: Direction) -> Result<Self, String> {
+        // the plane of the quad is undefined otherwise
+        if (u ^ v).length_squared() <= 1e-12 * u.length_squared() * v.length_squared() {
+            return Err("the edges of a quad must not be parallel or zero".to_string());
+        }
+        Ok(Quad { corner, u, v })
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let n = self.u ^ self.v;
+        let hit = hit_plane(ray, self.corner, n.as_unit_vector(), t_min, t_max)?;
+        let p = ray.at(hit.t) - self.corner;
+        let w = n / n.length_squared();
+        let alpha = w * (p ^ self.v);
+        let beta = w * (self.u ^ p);
+        if (0.0..=1.0).contains(&alpha) && (0.0..=1.0).contains(&beta) {
+            Some(
+                Hit {
+                    u: alpha,
+                    v: beta,
+                    ..hit
+                }
+                .with_tangent(self.u),
+            )
+        } else {
+            None
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(
+            Aabb::new(self.corner, self.corner + self.u + self.v)
+                .union(&Aabb::new(self.corner + self.u, self.corner + self.v)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::ray::vec3::UnitDirection;
+
+    #[test]
+    fn rejects_parallel_or_zero_edges() {
+        let corner = Location::new(0.0, 0.0, 0.0);
+        let u = Direction::new(1.0, 0.0, 0.0);
+        assert!(Quad::new(corner, u, Direction::new(2.0, 0.0, 0.0)).is_err());
+        assert!(Quad::new(corner, u, Direction::new(0.0, 0.0, 0.0)).is_err());
+        assert!(serde_json::from_str::<Quad>(
+            r#"{"corner": {"x": 0, "y": 0, "z": 0},
+                "u": {"x": 0, "y": 0, "z": 0},
+                "v": {"x": 0, "y": 1, "z": 0}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn hits_inside_the_edges() {
+        let quad = Quad::new(
+            Location::new(-1.0, -1.0, -2.0),
+            Direction::new(2.0, 0.0, 0.0),
+            Direction::new(0.0, 2.0, 0.0),
+        )
+        .unwrap();
+        let origin = Location::new(0.0, 0.0, 0.0);
+        let ray = Ray::new(origin, UnitDirection::new(0.0, 0.0, -1.0));
+        let hit = quad.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!((hit.u - 0.5).abs() < 1e-9 && (hit.v - 0.5).abs() < 1e-9);
+        let ray = Ray::new(origin, UnitDirection::new(1.0, 0.0, -1.0));
+        assert!(quad.hit(&ray, 0.0, f64::INFINITY).is_none());
+    }
+}
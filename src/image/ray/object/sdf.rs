@@ -0,0 +1,518 @@
+use super::super::bvh::Aabb;
+use super::super::vec3::{Location, UnitDirection, Vector};
+use super::super::Ray;
+use super::{Hit, Hittable};
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+// Surface where a signed distance function is 0, found by sphere tracing:
+// stepping along the ray by the distance to the closest point of the surface
+#[derive(Deserialize)]
+#[serde(try_from = "SdfData")]
+pub struct Sdf {
+    field: Field,
+}
+
+#[derive(Deserialize)]
+struct SdfData {
+    field: Field,
+}
+
+// Signed distance functions, negative inside, the smooth operations blending
+// the surfaces of both sides over `smoothness`
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Field {
+    Sphere {
+        center: Location,
+        radius: f64,
+    },
+    // box of dimensions `size`, whose edges are rounded by `radius`
+    RoundedBox {
+        center: Location,
+        size: Vector,
+        #[serde(default)]
+        radius: f64,
+    },
+    Torus {
+        center: Location,
+        #[serde(default = "default_axis")]
+        axis: UnitDirection,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    Capsule {
+        start: Location,
+        end: Location,
+        radius: f64,
+    },
+    // cube of side `size` with holes, repeated in the remaining cubes at each
+    // iteration
+    MengerSponge {
+        center: Location,
+        size: f64,
+        #[serde(default = "menger_iterations")]
+        iterations: u32,
+    },
+    // 3D Mandelbrot set, about `radius` wide, around the z axis
+    Mandelbulb {
+        center: Location,
+        radius: f64,
+        #[serde(default = "power")]
+        power: f64,
+        #[serde(default = "mandelbulb_iterations")]
+        iterations: u32,
+    },
+    SmoothUnion {
+        left: Box<Field>,
+        right: Box<Field>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+    // `left` without `right`
+    #[serde(alias = "smooth_subtraction")]
+    SmoothDifference {
+        left: Box<Field>,
+        right: Box<Field>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+}
+
+// Distance to the surface under which it is reached
+const EPSILON: f64 = 1e-4;
+const MAX_STEPS: usize = 512;
+
+fn default_axis() -> UnitDirection {
+    UnitDirection::new(0.0, 1.0, 0.0)
+}
+
+fn menger_iterations() -> u32 {
+    4
+}
+
+fn power() -> f64 {
+    8.0
+}
+
+fn mandelbulb_iterations() -> u32 {
+    10
+}
+
+fn to_array(vector: Vector) -> [f64; 3] {
+    [vector.get_x(), vector.get_y(), vector.get_z()]
+}
+
+fn from_array(a: [f64; 3]) -> Vector {
+    Vector::new(a[0], a[1], a[2])
+}
+
+// Direction of a gradient, unless it vanishes
+fn unit(gradient: Vector) -> Option<Vector> {
+    let length = gradient.length();
+    if length > 1e-12 {
+        Some(gradient / length)
+    } else {
+        None
+    }
+}
+
+// Blend of `a` and `b` going smoothly from the smallest one to the other one
+// over `smoothness`, and the weight of `a` in it
+fn smooth_min(a: f64, b: f64, smoothness: f64) -> (f64, f64) {
+    if smoothness <= 0.0 {
+        return if a < b { (a, 1.0) } else { (b, 0.0) };
+    }
+    let h = (0.5 + 0.5 * (b - a) / smoothness).clamp(0.0, 1.0);
+    (h * a + (1.0 - h) * b - smoothness * h * (1.0 - h), h)
+}
+
+// Iterations of the Mandelbulb from `p`, with the last point and the
+// derivative of its distance to the origin
+fn mandelbulb(p: Vector, power: f64, iterations: u32) -> (f64, f64) {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = z.length();
+    for _ in 0..iterations {
+        if r > 2.0 {
+            break;
+        }
+        let theta = (z.get_z() / r).clamp(-1.0, 1.0).acos() * power;
+        let phi = z.get_y().atan2(z.get_x()) * power;
+        let r_power = r.powf(power - 1.0);
+        dr = r_power * power * dr + 1.0;
+        z = (r_power * r)
+            * Vector::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            )
+            + p;
+        r = z.length();
+    }
+    (r, dr)
+}
+
+impl std::convert::TryFrom<SdfData> for Sdf {
+    type Error = String;
+
+    fn try_from(data: SdfData) -> Result<Self, Self::Error> {
+        Sdf::new(data.field)
+    }
+}
+
+impl Field {
+    // Parameters for which the surface is empty or the distance is not a
+    // number are rejected
+    fn check(&self) -> Result<(), String> {
+        let positive = |value: f64, name: &str| {
+            if value > 0.0 {
+                Ok(())
+            } else {
+                Err(format!(
+                    "the {} of a signed distance field must be positive",
+                    name
+                ))
+            }
+        };
+        match self {
+            Field::Sphere { radius, .. } | Field::Capsule { radius, .. } => {
+                positive(*radius, "radius")
+            }
+            Field::RoundedBox { size, radius, .. } => {
+                let smallest = size.get_x().min(size.get_y()).min(size.get_z());
+                positive(smallest, "size")?;
+                if *radius < 0.0 || *radius > 0.5 * smallest {
+                    return Err(
+                        "the radius of a rounded box must be between 0 and half its size"
+                            .to_string(),
+                    );
+                }
+                Ok(())
+            }
+            Field::Torus {
+                major_radius,
+                minor_radius,
+                ..
+            } => {
+                positive(*major_radius, "major radius")?;
+                positive(*minor_radius, "minor radius")
+            }
+            Field::MengerSponge { size, .. } => positive(*size, "size"),
+            Field::Mandelbulb {
+                radius,
+                power,
+                iterations,
+                ..
+            } => {
+                positive(*radius, "radius")?;
+                positive(*power, "power")?;
+                positive(*iterations as f64, "number of iterations")
+            }
+            Field::SmoothUnion { left, right, .. }
+            | Field::SmoothDifference { left, right, .. } => {
+                left.check()?;
+                right.check()
+            }
+        }
+    }
+
+    pub fn distance(&self, p: Location) -> f64 {
+        match self {
+            Field::Sphere { center, radius } => (p - *center).length() - radius,
+            Field::RoundedBox {
+                center,
+                size,
+                radius,
+            } => {
+                let (q, half) = (to_array(p - *center), to_array(*size));
+                let a = [0, 1, 2].map(|i| q[i].abs() - (0.5 * half[i] - radius));
+                let outside = from_array(a.map(|a| a.max(0.0))).length();
+                outside + a[0].max(a[1]).max(a[2]).min(0.0) - radius
+            }
+            Field::Torus {
+                center,
+                axis,
+                major_radius,
+                minor_radius,
+            } => {
+                let q = p - *center;
+                let height = q * *axis;
+                let ring = (q - height * *axis).length() - major_radius;
+                (ring * ring + height * height).sqrt() - minor_radius
+            }
+            Field::Capsule { start, end, radius } => {
+                let (pa, ba) = (p - *start, *end - *start);
+                let h = (pa * ba / ba.length_squared()).clamp(0.0, 1.0);
+                (pa - h * ba).length() - radius
+            }
+            Field::MengerSponge {
+                center,
+                size,
+                iterations,
+            } => {
+                // in a cube from -1 to 1
+                let p = to_array((2.0 / size) * (p - *center));
+                let a = p.map(|p| p.abs() - 1.0);
+                let mut res =
+                    from_array(a.map(|a| a.max(0.0))).length() + a[0].max(a[1]).max(a[2]).min(0.0);
+                let mut scale = 1.0;
+                for _ in 0..*iterations {
+                    let a = p.map(|p| (p * scale).rem_euclid(2.0) - 1.0);
+                    scale *= 3.0;
+                    let r = a.map(|a| (1.0 - 3.0 * a.abs()).abs());
+                    let cross = r[0].max(r[1]).min(r[1].max(r[2])).min(r[2].max(r[0]));
+                    res = res.max((cross - 1.0) / scale);
+                }
+                0.5 * size * res
+            }
+            Field::Mandelbulb {
+                center,
+                radius,
+                power,
+                iterations,
+            } => {
+                let (r, dr) = mandelbulb((p - *center) / *radius, *power, *iterations);
+                if r > 0.0 {
+                    0.5 * r.ln() * r / dr * radius
+                } else {
+                    0.0
+                }
+            }
+            Field::SmoothUnion {
+                left,
+                right,
+                smoothness,
+            } => smooth_min(left.distance(p), right.distance(p), *smoothness).0,
+            Field::SmoothDifference {
+                left,
+                right,
+                smoothness,
+            } => -smooth_min(-left.distance(p), right.distance(p), *smoothness).0,
+        }
+    }
+
+    // Unit gradient of the distance, where it is known analytically
+    fn gradient(&self, p: Location) -> Option<Vector> {
+        match self {
+            Field::Sphere { center, .. } => unit(p - *center),
+            Field::RoundedBox {
+                center,
+                size,
+                radius,
+            } => {
+                let (q, half) = (to_array(p - *center), to_array(*size));
+                let a = [0, 1, 2].map(|i| q[i].abs() - (0.5 * half[i] - radius));
+                let sign = q.map(|q| if q < 0.0 { -1.0 } else { 1.0 });
+                if a.iter().any(|a| *a > 0.0) {
+                    unit(from_array([0, 1, 2].map(|i| a[i].max(0.0) * sign[i])))
+                } else {
+                    let i = (0..3).fold(0, |i, j| if a[j] > a[i] { j } else { i });
+                    let mut res = [0.0; 3];
+                    res[i] = sign[i];
+                    Some(from_array(res))
+                }
+            }
+            Field::Torus {
+                center,
+                axis,
+                major_radius,
+                ..
+            } => {
+                let q = p - *center;
+                let height = q * *axis;
+                let radial = unit(q - height * *axis)?;
+                unit(q - *major_radius * radial)
+            }
+            Field::Capsule { start, end, .. } => {
+                let (pa, ba) = (p - *start, *end - *start);
+                let h = (pa * ba / ba.length_squared()).clamp(0.0, 1.0);
+                unit(pa - h * ba)
+            }
+            // only estimated
+            Field::MengerSponge { .. } | Field::Mandelbulb { .. } => None,
+            Field::SmoothUnion {
+                left,
+                right,
+                smoothness,
+            } => {
+                let (a, b) = (left.gradient(p)?, right.gradient(p)?);
+                let h = smooth_min(left.distance(p), right.distance(p), *smoothness).1;
+                unit(h * a + (1.0 - h) * b)
+            }
+            Field::SmoothDifference {
+                left,
+                right,
+                smoothness,
+            } => {
+                let (a, b) = (left.gradient(p)?, right.gradient(p)?);
+                let h = smooth_min(-left.distance(p), right.distance(p), *smoothness).1;
+                unit(h * a - (1.0 - h) * b)
+            }
+        }
+    }
+
+    // Normal pointing outside, from the gradient of the distance or
+    // estimated by finite differences
+    fn normal(&self, p: Location) -> UnitDirection {
+        if let Some(gradient) = self.gradient(p) {
+            return gradient.as_unit_vector();
+        }
+        // at the corners of a tetrahedron around the point
+        let step = 0.1 * EPSILON;
+        let res = [
+            (1.0, -1.0, -1.0),
+            (-1.0, -1.0, 1.0),
+            (-1.0, 1.0, -1.0),
+            (1.0, 1.0, 1.0),
+        ]
+        .iter()
+        .fold(Vector::new(0.0, 0.0, 0.0), |res, (x, y, z)| {
+            let k = Vector::new(*x, *y, *z);
+            res + self.distance(p + step * k) * k
+        });
+        unit(res)
+            .unwrap_or(Vector::new(0.0, 1.0, 0.0))
+            .as_unit_vector()
+    }
+
+    // Corners of a box around the surface
+    fn extent(&self) -> (Location, Location) {
+        let around = |center: Location, radius: f64| {
+            let radius = Vector::new(radius, radius, radius);
+            (center - radius, center + radius)
+        };
+        match self {
+            Field::Sphere { center, radius } => around(*center, *radius),
+            Field::RoundedBox { center, size, .. } => {
+                (*center - 0.5 * *size, *center + 0.5 * *size)
+            }
+            Field::Torus {
+                center,
+                major_radius,
+                minor_radius,
+                ..
+            } => around(*center, major_radius + minor_radius),
+            Field::Capsule { start, end, radius } => {
+                let (a, b) = (to_array(*start), to_array(*end));
+                (
+                    from_array([0, 1, 2].map(|i| a[i].min(b[i]) - radius)),
+                    from_array([0, 1, 2].map(|i| a[i].max(b[i]) + radius)),
+                )
+            }
+            Field::MengerSponge { center, size, .. } => around(*center, 0.5 * size),
+            // all the powers fit in there
+            Field::Mandelbulb { center, radius, .. } => around(*center, 1.5 * radius),
+            Field::SmoothUnion {
+                left,
+                right,
+                smoothness,
+            } => {
+                // blending swells the surface by a quarter of the smoothness
+                let ((a, b), (c, d)) = (left.extent(), right.extent());
+                let (a, b, c, d) = (to_array(a), to_array(b), to_array(c), to_array(d));
+                let margin = 0.25 * smoothness.max(0.0);
+                (
+                    from_array([0, 1, 2].map(|i| a[i].min(c[i]) - margin)),
+                    from_array([0, 1, 2].map(|i| b[i].max(d[i]) + margin)),
+                )
+            }
+            Field::SmoothDifference { left, .. } => left.extent(),
+        }
+    }
+}
+
+impl Sdf {
+    pub fn new(field: Field) -> Result<Self, String> {
+        field.check()?;
+        Ok(Sdf { field })
+    }
+
+    fn hit_at(&self, ray: &Ray, t: f64) -> Hit {
+        let normal = self.field.normal(ray.at(t));
+        Hit::new(
+            t,
+            normal,
+            ((-normal.get_z()).atan2(normal.get_x()) + PI) / (2.0 * PI),
+            (-normal.get_y()).acos() / PI,
+        )
+    }
+}
+
+impl Hittable for Sdf {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let (a, b) = self.field.extent();
+        let (start, end) = Aabb::new(a, b).clip(ray, t_min, t_max)?;
+        let mut t = start;
+        // the side of the surface the ray is on, outside for rays entering
+        // the bounds, otherwise known once far enough from the surface the
+        // ray may start from (inside for rays going through it)
+        let mut side = if start > t_min { Some(1.0) } else { None };
+        for _ in 0..MAX_STEPS {
+            if t > end {
+                return None;
+            }
+            let distance = self.field.distance(ray.at(t));
+            match side {
+                None if distance.abs() < EPSILON => t += EPSILON,
+                None => {
+                    side = Some(distance.signum());
+                    t += distance.abs();
+                }
+                Some(side) if side * distance < EPSILON => {
+                    return Some(self.hit_at(ray, t).facing(ray));
+                }
+                Some(side) => t += side * distance,
+            }
+        }
+        None
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let (a, b) = self.field.extent();
+        Some(Aabb::new(a, b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sdf(field: &str) -> Result<Sdf, serde_json::Error> {
+        serde_json::from_str(&format!(r#"{{"field": {}}}"#, field))
+    }
+
+    #[test]
+    fn rejects_empty_fields() {
+        let center = r#""center": {"x": 0, "y": 0, "z": 0}"#;
+        for field in [
+            format!(r#"{{"type": "sphere", {}, "radius": 0}}"#, center),
+            format!(
+                r#"{{"type": "rounded_box", {}, "size": {{"x": 1, "y": 0, "z": 1}}}}"#,
+                center
+            ),
+            format!(
+                r#"{{"type": "rounded_box", {}, "size": {{"x": 1, "y": 1, "z": 1}}, "radius": 0.6}}"#,
+                center
+            ),
+            format!(r#"{{"type": "menger_sponge", {}, "size": 0}}"#, center),
+            format!(r#"{{"type": "mandelbulb", {}, "radius": 0}}"#, center),
+            format!(r#"{{"type": "mandelbulb", {}, "radius": 1, "power": 0}}"#, center),
+            format!(
+                r#"{{"type": "mandelbulb", {}, "radius": 1, "iterations": 0}}"#,
+                center
+            ),
+            format!(
+                r#"{{"type": "smooth_union",
+                    "left": {{"type": "sphere", {0}, "radius": 1}},
+                    "right": {{"type": "sphere", {0}, "radius": 0}}}}"#,
+                center
+            ),
+        ]
+        .iter()
+        {
+            assert!(sdf(field).is_err(), "{}", field);
+        }
+        assert!(sdf(&format!(r#"{{"type": "sphere", {}, "radius": 1}}"#, center)).is_ok());
+    }
+}
@@ -0,0 +1,101 @@
+use super::super::bvh::Aabb;
+use super::super::vec3::{Location, Vector};
+use super::super::Ray;
+use super::{Hit, Hittable, Interval, Solid};
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+#[derive(Deserialize)]
+pub struct Sphere {
+    center: Location,
+    radius: f64,
+    // distance covered between times 0 and 1, after which it stays still
+    #[serde(default)]
+    velocity: Option<Vector>,
+}
+
+impl Sphere {
+    pub fn new(center: Location, radius: f64) -> Self {
+        Sphere {
+            center,
+            radius,
+            velocity: None,
+        }
+    }
+
+    pub fn with_velocity(self, velocity: Vector) -> Self {
+        Sphere {
+            velocity: Some(velocity),
+            ..self
+        }
+    }
+
+    fn center(&self, time: f64) -> Location {
+        match self.velocity {
+            Some(velocity) => self.center + time.clamp(0.0, 1.0) * velocity,
+            None => self.center,
+        }
+    }
+
+    // Both roots, if the ray's line crosses the sphere
+    fn roots(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let oc = ray.location - self.center(ray.time);
+        let half_b = oc * ray.direction;
+        let c = oc.length_squared() - self.radius * self.radius;
+        let d = half_b * half_b - c;
+        if d > 0.0 {
+            Some((-half_b - d.sqrt(), -half_b + d.sqrt()))
+        } else {
+            None
+        }
+    }
+
+    fn hit_at(&self, ray: &Ray, t: f64) -> Hit {
+        let normal = (ray.at(t) - self.center(ray.time)).as_unit_vector();
+        Hit::new(
+            t,
+            normal,
+            ((-normal.get_z()).atan2(normal.get_x()) + PI) / (2.0 * PI),
+            (-normal.get_y()).acos() / PI,
+        )
+        .with_tangent(Vector::new(normal.get_z(), 0.0, -normal.get_x()))
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let (near, far) = self.roots(ray)?;
+        let t = if (t_min..=t_max).contains(&near) {
+            near
+        } else if (t_min..=t_max).contains(&far) {
+            far
+        } else {
+            return None;
+        };
+        Some(self.hit_at(ray, t).facing(ray))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let radius = Vector::new(self.radius, self.radius, self.radius);
+        let start = Aabb::new(self.center - radius, self.center + radius);
+        Some(match self.velocity {
+            Some(velocity) => start.union(&Aabb::new(
+                self.center + velocity - radius,
+                self.center + velocity + radius,
+            )),
+            None => start,
+        })
+    }
+}
+
+impl Solid for Sphere {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        match self.roots(ray) {
+            Some((enter, exit)) => vec![Interval {
+                enter: self.hit_at(ray, enter),
+                exit: self.hit_at(ray, exit),
+            }],
+            None => Vec::new(),
+        }
+    }
+}
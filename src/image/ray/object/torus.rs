@@ -0,0 +1,160 @@
+use super::super::bvh::Aabb;
+use super::super::vec3::{Location, UnitDirection, Vector};
+use super::super::Ray;
+use super::polynomial::{solve_quadratic, solve_quartic};
+use super::{azimuth, closest, intervals, Frame, Hit, Hittable, Interval, Solid};
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+// Torus around `axis`, whose tube of radius `minor_radius` follows a circle
+// of radius `major_radius`
+#[derive(Deserialize)]
+pub struct Torus {
+    center: Location,
+    #[serde(default = "default_axis")]
+    axis: UnitDirection,
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+fn default_axis() -> UnitDirection {
+    UnitDirection::new(0.0, 1.0, 0.0)
+}
+
+impl Torus {
+    pub fn new(
+        center: Location,
+        axis: UnitDirection,
+        major_radius: f64,
+        minor_radius: f64,
+    ) -> Self {
+        Torus {
+            center,
+            axis,
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    // All the crossings of the ray's line with the surface
+    fn hits(&self, ray: &Ray) -> Vec<Hit> {
+        let frame = Frame::new(self.center, self.axis);
+        let o = frame.to_local(ray.location);
+        let d = frame.to_local_direction(ray.direction);
+        // start from the bounding sphere to keep the quartic well conditioned
+        let bounding_radius = self.major_radius + self.minor_radius;
+        let entry = solve_quadratic(
+            1.0,
+            2.0 * (o * d),
+            o.length_squared() - bounding_radius * bounding_radius,
+        )
+        .into_iter()
+        .fold(f64::INFINITY, f64::min);
+        if entry == f64::INFINITY {
+            return Vec::new();
+        }
+        let shift = entry;
+        let o = o + shift * d;
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2)
+        let r2 = self.major_radius * self.major_radius;
+        let e = o.length_squared() - r2 - self.minor_radius * self.minor_radius;
+        let f = o * d;
+        let roots = solve_quartic(
+            4.0 * f,
+            2.0 * e + 4.0 * f * f + 4.0 * r2 * d.get_z() * d.get_z(),
+            4.0 * f * e + 8.0 * r2 * o.get_z() * d.get_z(),
+            e * e - 4.0 * r2 * (self.minor_radius * self.minor_radius - o.get_z() * o.get_z()),
+        );
+        roots
+            .into_iter()
+            .map(|t| {
+                let p = o + t * d;
+                let ring = Vector::new(p.get_x(), p.get_y(), 0.0);
+                let ring = (self.major_radius / ring.length()) * ring;
+                let tube = p - ring;
+                Hit::new(
+                    t + shift,
+                    frame.to_world_direction(tube),
+                    azimuth(p),
+                    (tube.get_z().atan2(tube * ring / self.major_radius) + PI) / (2.0 * PI),
+                )
+                .with_tangent(frame.tangent(p))
+            })
+            .collect()
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        closest(ray, self.hits(ray), t_min, t_max)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let extent = |n: f64| self.major_radius * (1.0 - n * n).max(0.0).sqrt() + self.minor_radius;
+        let half = Vector::new(
+            extent(self.axis.get_x()),
+            extent(self.axis.get_y()),
+            extent(self.axis.get_z()),
+        );
+        Some(Aabb::new(self.center - half, self.center + half))
+    }
+}
+
+impl Solid for Torus {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        intervals(self.hits(ray), ray.direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{assert_hit, hit};
+    use super::*;
+
+    fn torus() -> Torus {
+        Torus::new(
+            Location::new(0.0, 0.0, 0.0),
+            UnitDirection::new(0.0, 1.0, 0.0),
+            2.0,
+            0.5,
+        )
+    }
+
+    #[test]
+    fn hits_the_tube() {
+        assert_hit(
+            hit(&torus(), (0.0, 0.0, 5.0), (0.0, 0.0, -1.0)),
+            2.5,
+            (0.0, 0.0, 1.0),
+        );
+        assert_hit(
+            hit(&torus(), (2.0, 5.0, 0.0), (0.0, -1.0, 0.0)),
+            4.5,
+            (0.0, 1.0, 0.0),
+        );
+        assert_hit(
+            hit(&torus(), (0.0, -5.0, -2.0), (0.0, 1.0, 0.0)),
+            4.5,
+            (0.0, -1.0, 0.0),
+        );
+        // from the hole, towards the inner side of the tube
+        assert_hit(
+            hit(&torus(), (0.0, 0.0, 0.0), (1.0, 0.0, 0.0)),
+            1.5,
+            (-1.0, 0.0, 0.0),
+        );
+        // at 45 degrees up the tube
+        let y = 0.5 * 0.5f64.sqrt();
+        assert_hit(
+            hit(&torus(), (5.0, y, 0.0), (-1.0, 0.0, 0.0)),
+            3.0 - y,
+            (1.0, 1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn misses_through_the_hole() {
+        assert!(hit(&torus(), (0.0, 5.0, 0.0), (0.0, -1.0, 0.0)).is_none());
+        assert!(hit(&torus(), (0.0, 0.6, 5.0), (0.0, 0.0, -1.0)).is_none());
+    }
+}
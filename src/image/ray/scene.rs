@@ -0,0 +1,539 @@
+mod gltf;
+
+use super::animation::{Animated, Animation};
+use super::bump::Bump;
+use super::bvh::Aabb;
+use super::color::{Color, BLACK, WHITE};
+use super::light::Light;
+use super::material::Material;
+use super::medium::Medium;
+use super::object::{Object, Shape};
+use super::texture::Texture;
+use super::transform::{Motion, Transform};
+use super::vec3::{Location, UnitDirection};
+use super::Background;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+pub enum NodeKind {
+    Shape(Arc<Shape>),
+    Group(Vec<Node>),
+    // the node with this name, placed again
+    Instance(String),
+    // copies of a node, one per transform
+    Instances(Box<Node>, Vec<Transform>),
+}
+
+// Node of the scene graph, its transform, color or texture, material, bump map
+// and medium apply to its descendants (their own ones take precedence)
+#[derive(Deserialize)]
+pub struct Node {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub transform: Option<Animated<Transform>>,
+    // transform at time 1 of moving nodes, `transform` being the one at time 0
+    #[serde(default)]
+    pub end_transform: Option<Transform>,
+    #[serde(default)]
+    pub color: Option<Color>,
+    // takes precedence over the color
+    #[serde(default)]
+    pub texture: Option<Arc<Texture>>,
+    // diffuse by default
+    #[serde(default)]
+    pub material: Option<Arc<Material>>,
+    #[serde(default)]
+    pub bump: Option<Arc<Bump>>,
+    // inside closed objects
+    #[serde(default)]
+    pub medium: Option<Arc<Medium>>,
+    // hidden nodes are only rendered through instances
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(flatten)]
+    pub kind: NodeKind,
+}
+
+// Camera of a scene file, the options of the command line take precedence
+#[derive(Deserialize, Default)]
+pub struct Camera {
+    #[serde(default)]
+    pub location: Option<Animated<Location>>,
+    #[serde(default)]
+    pub direction: Option<Animated<UnitDirection>>,
+    // name of a node to point the camera at, instead of a direction
+    #[serde(default)]
+    pub target: Option<String>,
+    // in degrees
+    #[serde(default)]
+    pub fov: Option<Animated<f64>>,
+    // in degrees, as given by glTF cameras, used without `fov` once the
+    // aspect ratio of the image is known
+    #[serde(skip)]
+    pub vertical_fov: Option<Animated<f64>>,
+}
+
+// Content of a json file, either a list of nodes or an object with a
+// `camera`, a `background`, a `medium`, `lights` and the list of nodes as
+// `objects`
+#[derive(Default)]
+pub struct Scene {
+    pub camera: Camera,
+    // the background of the command line takes precedence
+    pub background: Option<Background>,
+    // filling the scene, outside of the objects
+    pub medium: Option<Arc<Medium>>,
+    pub lights: Vec<Light>,
+    pub nodes: Vec<Node>,
+}
+
+impl<'de> Deserialize<'de> for NodeKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Group {
+            children: Vec<Node>,
+        }
+        #[derive(Deserialize)]
+        struct Instance {
+            of: String,
+        }
+        #[derive(Deserialize)]
+        struct Instances {
+            object: Box<Node>,
+            transforms: Vec<Transform>,
+        }
+        let mut value = Value::deserialize(deserializer)?;
+        let kind = value
+            .get("type")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        match kind.as_deref() {
+            Some("group") => {
+                serde_json::from_value(value).map(|group: Group| NodeKind::Group(group.children))
+            }
+            Some("instance") => serde_json::from_value(value)
+                .map(|instance: Instance| NodeKind::Instance(instance.of)),
+            Some("instances") => serde_json::from_value(value).map(|instances: Instances| {
+                NodeKind::Instances(instances.object, instances.transforms)
+            }),
+            Some(_) => serde_json::from_value(value).map(|shape| NodeKind::Shape(Arc::new(shape))),
+            // objects without a "type" are spheres, for compatibility with older files
+            None => {
+                if let Some(map) = value.as_object_mut() {
+                    map.insert("type".to_string(), Value::from("sphere"));
+                }
+                serde_json::from_value(value).map(|shape| NodeKind::Shape(Arc::new(shape)))
+            }
+        }
+        .map_err(D::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Scene {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct File {
+            #[serde(default)]
+            camera: Camera,
+            #[serde(default)]
+            background: Option<Background>,
+            #[serde(default)]
+            medium: Option<Arc<Medium>>,
+            #[serde(default)]
+            lights: Vec<Light>,
+            objects: Vec<Node>,
+        }
+        let value = Value::deserialize(deserializer)?;
+        if value.is_array() {
+            serde_json::from_value(value).map(Scene::new)
+        } else {
+            serde_json::from_value(value).map(|file: File| Scene {
+                camera: file.camera,
+                background: file.background,
+                medium: file.medium,
+                lights: file.lights,
+                nodes: file.objects,
+            })
+        }
+        .map_err(D::Error::custom)
+    }
+}
+
+// `own` followed by `parent`
+fn compose(own: Option<&Transform>, parent: Option<&Transform>) -> Option<Transform> {
+    match (own, parent) {
+        (Some(own), Some(parent)) => Some(own.then(parent)),
+        (Some(transform), None) | (None, Some(transform)) => Some(*transform),
+        (None, None) => None,
+    }
+}
+
+// What a node inherits from its ancestors
+#[derive(Clone, Default)]
+struct Context {
+    // from the parent to the scene
+    motion: Vec<Motion>,
+    texture: Option<Arc<Texture>>,
+    material: Option<Arc<Material>>,
+    bump: Option<Arc<Bump>>,
+    medium: Option<Arc<Medium>>,
+}
+
+impl Context {
+    // The motion of children moved by `motion` in this context
+    fn moved(&self, motion: Option<Motion>) -> Vec<Motion> {
+        match (motion, self.motion.first()) {
+            (None, _) => self.motion.clone(),
+            // still transforms are combined, to be applied at once
+            (Some(Motion::Still(own)), Some(Motion::Still(parent))) => {
+                let mut res = self.motion.clone();
+                res[0] = Motion::Still(Box::new(own.then(parent)));
+                res
+            }
+            (Some(motion), _) => {
+                let mut res = vec![motion];
+                res.extend(self.motion.iter().cloned());
+                res
+            }
+        }
+    }
+}
+
+impl Node {
+    fn new(kind: NodeKind) -> Self {
+        Node {
+            name: None,
+            transform: None,
+            end_transform: None,
+            color: None,
+            texture: None,
+            material: None,
+            bump: None,
+            medium: None,
+            hidden: false,
+            kind,
+        }
+    }
+
+    pub fn shape(shape: Shape) -> Self {
+        Node::new(NodeKind::Shape(Arc::new(shape)))
+    }
+
+    pub fn group(children: Vec<Node>) -> Self {
+        Node::new(NodeKind::Group(children))
+    }
+
+    pub fn instance(of: &str) -> Self {
+        Node::new(NodeKind::Instance(of.to_string()))
+    }
+
+    pub fn instances(node: Node, transforms: Vec<Transform>) -> Self {
+        Node::new(NodeKind::Instances(Box::new(node), transforms))
+    }
+
+    pub fn named(self, name: &str) -> Self {
+        Node {
+            name: Some(name.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Transform) -> Self {
+        Node {
+            transform: Some(Animated::Constant(transform)),
+            ..self
+        }
+    }
+
+    pub fn animated(self, transform: Animation<Transform>) -> Self {
+        Node {
+            transform: Some(Animated::Keyframes(transform)),
+            ..self
+        }
+    }
+
+    pub fn moving_to(self, end_transform: Transform) -> Self {
+        Node {
+            end_transform: Some(end_transform),
+            ..self
+        }
+    }
+
+    pub fn with_color(self, color: Color) -> Self {
+        Node {
+            color: Some(color),
+            ..self
+        }
+    }
+
+    pub fn with_texture(self, texture: Texture) -> Self {
+        Node {
+            texture: Some(Arc::new(texture)),
+            ..self
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Node {
+            material: Some(Arc::new(material)),
+            ..self
+        }
+    }
+
+    pub fn with_bump(self, bump: Bump) -> Self {
+        Node {
+            bump: Some(Arc::new(bump)),
+            ..self
+        }
+    }
+
+    pub fn with_medium(self, medium: Medium) -> Self {
+        Node {
+            medium: Some(Arc::new(medium)),
+            ..self
+        }
+    }
+
+    pub fn hidden(self) -> Self {
+        Node {
+            hidden: true,
+            ..self
+        }
+    }
+
+    // Its own transform, which may change over time
+    pub fn motion(&self) -> Result<Option<Motion>, String> {
+        let motion = match (&self.transform, &self.end_transform) {
+            (Some(Animated::Keyframes(_)), Some(_)) => {
+                return Err(match &self.name {
+                    Some(name) => format!("{} has both keyframes and an end_transform", name),
+                    None => "keyframes and an end_transform cannot be combined".to_string(),
+                })
+            }
+            (Some(transform), None) => Motion::try_from(transform).map(Some),
+            (start, Some(end)) => {
+                let start = match start {
+                    Some(Animated::Constant(transform)) => *transform,
+                    _ => Transform::identity(),
+                };
+                Motion::new(&start, end).map(Some)
+            }
+            (None, None) => Ok(None),
+        };
+        motion.map_err(|e| match &self.name {
+            Some(name) => format!("{}: {}", name, e),
+            None => e,
+        })
+    }
+
+    // Children in the graph, references by name are not followed
+    pub fn children(&self) -> Vec<&Node> {
+        match &self.kind {
+            NodeKind::Group(children) => children.iter().collect(),
+            NodeKind::Instances(node, _) => vec![node],
+            NodeKind::Shape(_) | NodeKind::Instance(_) => Vec::new(),
+        }
+    }
+
+    // Calls `f` on this node and its descendants, depth first, with the
+    // transform of their parent at this time
+    pub fn walk<'a, F: FnMut(&'a Node, Option<&Transform>)>(
+        &'a self,
+        time: f64,
+        parent: Option<&Transform>,
+        f: &mut F,
+    ) -> Result<(), String> {
+        f(self, parent);
+        let own = self.motion()?.map(|motion| motion.at(time));
+        let transform = compose(own.as_ref(), parent);
+        for child in self.children() {
+            child.walk(time, transform.as_ref(), f)?;
+        }
+        Ok(())
+    }
+
+    // Calls `f` on this node and its descendants, depth first
+    fn visit<'a, F: FnMut(&'a Node)>(&'a self, f: &mut F) {
+        f(self);
+        for child in self.children() {
+            child.visit(f);
+        }
+    }
+
+    fn objects(
+        &self,
+        names: &HashMap<&str, &Node>,
+        context: &Context,
+        path: &mut Vec<String>,
+        res: &mut Vec<Object>,
+    ) -> Result<(), String> {
+        let own = match (&self.texture, self.color) {
+            (Some(texture), _) => Some(texture.clone()),
+            (None, Some(color)) => Some(Arc::new(Texture::from(color))),
+            (None, None) => None,
+        };
+        let context = Context {
+            motion: context.moved(self.motion()?),
+            texture: own.or_else(|| context.texture.clone()),
+            material: self.material.clone().or_else(|| context.material.clone()),
+            bump: self.bump.clone().or_else(|| context.bump.clone()),
+            medium: self.medium.clone().or_else(|| context.medium.clone()),
+        };
+        match &self.kind {
+            NodeKind::Shape(shape) => {
+                // objects filled with a medium are invisible unless they have
+                // a material
+                let medium = context.medium;
+                let material = context.material.unwrap_or_else(|| {
+                    Arc::new(match &medium {
+                        Some(_) => Material::Boundary,
+                        None => Material::Diffuse,
+                    })
+                });
+                // only diffuse objects need a color, others are white by default
+                let texture = match (context.texture, &*material) {
+                    (Some(texture), _) => texture,
+                    // meshes may have their own colors
+                    (None, _) if shape.has_colors() => Arc::new(Texture::VertexColors),
+                    (None, Material::Diffuse) => {
+                        return Err(match &self.name {
+                            Some(name) => format!("{} has no color", name),
+                            None => "object without a color".to_string(),
+                        })
+                    }
+                    (None, _) => Arc::new(Texture::from(WHITE)),
+                };
+                res.push(
+                    Object::animated(shape.clone(), texture, context.motion)
+                        .with_material(material)
+                        .with_bump(context.bump)
+                        .with_medium(medium),
+                );
+            }
+            NodeKind::Group(children) => {
+                for child in children.iter().filter(|child| !child.hidden) {
+                    child.objects(names, &context, path, res)?;
+                }
+            }
+            NodeKind::Instance(of) => {
+                if path.contains(of) {
+                    return Err(format!("{} contains an instance of itself", of));
+                }
+                let node = names
+                    .get(of.as_str())
+                    .ok_or_else(|| format!("no node named {}", of))?;
+                path.push(of.clone());
+                node.objects(names, &context, path, res)?;
+                path.pop();
+            }
+            NodeKind::Instances(node, transforms) => {
+                for instance in transforms {
+                    let context = Context {
+                        motion: context.moved(Some(Motion::Still(Box::new(*instance)))),
+                        ..context.clone()
+                    };
+                    node.objects(names, &context, path, res)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Scene {
+    pub fn new(nodes: Vec<Node>) -> Self {
+        Scene {
+            camera: Camera::default(),
+            background: None,
+            medium: None,
+            lights: Vec::new(),
+            nodes,
+        }
+    }
+
+    // The nodes of a .gltf or .glb file, with its camera and lights
+    pub fn load_gltf(file_name: &str) -> Result<Self, String> {
+        gltf::load(file_name)
+    }
+
+    pub fn add(&mut self, node: Node) {
+        self.nodes.push(node);
+    }
+
+    pub fn walk<'a, F: FnMut(&'a Node, Option<&Transform>)>(
+        &'a self,
+        time: f64,
+        f: &mut F,
+    ) -> Result<(), String> {
+        for node in &self.nodes {
+            node.walk(time, None, f)?;
+        }
+        Ok(())
+    }
+
+    // Named nodes, the first one wins when names are repeated
+    fn names(&self) -> HashMap<&str, &Node> {
+        let mut names = HashMap::new();
+        for node in &self.nodes {
+            node.visit(&mut |node| {
+                if let Some(name) = &node.name {
+                    names.entry(name.as_str()).or_insert(node);
+                }
+            });
+        }
+        names
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Node> {
+        self.names().get(name).copied()
+    }
+
+    // The objects to render, at all times
+    pub fn objects(&self) -> Result<Vec<Object>, String> {
+        let names = self.names();
+        let mut res = Vec::new();
+        for node in self.nodes.iter().filter(|node| !node.hidden) {
+            node.objects(&names, &Context::default(), &mut Vec::new(), &mut res)?;
+        }
+        Ok(res)
+    }
+
+    // Bounds of the node with this name, where it is in the scene at this time
+    pub fn bounds(&self, name: &str, time: f64) -> Result<Aabb, String> {
+        let names = self.names();
+        let mut found = None;
+        self.walk(time, &mut |node, parent| {
+            if found.is_none() && node.name.as_deref() == Some(name) {
+                found = Some((node, parent.copied()));
+            }
+        })?;
+        let (node, parent) = found.ok_or_else(|| format!("no node named {}", name))?;
+        let context = Context {
+            motion: parent
+                .into_iter()
+                .map(|parent| Motion::Still(Box::new(parent)))
+                .collect(),
+            // colors do not matter here
+            texture: Some(Arc::new(Texture::from(BLACK))),
+            material: None,
+            bump: None,
+            medium: None,
+        };
+        let mut objects = Vec::new();
+        node.objects(&names, &context, &mut Vec::new(), &mut objects)?;
+        objects
+            .iter()
+            .map(|object| object.bounds_at(time))
+            .try_fold(None, |res: Option<Aabb>, bounds| {
+                let bounds = bounds?;
+                Some(Some(res.map_or(bounds, |res| res.union(&bounds))))
+            })
+            .flatten()
+            .ok_or_else(|| format!("{} is unbounded", name))
+    }
+}
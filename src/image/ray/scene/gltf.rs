@@ -0,0 +1,1083 @@
+use super::super::animation::Animated;
+use super::super::bump::Bump;
+use super::super::color::{Color, WHITE};
+use super::super::light::Light;
+use super::super::material::principled::Principled;
+use super::super::material::Material;
+use super::super::object::mesh::Mesh;
+use super::super::object::Shape;
+use super::super::texture::bitmap::{Bitmap, Wrap};
+use super::super::texture::{ImageTexture, Texture};
+use super::super::transform::{Matrix, Transform};
+use super::super::vec3::{Location, Vector};
+use super::{Camera, Node, NodeKind, Scene};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+// The parts of a glTF 2.0 file that can be rendered, see
+// https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    #[serde(default)]
+    scene: Option<usize>,
+    #[serde(default)]
+    scenes: Vec<SceneData>,
+    #[serde(default)]
+    nodes: Vec<NodeData>,
+    #[serde(default)]
+    meshes: Vec<MeshData>,
+    #[serde(default)]
+    accessors: Vec<Accessor>,
+    #[serde(default)]
+    buffer_views: Vec<BufferView>,
+    #[serde(default)]
+    buffers: Vec<Buffer>,
+    #[serde(default)]
+    materials: Vec<MaterialData>,
+    #[serde(default)]
+    textures: Vec<TextureData>,
+    #[serde(default)]
+    images: Vec<ImageData>,
+    #[serde(default)]
+    samplers: Vec<Sampler>,
+    #[serde(default)]
+    cameras: Vec<CameraData>,
+    #[serde(default)]
+    extensions: DocumentExtensions,
+}
+
+#[derive(Deserialize)]
+struct SceneData {
+    #[serde(default)]
+    nodes: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct NodeData {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    children: Vec<usize>,
+    // columns of the matrix, instead of the translation, rotation and scale
+    #[serde(default)]
+    matrix: Option<[f64; 16]>,
+    #[serde(default)]
+    translation: Option<[f64; 3]>,
+    // quaternion, as x, y, z and w
+    #[serde(default)]
+    rotation: Option<[f64; 4]>,
+    #[serde(default)]
+    scale: Option<[f64; 3]>,
+    #[serde(default)]
+    mesh: Option<usize>,
+    #[serde(default)]
+    camera: Option<usize>,
+    #[serde(default)]
+    extensions: NodeExtensions,
+}
+
+#[derive(Deserialize)]
+struct MeshData {
+    #[serde(default)]
+    primitives: Vec<Primitive>,
+}
+
+#[derive(Deserialize)]
+struct Primitive {
+    // accessors of the vertices, by attribute
+    attributes: HashMap<String, usize>,
+    #[serde(default)]
+    indices: Option<usize>,
+    #[serde(default)]
+    material: Option<usize>,
+    #[serde(default = "triangles")]
+    mode: u32,
+}
+
+// Values of a buffer view, each one made of `type` components
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Accessor {
+    #[serde(default)]
+    buffer_view: Option<usize>,
+    #[serde(default)]
+    byte_offset: usize,
+    component_type: u32,
+    // integers standing for numbers in [0, 1] or [-1, 1]
+    #[serde(default)]
+    normalized: bool,
+    count: usize,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    sparse: Option<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_length: usize,
+    #[serde(default)]
+    byte_stride: Option<usize>,
+}
+
+// The binary chunk of glb files when there is no uri
+#[derive(Deserialize)]
+struct Buffer {
+    #[serde(default)]
+    uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MaterialData {
+    #[serde(default)]
+    pbr_metallic_roughness: Pbr,
+    #[serde(default)]
+    normal_texture: Option<TextureInfo>,
+    #[serde(default)]
+    emissive_texture: Option<TextureInfo>,
+    #[serde(default)]
+    emissive_factor: [f64; 3],
+    #[serde(default)]
+    extensions: MaterialExtensions,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Pbr {
+    // the alpha is ignored
+    #[serde(default = "opaque_white")]
+    base_color_factor: [f64; 4],
+    #[serde(default)]
+    base_color_texture: Option<TextureInfo>,
+    #[serde(default = "one")]
+    metallic_factor: f64,
+    #[serde(default = "one")]
+    roughness_factor: f64,
+    #[serde(default)]
+    metallic_roughness_texture: Option<TextureInfo>,
+}
+
+#[derive(Deserialize)]
+struct TextureInfo {
+    index: usize,
+    // of normal textures
+    #[serde(default = "one")]
+    scale: f64,
+}
+
+#[derive(Deserialize)]
+struct TextureData {
+    #[serde(default)]
+    source: Option<usize>,
+    #[serde(default)]
+    sampler: Option<usize>,
+}
+
+// Either a file or a buffer view
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageData {
+    #[serde(default)]
+    uri: Option<String>,
+    #[serde(default)]
+    buffer_view: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Sampler {
+    #[serde(default = "repeat")]
+    wrap_s: u32,
+}
+
+#[derive(Deserialize)]
+struct CameraData {
+    #[serde(default)]
+    perspective: Option<Perspective>,
+}
+
+// The aspect ratio of the camera is the one of the image
+#[derive(Deserialize)]
+struct Perspective {
+    // vertical field of view, in radians
+    yfov: f64,
+}
+
+#[derive(Deserialize, Default)]
+struct DocumentExtensions {
+    #[serde(rename = "KHR_lights_punctual", default)]
+    lights: Option<Lights>,
+}
+
+#[derive(Deserialize)]
+struct Lights {
+    lights: Vec<LightData>,
+}
+
+#[derive(Deserialize)]
+struct LightData {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default = "white")]
+    color: [f64; 3],
+    #[serde(default = "one")]
+    intensity: f64,
+    #[serde(default)]
+    spot: Option<Spot>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Spot {
+    // in radians
+    #[serde(default)]
+    inner_cone_angle: f64,
+    #[serde(default = "quarter_turn")]
+    outer_cone_angle: f64,
+}
+
+#[derive(Deserialize, Default)]
+struct NodeExtensions {
+    #[serde(rename = "KHR_lights_punctual", default)]
+    light: Option<LightIndex>,
+}
+
+#[derive(Deserialize)]
+struct LightIndex {
+    light: usize,
+}
+
+#[derive(Deserialize, Default)]
+struct MaterialExtensions {
+    #[serde(rename = "KHR_materials_emissive_strength", default)]
+    emissive_strength: Option<EmissiveStrength>,
+    #[serde(rename = "KHR_materials_transmission", default)]
+    transmission: Option<Transmission>,
+    #[serde(rename = "KHR_materials_ior", default)]
+    ior: Option<Ior>,
+    #[serde(rename = "KHR_materials_clearcoat", default)]
+    clearcoat: Option<Clearcoat>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EmissiveStrength {
+    #[serde(default = "one")]
+    emissive_strength: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Transmission {
+    #[serde(default)]
+    transmission_factor: f64,
+}
+
+#[derive(Deserialize)]
+struct Ior {
+    #[serde(default = "glass")]
+    ior: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Clearcoat {
+    #[serde(default)]
+    clearcoat_factor: f64,
+    #[serde(default)]
+    clearcoat_roughness_factor: f64,
+}
+
+// How the primitives with a material look
+struct Look {
+    texture: Arc<Texture>,
+    material: Arc<Material>,
+    bump: Option<Arc<Bump>>,
+    // of the base color, multiplying the colors of the vertices
+    factor: Color,
+    // the base color has a texture, taking precedence over vertex colors
+    textured: bool,
+}
+
+struct Loader<'a> {
+    document: Document,
+    directory: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    // per mesh, its primitives with their materials
+    meshes: Vec<Vec<(Arc<Shape>, Option<usize>)>>,
+    looks: Vec<Look>,
+    // of primitives without a material
+    default_look: Look,
+    camera: Option<Camera>,
+    lights: Vec<Light>,
+}
+
+// glTF primitive modes
+const TRIANGLES: u32 = 4;
+const TRIANGLE_STRIP: u32 = 5;
+const TRIANGLE_FAN: u32 = 6;
+
+fn triangles() -> u32 {
+    TRIANGLES
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn white() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn opaque_white() -> [f64; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+fn repeat() -> u32 {
+    10497
+}
+
+fn glass() -> f64 {
+    1.5
+}
+
+fn quarter_turn() -> f64 {
+    std::f64::consts::FRAC_PI_4
+}
+
+impl Default for Pbr {
+    fn default() -> Self {
+        Pbr {
+            base_color_factor: opaque_white(),
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+        }
+    }
+}
+
+fn color([red, green, blue]: [f64; 3]) -> Color {
+    Color::new(red, green, blue)
+}
+
+// Translation, rotation and scale, in this order of priority
+fn trs_matrix(node: &NodeData) -> Matrix {
+    let [tx, ty, tz] = node.translation.unwrap_or([0.0; 3]);
+    let [x, y, z, w] = node.rotation.unwrap_or([0.0, 0.0, 0.0, 1.0]);
+    let [sx, sy, sz] = node.scale.unwrap_or([1.0; 3]);
+    let norm = (x * x + y * y + z * z + w * w).sqrt().max(1e-12);
+    let (x, y, z, w) = (x / norm, y / norm, z / norm, w / norm);
+    [
+        [
+            (1.0 - 2.0 * (y * y + z * z)) * sx,
+            2.0 * (x * y - w * z) * sy,
+            2.0 * (x * z + w * y) * sz,
+            tx,
+        ],
+        [
+            2.0 * (x * y + w * z) * sx,
+            (1.0 - 2.0 * (x * x + z * z)) * sy,
+            2.0 * (y * z - w * x) * sz,
+            ty,
+        ],
+        [
+            2.0 * (x * z - w * y) * sx,
+            2.0 * (y * z + w * x) * sy,
+            (1.0 - 2.0 * (x * x + y * y)) * sz,
+            tz,
+        ],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+fn base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut res = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0_u32, 0);
+    for c in text
+        .bytes()
+        .filter(|c| *c != b'=' && !c.is_ascii_whitespace())
+    {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err("invalid base64 data".to_string()),
+        };
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            res.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+    Ok(res)
+}
+
+// Uris escape some characters (e.g. spaces as %20)
+fn unescaped(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                res.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                res.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&res).into_owned()
+}
+
+// The json and binary chunks of a glb file
+fn split_glb(data: &[u8]) -> Result<(&[u8], Option<&[u8]>), String> {
+    let word = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+            .ok_or("truncated glb file")
+    };
+    if word(4)? != 2 {
+        return Err("only glTF 2.0 is supported".to_string());
+    }
+    let mut json = None;
+    let mut binary = None;
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let (length, kind) = (word(offset)?, word(offset + 4)?);
+        let chunk = data
+            .get(offset + 8..offset + 8 + length)
+            .ok_or("truncated glb file")?;
+        match kind {
+            0x4e4f_534a => json = json.or(Some(chunk)),
+            0x004e_4942 => binary = binary.or(Some(chunk)),
+            _ => (),
+        }
+        offset += 8 + length;
+    }
+    Ok((json.ok_or("no json chunk in glb file")?, binary))
+}
+
+impl Loader<'_> {
+    // Content of a data uri or of a file next to the glTF file
+    fn uri_data(&self, uri: &str) -> Result<Vec<u8>, String> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let comma = data.find(',').ok_or("invalid data uri")?;
+            if !data[..comma].ends_with(";base64") {
+                return Err("data uris must be in base64".to_string());
+            }
+            return base64(&data[comma + 1..]);
+        }
+        let path = self.directory.join(unescaped(uri));
+        std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Values of an accessor, one after the other, and how many make an item
+    fn accessor(&self, index: usize) -> Result<(usize, Vec<f64>), String> {
+        let accessor = self
+            .document
+            .accessors
+            .get(index)
+            .ok_or_else(|| format!("no accessor {}", index))?;
+        if accessor.sparse.is_some() {
+            return Err("sparse accessors are not supported".to_string());
+        }
+        let components: usize = match accessor.kind.as_str() {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" | "MAT2" => 4,
+            "MAT3" => 9,
+            "MAT4" => 16,
+            kind => return Err(format!("unknown accessor type {}", kind)),
+        };
+        let size = match accessor.component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            kind => return Err(format!("unknown component type {}", kind)),
+        };
+        // the counts come from the file, and may not fit
+        let too_big = || format!("accessor {} too big", index);
+        let count = components.checked_mul(accessor.count).ok_or_else(too_big)?;
+        let view = match accessor.buffer_view {
+            Some(view) => self
+                .document
+                .buffer_views
+                .get(view)
+                .ok_or_else(|| format!("no buffer view {}", view))?,
+            // all zeros
+            None => {
+                let mut values = Vec::new();
+                values.try_reserve_exact(count).map_err(|_| too_big())?;
+                values.resize(count, 0.0);
+                return Ok((components, values));
+            }
+        };
+        let data = self
+            .buffers
+            .get(view.buffer)
+            .and_then(|buffer| {
+                buffer.get(view.byte_offset..view.byte_offset.checked_add(view.byte_length)?)
+            })
+            .ok_or_else(|| format!("buffer view of accessor {} out of its buffer", index))?;
+        let stride = view.byte_stride.unwrap_or(components * size);
+        if stride < components * size {
+            return Err(format!(
+                "buffer view of accessor {} has items overlapping",
+                index
+            ));
+        }
+        // the items must be in the buffer view before they are read
+        if accessor.count > 0 {
+            let end = (accessor.count - 1)
+                .checked_mul(stride)
+                .and_then(|offset| offset.checked_add(accessor.byte_offset))
+                .and_then(|offset| offset.checked_add(components * size));
+            if end.is_none_or(|end| end > data.len()) {
+                return Err(format!("accessor {} out of its buffer view", index));
+            }
+        }
+        let mut values = Vec::with_capacity(count);
+        for i in 0..accessor.count {
+            for k in 0..components {
+                let offset = accessor.byte_offset + i * stride + k * size;
+                let b = data
+                    .get(offset..offset + size)
+                    .ok_or_else(|| format!("accessor {} out of its buffer view", index))?;
+                let (value, max) = match accessor.component_type {
+                    5120 => (b[0] as i8 as f64, 127.0),
+                    5121 => (b[0] as f64, 255.0),
+                    5122 => (i16::from_le_bytes([b[0], b[1]]) as f64, 32767.0),
+                    5123 => (u16::from_le_bytes([b[0], b[1]]) as f64, 65535.0),
+                    5125 => (
+                        u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                        4294967295.0,
+                    ),
+                    _ => (f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.0),
+                };
+                values.push(if accessor.normalized {
+                    (value / max).max(-1.0)
+                } else {
+                    value
+                });
+            }
+        }
+        Ok((components, values))
+    }
+
+    // The image of a texture, multiplied by `tint`
+    fn image_texture(&self, info: &TextureInfo, tint: Color) -> Result<ImageTexture, String> {
+        let texture = self
+            .document
+            .textures
+            .get(info.index)
+            .ok_or_else(|| format!("no texture {}", info.index))?;
+        let image = texture
+            .source
+            .and_then(|source| self.document.images.get(source))
+            .ok_or_else(|| format!("texture {} has no image", info.index))?;
+        let data = match (&image.uri, image.buffer_view) {
+            (Some(uri), _) => self.uri_data(uri)?,
+            (None, Some(view)) => {
+                let view = self
+                    .document
+                    .buffer_views
+                    .get(view)
+                    .ok_or_else(|| format!("no buffer view {}", view))?;
+                self.buffers
+                    .get(view.buffer)
+                    .and_then(|buffer| {
+                        buffer
+                            .get(view.byte_offset..view.byte_offset.checked_add(view.byte_length)?)
+                    })
+                    .ok_or("image out of its buffer")?
+                    .to_vec()
+            }
+            (None, None) => return Err(format!("texture {} has no image", info.index)),
+        };
+        if !data.starts_with(b"\x89PNG") {
+            return Err(format!(
+                "texture {}: only png images are supported",
+                info.index
+            ));
+        }
+        let bitmap = Bitmap::decode(data.as_slice())
+            .map_err(|e| format!("texture {}: {}", info.index, e))?;
+        let wrap = match texture
+            .sampler
+            .and_then(|sampler| self.document.samplers.get(sampler))
+            .map(|sampler| sampler.wrap_s)
+        {
+            Some(33071) => Wrap::Clamp,
+            Some(33648) => Wrap::Mirror,
+            _ => Wrap::Repeat,
+        };
+        Ok(ImageTexture::new(bitmap.tinted(tint), wrap))
+    }
+
+    fn look(&self, material: &MaterialData) -> Result<Look, String> {
+        let pbr = &material.pbr_metallic_roughness;
+        let [red, green, blue, _] = pbr.base_color_factor;
+        let factor = Color::new(red, green, blue);
+        let texture = match &pbr.base_color_texture {
+            Some(info) => Texture::Image(self.image_texture(info, factor)?),
+            None => Texture::from(factor),
+        };
+        let extensions = &material.extensions;
+        let mut principled = Principled::default()
+            .with_metallic(pbr.metallic_factor)
+            .with_roughness(pbr.roughness_factor)
+            .with_emission(
+                color(material.emissive_factor),
+                extensions
+                    .emissive_strength
+                    .as_ref()
+                    .map_or(1.0, |strength| strength.emissive_strength),
+            );
+        if let Some(info) = &pbr.metallic_roughness_texture {
+            principled = principled
+                .with_metallic_roughness(Texture::Image(self.image_texture(info, WHITE)?));
+        }
+        if let Some(info) = &material.emissive_texture {
+            principled =
+                principled.with_emission_texture(Texture::Image(self.image_texture(info, WHITE)?));
+        }
+        if extensions.transmission.is_some() || extensions.ior.is_some() {
+            principled = principled.with_transmission(
+                extensions
+                    .transmission
+                    .as_ref()
+                    .map_or(0.0, |transmission| transmission.transmission_factor),
+                extensions.ior.as_ref().map_or(glass(), |ior| ior.ior),
+            );
+        }
+        if let Some(clearcoat) = &extensions.clearcoat {
+            principled = principled.with_clearcoat(
+                clearcoat.clearcoat_factor,
+                clearcoat.clearcoat_roughness_factor,
+            );
+        }
+        let bump = match &material.normal_texture {
+            Some(info) => Some(Arc::new(Bump::normal_map(
+                self.image_texture(info, WHITE)?,
+                info.scale,
+            ))),
+            None => None,
+        };
+        Ok(Look {
+            texture: Arc::new(texture),
+            material: Arc::new(Material::Principled(principled)),
+            bump,
+            factor,
+            textured: pbr.base_color_texture.is_some(),
+        })
+    }
+
+    // None for primitives without triangles (points or lines)
+    fn primitive(&self, primitive: &Primitive) -> Result<Option<Mesh>, String> {
+        // with one of these numbers of components, the number of items being
+        // checked against the vertices by the mesh
+        let attribute = |name: &str, components: &[usize]| {
+            let (found, values) = match primitive.attributes.get(name) {
+                Some(index) => self.accessor(*index)?,
+                None => return Ok(None),
+            };
+            if components.contains(&found) {
+                Ok(Some((found, values)))
+            } else {
+                Err(format!("{} with {} components", name, found))
+            }
+        };
+        let (_, positions) = attribute("POSITION", &[3])?.ok_or("primitive without positions")?;
+        let vertices: Vec<Location> = positions
+            .chunks_exact(3)
+            .map(|p| Location::new(p[0], p[1], p[2]))
+            .collect();
+        let indices: Vec<u32> = match primitive.indices {
+            Some(index) => self.accessor(index)?.1.iter().map(|i| *i as u32).collect(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+        let triangles: Vec<[u32; 3]> = match primitive.mode {
+            TRIANGLES => indices
+                .chunks_exact(3)
+                .map(|t| [t[0], t[1], t[2]])
+                .collect(),
+            // every other triangle is flipped to keep the same side outwards
+            TRIANGLE_STRIP => indices
+                .windows(3)
+                .enumerate()
+                .map(|(i, t)| {
+                    if i % 2 == 0 {
+                        [t[0], t[1], t[2]]
+                    } else {
+                        [t[1], t[0], t[2]]
+                    }
+                })
+                .collect(),
+            TRIANGLE_FAN => (1..indices.len().saturating_sub(1))
+                .map(|i| [indices[0], indices[i], indices[i + 1]])
+                .collect(),
+            _ => Vec::new(),
+        };
+        if triangles.is_empty() {
+            return Ok(None);
+        }
+        let normals = attribute("NORMAL", &[3])?.map_or(Vec::new(), |(_, normals)| {
+            normals
+                .chunks_exact(3)
+                .map(|n| Vector::new(n[0], n[1], n[2]))
+                .collect()
+        });
+        // v goes down the images in glTF
+        let uvs = attribute("TEXCOORD_0", &[2])?.map_or(Vec::new(), |(_, uvs)| {
+            uvs.chunks_exact(2).map(|uv| (uv[0], 1.0 - uv[1])).collect()
+        });
+        let factor = match primitive.material {
+            Some(material) => self.looks[material].factor,
+            None => self.default_look.factor,
+        };
+        let colors = attribute("COLOR_0", &[3, 4])?.map_or(Vec::new(), |(components, colors)| {
+            colors
+                .chunks_exact(components)
+                .map(|c| factor * Color::new(c[0], c[1], c[2]))
+                .collect()
+        });
+        Ok(Some(
+            Mesh::new(vertices, triangles)?
+                .with_normals(normals)?
+                .with_uvs(uvs)?
+                .with_colors(colors)?,
+        ))
+    }
+
+    // The node with its descendants, None if it is scaled down to nothing
+    fn node(
+        &mut self,
+        index: usize,
+        parent: &Transform,
+        path: &mut Vec<usize>,
+    ) -> Result<Option<Node>, String> {
+        if path.contains(&index) {
+            return Err(format!("node {} is its own descendant", index));
+        }
+        let data = self
+            .document
+            .nodes
+            .get(index)
+            .ok_or_else(|| format!("no node {}", index))?;
+        let matrix = match data.matrix {
+            Some(m) => [
+                [m[0], m[4], m[8], m[12]],
+                [m[1], m[5], m[9], m[13]],
+                [m[2], m[6], m[10], m[14]],
+                [m[3], m[7], m[11], m[15]],
+            ],
+            None => trs_matrix(data),
+        };
+        let own = match Transform::from_matrix(matrix) {
+            Some(transform) => transform,
+            None => return Ok(None),
+        };
+        let transform = own.then(parent);
+        let location = transform.location(Location::new(0.0, 0.0, 0.0));
+        // cameras and lights look towards -z
+        let direction = transform
+            .direction(Vector::new(0.0, 0.0, -1.0))
+            .as_unit_vector();
+        let perspective = data
+            .camera
+            .and_then(|camera| self.document.cameras.get(camera))
+            .and_then(|camera| camera.perspective.as_ref());
+        if let (true, Some(perspective)) = (self.camera.is_none(), perspective) {
+            self.camera = Some(Camera {
+                location: Some(Animated::Constant(location)),
+                direction: Some(Animated::Constant(direction)),
+                target: None,
+                fov: None,
+                vertical_fov: Some(Animated::Constant(perspective.yfov.to_degrees())),
+            });
+        }
+        if let Some(light) = &data.extensions.light {
+            let light = self
+                .document
+                .extensions
+                .lights
+                .as_ref()
+                .and_then(|lights| lights.lights.get(light.light))
+                .ok_or_else(|| format!("no light {}", light.light))?;
+            let (color, intensity) = (color(light.color), light.intensity);
+            self.lights.push(match (light.kind.as_str(), &light.spot) {
+                ("directional", _) => Light::Directional {
+                    direction,
+                    color,
+                    intensity,
+                },
+                ("spot", Some(spot)) => Light::Spot {
+                    location,
+                    direction,
+                    color,
+                    intensity,
+                    inner_angle: spot.inner_cone_angle.to_degrees(),
+                    outer_angle: spot.outer_cone_angle.to_degrees(),
+                },
+                ("spot", None) => Light::Spot {
+                    location,
+                    direction,
+                    color,
+                    intensity,
+                    inner_angle: 0.0,
+                    outer_angle: quarter_turn().to_degrees(),
+                },
+                _ => Light::Point {
+                    location,
+                    color,
+                    intensity,
+                },
+            });
+        }
+        let mut children = Vec::new();
+        if let Some(mesh) = data.mesh {
+            let primitives = self
+                .meshes
+                .get(mesh)
+                .ok_or_else(|| format!("no mesh {}", mesh))?;
+            for (shape, material) in primitives {
+                let look = match material {
+                    Some(material) => &self.looks[*material],
+                    None => &self.default_look,
+                };
+                let texture = if shape.has_colors() && !look.textured {
+                    Arc::new(Texture::VertexColors)
+                } else {
+                    look.texture.clone()
+                };
+                children.push(Node {
+                    texture: Some(texture),
+                    material: Some(look.material.clone()),
+                    bump: look.bump.clone(),
+                    ..Node::new(NodeKind::Shape(shape.clone()))
+                });
+            }
+        }
+        let name = data.name.clone();
+        path.push(index);
+        for child in data.children.clone() {
+            if let Some(node) = self.node(child, &transform, path)? {
+                children.push(node);
+            }
+        }
+        path.pop();
+        let node = Node::group(children).with_transform(own);
+        Ok(Some(match name {
+            Some(name) => node.named(&name),
+            None => node,
+        }))
+    }
+}
+
+// The nodes of the default scene of a .gltf or .glb file, its first
+// perspective camera and its lights
+pub fn load(file_name: &str) -> Result<Scene, String> {
+    let data = std::fs::read(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+    let directory = Path::new(file_name)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    read(&data, directory).map_err(|e| format!("{}: {}", file_name, e))
+}
+
+fn read(data: &[u8], directory: &Path) -> Result<Scene, String> {
+    let (json, binary) = if data.starts_with(b"glTF") {
+        split_glb(data)?
+    } else {
+        (data, None)
+    };
+    let document: Document = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+    let mut loader = Loader {
+        document,
+        directory,
+        buffers: Vec::new(),
+        meshes: Vec::new(),
+        looks: Vec::new(),
+        default_look: Look {
+            texture: Arc::new(Texture::from(WHITE)),
+            // the default material of glTF
+            material: Arc::new(Material::Principled(
+                Principled::default().with_metallic(1.0).with_roughness(1.0),
+            )),
+            bump: None,
+            factor: WHITE,
+            textured: false,
+        },
+        camera: None,
+        lights: Vec::new(),
+    };
+    for (i, buffer) in loader.document.buffers.iter().enumerate() {
+        let data = match (&buffer.uri, binary) {
+            (Some(uri), _) => loader.uri_data(uri)?,
+            (None, Some(binary)) if i == 0 => binary.to_vec(),
+            (None, _) => return Err(format!("buffer {} has no data", i)),
+        };
+        loader.buffers.push(data);
+    }
+    loader.looks = loader
+        .document
+        .materials
+        .iter()
+        .map(|material| loader.look(material))
+        .collect::<Result<_, _>>()?;
+    for mesh in &loader.document.meshes {
+        let mut primitives = Vec::new();
+        for primitive in &mesh.primitives {
+            if let Some(material) = primitive.material {
+                if material >= loader.looks.len() {
+                    return Err(format!("no material {}", material));
+                }
+            }
+            if let Some(shape) = loader.primitive(primitive)? {
+                primitives.push((Arc::new(Shape::Mesh(shape)), primitive.material));
+            }
+        }
+        loader.meshes.push(primitives);
+    }
+    let roots = match loader
+        .document
+        .scenes
+        .get(loader.document.scene.unwrap_or(0))
+    {
+        Some(scene) => scene.nodes.clone(),
+        // the nodes that are not children of others
+        None => (0..loader.document.nodes.len())
+            .filter(|i| {
+                !loader
+                    .document
+                    .nodes
+                    .iter()
+                    .any(|node| node.children.contains(i))
+            })
+            .collect(),
+    };
+    let mut nodes = Vec::new();
+    for root in roots {
+        if let Some(node) = loader.node(root, &Transform::identity(), &mut Vec::new())? {
+            nodes.push(node);
+        }
+    }
+    Ok(Scene {
+        camera: loader.camera.unwrap_or_default(),
+        lights: loader.lights,
+        ..Scene::new(nodes)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A triangle seen by a camera, with the accessors of its attributes
+    fn gltf(attributes: &str, accessors: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0, 1] }}],
+                "nodes": [
+                    {{ "mesh": 0 }},
+                    {{ "camera": 0, "translation": [0, 0, 3] }}
+                ],
+                "cameras": [{{
+                    "type": "perspective",
+                    "perspective": {{ "yfov": 0.5, "aspectRatio": 2.0, "znear": 0.1 }}
+                }}],
+                "meshes": [{{ "primitives": [{{ "attributes": {{ {} }} }}] }}],
+                "accessors": [{}],
+                "bufferViews": [{{ "buffer": 0, "byteLength": 36 }}],
+                "buffers": [{{
+                    "byteLength": 36,
+                    "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA"
+                }}]
+            }}"#,
+            attributes, accessors
+        )
+    }
+
+    // With only the position accessor
+    fn load(accessor: &str) -> Result<Scene, String> {
+        read(gltf(r#""POSITION": 0"#, accessor).as_bytes(), Path::new(""))
+    }
+
+    #[test]
+    fn reads_a_triangle_and_its_camera() {
+        let scene =
+            load(r#"{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }"#)
+                .unwrap();
+        assert_eq!(scene.objects().unwrap().len(), 1);
+        // converted to a horizontal one with the aspect ratio of the image
+        assert!(scene.camera.fov.is_none());
+        let fov = scene.camera.vertical_fov.unwrap().at(0.0);
+        assert!((fov - 0.5f64.to_degrees()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rejects_accessors_out_of_their_buffer_view() {
+        assert!(
+            load(r#"{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3" }"#)
+                .is_err()
+        );
+        let huge = format!(
+            r#"{{ "bufferView": 0, "componentType": 5126, "count": {}, "type": "VEC3" }}"#,
+            usize::MAX / 2
+        );
+        assert!(load(&huge).is_err());
+    }
+
+    #[test]
+    fn rejects_accessors_too_big_for_memory() {
+        let huge = format!(
+            r#"{{ "componentType": 5126, "count": {}, "type": "MAT4" }}"#,
+            usize::MAX / 8
+        );
+        assert!(load(&huge).is_err());
+    }
+
+    #[test]
+    fn rejects_attributes_not_matching_the_positions() {
+        let positions = r#"{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }"#;
+        let load = |normals: &str| {
+            let accessors = format!("{}, {}", positions, normals);
+            read(
+                gltf(r#""POSITION": 0, "NORMAL": 1"#, &accessors).as_bytes(),
+                Path::new(""),
+            )
+        };
+        assert!(load(positions).is_ok());
+        assert!(
+            load(r#"{ "bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3" }"#)
+                .is_err()
+        );
+        assert!(
+            load(r#"{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC2" }"#)
+                .is_err()
+        );
+    }
+}
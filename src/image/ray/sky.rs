@@ -0,0 +1,218 @@
+use super::color::Color;
+use super::vec3::UnitDirection;
+use rand::Rng;
+use std::f64::consts::PI;
+
+// Clear daylight sky of Preetham, Shirley and Smits ("A Practical Analytic
+// Model for Daylight", 1999), with the sun and the ground below the horizon
+#[derive(Clone)]
+pub struct Sky {
+    sun: UnitDirection,
+    // in radians
+    sun_zenith: f64,
+    // Perez coefficients of the chromaticity and the luminance
+    coefficients: [[f64; 5]; 3],
+    // xyY at the zenith
+    zenith: [f64; 3],
+    sun_color: Color,
+    ground: Color,
+    intensity: f64,
+}
+
+// angular radius of the sun, in radians
+const SUN_RADIUS: f64 = 0.004_65;
+// radiance of the sun outside the atmosphere, in the same unit as the sky
+const SUN_RADIANCE: f64 = 60_000.0;
+// sky luminances are given in kcd/m², 1 being displayed as white after this
+const SCALE: f64 = 0.03;
+// probability of sampling the sun rather than the rest of the sky
+const SUN_SAMPLES: f64 = 0.5;
+
+// Perez et al.'s distribution of the luminance of the sky
+fn perez(coefficients: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / cos_theta.max(1e-3)).exp())
+        * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+}
+
+// Linear sRGB color of a CIE xyY color
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    Color::new(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    )
+}
+
+// Share of the light of the sun going through the atmosphere at these
+// wavelengths, scattered by air (Rayleigh) and aerosols (Ångström's formula)
+fn transmittance(sun_zenith: f64, turbidity: f64, wavelength: f64) -> f64 {
+    // Kasten and Young's relative air mass
+    let degrees = sun_zenith.to_degrees();
+    let air_mass = 1.0 / (sun_zenith.cos() + 0.50572 * (96.07995 - degrees).powf(-1.6364));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let rayleigh = 0.008735 * wavelength.powf(-4.08);
+    let aerosols = beta * wavelength.powf(-1.3);
+    (-air_mass * (rayleigh + aerosols)).exp()
+}
+
+impl Sky {
+    // Sun elevation and azimuth in degrees, the azimuth being counted
+    // counterclockwise seen from above from -z
+    pub fn new(
+        elevation: f64,
+        azimuth: f64,
+        turbidity: f64,
+        ground_albedo: Color,
+    ) -> Result<Self, String> {
+        if !(0.0..=90.0).contains(&elevation) {
+            return Err("the sun elevation must be in [0, 90]".to_string());
+        }
+        if !(2.0..=10.0).contains(&turbidity) {
+            return Err("the turbidity must be in [2, 10]".to_string());
+        }
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        let sun = UnitDirection::new(
+            -azimuth.sin() * elevation.cos(),
+            elevation.sin(),
+            -azimuth.cos() * elevation.cos(),
+        );
+        let theta = PI / 2.0 - elevation;
+        let t = turbidity;
+        let coefficients = [
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+        ];
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let thetas = [theta.powi(3), theta.powi(2), theta, 1.0];
+            let row = |i: usize| (0..4).map(|j| m[i][j] * thetas[j]).sum::<f64>();
+            t * t * row(0) + t * row(1) + row(2)
+        };
+        let x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        // red, green and blue wavelengths in micrometers
+        let sun_color = SUN_RADIANCE
+            * Color::new(
+                transmittance(theta, t, 0.68),
+                transmittance(theta, t, 0.55),
+                transmittance(theta, t, 0.44),
+            );
+        let mut sky = Sky {
+            sun,
+            sun_zenith: theta,
+            coefficients,
+            zenith: [x, y, luminance],
+            sun_color,
+            ground: Color::new(0.0, 0.0, 0.0),
+            intensity: 1.0,
+        };
+        // the ground is lit by the sun and the sky
+        let (steps_theta, steps_phi) = (32, 64);
+        let (d_theta, d_phi) = (PI / 2.0 / steps_theta as f64, 2.0 * PI / steps_phi as f64);
+        let mut irradiance = (SUN_RADIUS * SUN_RADIUS * PI * sun.get_y()) * sky.sun_color;
+        for i in 0..steps_theta {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..steps_phi {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let direction = UnitDirection::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                irradiance = irradiance
+                    + (theta.cos() * theta.sin() * d_theta * d_phi) * sky.sky_color(direction);
+            }
+        }
+        sky.ground = (1.0 / PI) * (ground_albedo * irradiance);
+        Ok(sky)
+    }
+
+    pub fn with_intensity(self, intensity: f64) -> Self {
+        Sky { intensity, ..self }
+    }
+
+    // Light of the sky alone, above the horizon
+    fn sky_color(&self, direction: UnitDirection) -> Color {
+        let cos_theta = direction.get_y();
+        let gamma = (direction * self.sun).clamp(-1.0, 1.0).acos();
+        let value = |i: usize| {
+            self.zenith[i] * perez(&self.coefficients[i], cos_theta, gamma)
+                / perez(&self.coefficients[i], 1.0, self.sun_zenith)
+        };
+        xyy_to_rgb(value(0), value(1), SCALE * value(2))
+    }
+
+    pub fn color(&self, direction: UnitDirection) -> Color {
+        let res = if direction.get_y() < 0.0 {
+            self.ground
+        } else if direction * self.sun >= SUN_RADIUS.cos() {
+            self.sky_color(direction) + self.sun_color
+        } else {
+            self.sky_color(direction)
+        };
+        self.intensity * res
+    }
+
+    // Probability density of sampling this direction, per unit solid angle,
+    // 0 below the horizon: the ground is even, and left to bouncing rays
+    pub fn pdf(&self, direction: UnitDirection) -> f64 {
+        let sun = if direction * self.sun >= SUN_RADIUS.cos() {
+            SUN_SAMPLES / (2.0 * PI * (1.0 - SUN_RADIUS.cos()))
+        } else {
+            0.0
+        };
+        sun + (1.0 - SUN_SAMPLES) * direction.get_y().max(0.0) / PI
+    }
+
+    // Direction towards the sun or the rest of the sky, with its probability
+    // density
+    pub fn sample(&self) -> (UnitDirection, f64) {
+        let mut rng = rand::thread_rng();
+        let direction = if rng.gen::<f64>() < SUN_SAMPLES {
+            // uniformly in the cone of the sun
+            let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - SUN_RADIUS.cos());
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let phi = 2.0 * PI * rng.gen::<f64>();
+            let (a, b) = self.sun.orthonormal_basis();
+            (cos_theta * self.sun + sin_theta * phi.cos() * a + sin_theta * phi.sin() * b)
+                .as_unit_vector()
+        } else {
+            // by the cosine of the angle with the zenith
+            let r = rng.gen::<f64>().sqrt();
+            let phi = 2.0 * PI * rng.gen::<f64>();
+            UnitDirection::new(r * phi.cos(), (1.0 - r * r).max(0.0).sqrt(), r * phi.sin())
+        };
+        (direction, self.pdf(direction))
+    }
+}
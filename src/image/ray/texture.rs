@@ -0,0 +1,244 @@
+pub mod bitmap;
+pub mod perlin;
+
+use super::color::{Color, WHITE};
+use super::object::Hit;
+use bitmap::{Bitmap, Wrap};
+use perlin::Perlin;
+use serde::{Deserialize, Deserializer};
+use std::sync::{Arc, OnceLock};
+
+// Color of an object at each point of its surface, given by its texture
+// coordinates or its position in the space of the object
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Texture {
+    Constant {
+        color: Color,
+    },
+    // squares of the texture coordinates, `scale` of them per unit
+    Checker {
+        #[serde(deserialize_with = "color_or_texture")]
+        even: Arc<Texture>,
+        #[serde(deserialize_with = "color_or_texture")]
+        odd: Arc<Texture>,
+        #[serde(default = "default_checker_scale")]
+        scale: f64,
+    },
+    // cubes of the space of the object
+    #[serde(rename = "checker_3d")]
+    Checker3d {
+        #[serde(deserialize_with = "color_or_texture")]
+        even: Arc<Texture>,
+        #[serde(deserialize_with = "color_or_texture")]
+        odd: Arc<Texture>,
+        #[serde(default = "one")]
+        size: f64,
+    },
+    Image(ImageTexture),
+    // fractal noise, darker where it is low
+    Noise {
+        #[serde(default = "white")]
+        color: Color,
+        #[serde(default = "one")]
+        scale: f64,
+        #[serde(default = "one_octave")]
+        octaves: u32,
+    },
+    // veins along x, distorted by turbulence
+    Marble {
+        #[serde(default = "marble_colors")]
+        colors: (Color, Color),
+        #[serde(default = "one")]
+        scale: f64,
+        #[serde(default = "marble_turbulence")]
+        turbulence: f64,
+        #[serde(default = "six_octaves")]
+        octaves: u32,
+    },
+    // rings around the y axis, `scale` of them per unit
+    Wood {
+        #[serde(default = "wood_colors")]
+        colors: (Color, Color),
+        #[serde(default = "wood_scale")]
+        scale: f64,
+        #[serde(default = "wood_turbulence")]
+        turbulence: f64,
+        #[serde(default = "four_octaves")]
+        octaves: u32,
+    },
+    // colors of the vertices of meshes, white elsewhere
+    VertexColors,
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "ImageTextureData")]
+pub struct ImageTexture {
+    bitmap: Arc<Bitmap>,
+    wrap: Wrap,
+    // copies of the image per unit of the texture coordinates
+    scale: f64,
+}
+
+#[derive(Deserialize)]
+struct ImageTextureData {
+    file: String,
+    #[serde(default)]
+    wrap: Wrap,
+    #[serde(default = "one")]
+    scale: f64,
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn white() -> Color {
+    Color::new(1.0, 1.0, 1.0)
+}
+
+fn one_octave() -> u32 {
+    1
+}
+
+fn four_octaves() -> u32 {
+    4
+}
+
+fn six_octaves() -> u32 {
+    6
+}
+
+fn default_checker_scale() -> f64 {
+    10.0
+}
+
+fn marble_colors() -> (Color, Color) {
+    (Color::new(0.9, 0.9, 0.88), Color::new(0.2, 0.2, 0.22))
+}
+
+fn marble_turbulence() -> f64 {
+    5.0
+}
+
+fn wood_colors() -> (Color, Color) {
+    (Color::new(0.75, 0.55, 0.3), Color::new(0.45, 0.25, 0.1))
+}
+
+fn wood_scale() -> f64 {
+    4.0
+}
+
+fn wood_turbulence() -> f64 {
+    0.5
+}
+
+// Nested textures can be given as plain colors
+fn color_or_texture<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Arc<Texture>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Color(Color),
+        Texture(Texture),
+    }
+    Ok(Arc::new(match Value::deserialize(deserializer)? {
+        Value::Color(color) => Texture::Constant { color },
+        Value::Texture(texture) => texture,
+    }))
+}
+
+fn perlin() -> &'static Perlin {
+    static PERLIN: OnceLock<Perlin> = OnceLock::new();
+    PERLIN.get_or_init(Perlin::new)
+}
+
+impl std::convert::TryFrom<ImageTextureData> for ImageTexture {
+    type Error = String;
+
+    fn try_from(data: ImageTextureData) -> Result<Self, Self::Error> {
+        Ok(ImageTexture::new(Bitmap::load(&data.file)?, data.wrap).scaled(data.scale))
+    }
+}
+
+impl ImageTexture {
+    pub fn new(bitmap: Bitmap, wrap: Wrap) -> Self {
+        ImageTexture {
+            bitmap: Arc::new(bitmap),
+            wrap,
+            scale: 1.0,
+        }
+    }
+
+    pub fn scaled(self, scale: f64) -> Self {
+        ImageTexture { scale, ..self }
+    }
+
+    pub fn color(&self, u: f64, v: f64) -> Color {
+        self.bitmap.color(u * self.scale, v * self.scale, self.wrap)
+    }
+}
+
+fn mix(colors: (Color, Color), t: f64) -> Color {
+    (1.0 - t) * colors.0 + t * colors.1
+}
+
+impl Texture {
+    pub fn color(&self, hit: &Hit) -> Color {
+        let point = hit.point;
+        match self {
+            Texture::Constant { color } => *color,
+            Texture::Checker { even, odd, scale } => {
+                let square = (hit.u * scale).floor() + (hit.v * scale).floor();
+                if square.rem_euclid(2.0) < 1.0 {
+                    even.color(hit)
+                } else {
+                    odd.color(hit)
+                }
+            }
+            Texture::Checker3d { even, odd, size } => {
+                let cube = (point.get_x() / size).floor()
+                    + (point.get_y() / size).floor()
+                    + (point.get_z() / size).floor();
+                if cube.rem_euclid(2.0) < 1.0 {
+                    even.color(hit)
+                } else {
+                    odd.color(hit)
+                }
+            }
+            Texture::Image(image) => image.color(hit.u, hit.v),
+            Texture::Noise {
+                color,
+                scale,
+                octaves,
+            } => (0.5 * (1.0 + perlin().fbm(*scale * point, *octaves))).clamp(0.0, 1.0) * *color,
+            Texture::Marble {
+                colors,
+                scale,
+                turbulence,
+                octaves,
+            } => {
+                let point = *scale * point;
+                let phase = point.get_x() + turbulence * perlin().turbulence(point, *octaves);
+                mix(*colors, 0.5 * (1.0 + phase.sin()))
+            }
+            Texture::Wood {
+                colors,
+                scale,
+                turbulence,
+                octaves,
+            } => {
+                let radius = (point.get_x() * point.get_x() + point.get_z() * point.get_z()).sqrt();
+                let rings = scale * radius + turbulence * perlin().fbm(point, *octaves);
+                // light wood darkening towards the end of each ring
+                mix(*colors, rings.rem_euclid(1.0).powi(2))
+            }
+            Texture::VertexColors => hit.color.unwrap_or(WHITE),
+        }
+    }
+}
+
+impl From<Color> for Texture {
+    fn from(color: Color) -> Self {
+        Texture::Constant { color }
+    }
+}
@@ -0,0 +1,103 @@
+use super::super::color::Color;
+use png::{ColorType, Decoder, Transformations};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+
+// How texture coordinates outside of [0, 1] are brought back in the image
+#[derive(Deserialize, Copy, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Wrap {
+    #[default]
+    Repeat,
+    // the pixels on the edges are stretched
+    Clamp,
+    // every other copy is flipped
+    Mirror,
+}
+
+// Pixels of a png file, row by row from the top
+pub struct Bitmap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Wrap {
+    // Index of a pixel in a row or column of `size` pixels
+    fn index(self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        match self {
+            Wrap::Repeat => i.rem_euclid(size) as usize,
+            Wrap::Clamp => i.clamp(0, size - 1) as usize,
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                (if i < size { i } else { 2 * size - 1 - i }) as usize
+            }
+        }
+    }
+}
+
+impl Bitmap {
+    pub fn load(file_name: &str) -> Result<Self, String> {
+        let file = File::open(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+        Bitmap::decode(file).map_err(|e| format!("{}: {}", file_name, e))
+    }
+
+    // A png image, e.g. embedded in another file
+    pub fn decode<R: Read>(data: R) -> Result<Self, String> {
+        let mut decoder = Decoder::new(data);
+        // 8 bits per channel, palettes replaced by their colors
+        decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+        let channels = match info.color_type {
+            ColorType::Grayscale => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+            ColorType::Indexed => return Err("unsupported palette".to_string()),
+        };
+        let (width, height) = (info.width as usize, info.height as usize);
+        let pixels = buffer[..info.buffer_size()]
+            .chunks(channels)
+            .map(|pixel| {
+                let value = |i: usize| pixel[i] as f64 / 255.0;
+                if channels < 3 {
+                    Color::new(value(0), value(0), value(0))
+                } else {
+                    Color::new(value(0), value(1), value(2))
+                }
+            })
+            .collect();
+        Ok(Bitmap {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // Each pixel multiplied by `color`
+    pub fn tinted(self, color: Color) -> Self {
+        Bitmap {
+            pixels: self.pixels.iter().map(|pixel| color * *pixel).collect(),
+            ..self
+        }
+    }
+
+    // Bilinear interpolation of the pixels around (u, v), v going up
+    pub fn color(&self, u: f64, v: f64, wrap: Wrap) -> Color {
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let pixel = |x: f64, y: f64| {
+            let x = wrap.index(x as i64, self.width);
+            let y = wrap.index(y as i64, self.height);
+            self.pixels[y * self.width + x]
+        };
+        (1.0 - dy) * ((1.0 - dx) * pixel(x0, y0) + dx * pixel(x0 + 1.0, y0))
+            + dy * ((1.0 - dx) * pixel(x0, y0 + 1.0) + dx * pixel(x0 + 1.0, y0 + 1.0))
+    }
+}
@@ -0,0 +1,127 @@
+use super::super::vec3::Location;
+
+// Ken Perlin's improved noise ("Improving Noise", 2002), with a permutation
+// fixed once and for all so that textures don't change between renders
+
+fn permutation() -> [usize; 512] {
+    let mut res = [0; 512];
+    let mut values: Vec<usize> = (0..256).collect();
+    // xorshift, shuffling the values the same way every time
+    let mut state: u32 = 2_463_534_242;
+    for i in (1..256).rev() {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        values.swap(i, state as usize % (i + 1));
+    }
+    for i in 0..512 {
+        res[i] = values[i % 256];
+    }
+    res
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// Dot product with one of 12 directions towards the edges of a cube
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+pub struct Perlin {
+    p: [usize; 512],
+}
+
+impl Perlin {
+    pub fn new() -> Self {
+        Perlin { p: permutation() }
+    }
+
+    // In [-1, 1], 0 at integer coordinates
+    pub fn noise(&self, point: Location) -> f64 {
+        let p = &self.p;
+        let (x, y, z) = (point.get_x(), point.get_y(), point.get_z());
+        let (xi, yi, zi) = (
+            x.floor() as i64 & 255,
+            y.floor() as i64 & 255,
+            z.floor() as i64 & 255,
+        );
+        let (xi, yi, zi) = (xi as usize, yi as usize, zi as usize);
+        let (x, y, z) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let a = p[xi] + yi;
+        let (aa, ab) = (p[a] + zi, p[a + 1] + zi);
+        let b = p[xi + 1] + yi;
+        let (ba, bb) = (p[b] + zi, p[b + 1] + zi);
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    grad(p[ab], x, y - 1.0, z),
+                    grad(p[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p[aa + 1], x, y, z - 1.0),
+                    grad(p[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(p[ab + 1], x, y - 1.0, z - 1.0),
+                    grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    // Fractional Brownian motion: octaves of noise of doubling frequency and
+    // halving amplitude, in about [-1, 1]
+    pub fn fbm(&self, point: Location, octaves: u32) -> f64 {
+        let mut res = 0.0;
+        let (mut frequency, mut amplitude) = (1.0, 0.5);
+        for _ in 0..octaves.max(1) {
+            res += amplitude * self.noise(frequency * point);
+            frequency *= 2.0;
+            amplitude *= 0.5;
+        }
+        2.0 * res
+    }
+
+    // Sum of the absolute values of the octaves, in about [0, 1]
+    pub fn turbulence(&self, point: Location, octaves: u32) -> f64 {
+        let mut res = 0.0;
+        let (mut frequency, mut amplitude) = (1.0, 0.5);
+        for _ in 0..octaves.max(1) {
+            res += amplitude * self.noise(frequency * point).abs();
+            frequency *= 2.0;
+            amplitude *= 0.5;
+        }
+        2.0 * res
+    }
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Perlin::new()
+    }
+}
@@ -0,0 +1,669 @@
+use super::animation::{Animated, Animation, Interpolate, Interpolation, Keyframe};
+use super::bvh::Aabb;
+use super::vec3::{Direction, Location, UnitDirection, Vector};
+use super::Ray;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::f64::consts::PI;
+
+pub type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Scale {
+    Uniform(f64),
+    Axes(Vector),
+}
+
+// Steps of a transform as written in json files, applied in order
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Step {
+    Translate(Vector),
+    // angle in degrees
+    Rotate { axis: UnitDirection, angle: f64 },
+    Scale(Scale),
+    Matrix(Matrix),
+}
+
+// Affine transform, with its inverse
+#[derive(Deserialize, Copy, Clone)]
+#[serde(try_from = "Vec<Step>")]
+pub struct Transform {
+    matrix: Matrix,
+    inverse: Matrix,
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut res = [[0.0; 4]; 4];
+    for (i, row) in res.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    res
+}
+
+// Gauss-Jordan elimination with partial pivoting
+fn invert(matrix: &Matrix) -> Option<Matrix> {
+    let mut m = *matrix;
+    let mut res = IDENTITY;
+    for column in 0..4 {
+        let pivot = (column..4).max_by(|&a, &b| {
+            m[a][column]
+                .abs()
+                .partial_cmp(&m[b][column].abs())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })?;
+        if m[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        m.swap(column, pivot);
+        res.swap(column, pivot);
+        let k = 1.0 / m[column][column];
+        for j in 0..4 {
+            m[column][j] *= k;
+            res[column][j] *= k;
+        }
+        for row in 0..4 {
+            if row != column {
+                let k = m[row][column];
+                for j in 0..4 {
+                    m[row][j] -= k * m[column][j];
+                    res[row][j] -= k * res[column][j];
+                }
+            }
+        }
+    }
+    Some(res)
+}
+
+fn step_matrix(step: &Step) -> Matrix {
+    match step {
+        Step::Translate(v) => [
+            [1.0, 0.0, 0.0, v.get_x()],
+            [0.0, 1.0, 0.0, v.get_y()],
+            [0.0, 0.0, 1.0, v.get_z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+        Step::Rotate { axis, angle } => {
+            let (sin, cos) = (angle * PI / 180.0).sin_cos();
+            let (x, y, z) = (axis.get_x(), axis.get_y(), axis.get_z());
+            let c = 1.0 - cos;
+            [
+                [
+                    cos + x * x * c,
+                    x * y * c - z * sin,
+                    x * z * c + y * sin,
+                    0.0,
+                ],
+                [
+                    y * x * c + z * sin,
+                    cos + y * y * c,
+                    y * z * c - x * sin,
+                    0.0,
+                ],
+                [
+                    z * x * c - y * sin,
+                    z * y * c + x * sin,
+                    cos + z * z * c,
+                    0.0,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ]
+        }
+        Step::Scale(scale) => {
+            let v = match scale {
+                Scale::Uniform(k) => Vector::new(*k, *k, *k),
+                Scale::Axes(v) => *v,
+            };
+            [
+                [v.get_x(), 0.0, 0.0, 0.0],
+                [0.0, v.get_y(), 0.0, 0.0],
+                [0.0, 0.0, v.get_z(), 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ]
+        }
+        Step::Matrix(matrix) => *matrix,
+    }
+}
+
+impl TryFrom<Vec<Step>> for Transform {
+    type Error = String;
+
+    fn try_from(steps: Vec<Step>) -> Result<Self, Self::Error> {
+        let matrix = steps.iter().fold(IDENTITY, |matrix, step| {
+            multiply(&step_matrix(step), &matrix)
+        });
+        match invert(&matrix) {
+            Some(inverse) => Ok(Transform { matrix, inverse }),
+            None => Err("transform is not invertible".to_string()),
+        }
+    }
+}
+
+fn apply(m: &Matrix, v: Vector, w: f64) -> Vector {
+    let row =
+        |i: usize| m[i][0] * v.get_x() + m[i][1] * v.get_y() + m[i][2] * v.get_z() + m[i][3] * w;
+    Vector::new(row(0), row(1), row(2))
+}
+
+impl Transform {
+    // translations and rotations are always invertible
+    fn from_step(step: Step) -> Self {
+        Transform::from_matrix(step_matrix(&step)).expect("transform is not invertible")
+    }
+
+    pub fn identity() -> Self {
+        Transform {
+            matrix: IDENTITY,
+            inverse: IDENTITY,
+        }
+    }
+
+    pub fn translation(offset: Vector) -> Self {
+        Transform::from_step(Step::Translate(offset))
+    }
+
+    // angle in degrees
+    pub fn rotation(axis: UnitDirection, angle: f64) -> Self {
+        Transform::from_step(Step::Rotate { axis, angle })
+    }
+
+    // None if a factor is 0
+    pub fn scaling(factors: Vector) -> Option<Self> {
+        Transform::from_matrix(step_matrix(&Step::Scale(Scale::Axes(factors))))
+    }
+
+    // None if the matrix is not invertible
+    pub fn from_matrix(matrix: Matrix) -> Option<Self> {
+        Some(Transform {
+            matrix,
+            inverse: invert(&matrix)?,
+        })
+    }
+
+    // This transform followed by `other`
+    pub fn then(&self, other: &Transform) -> Self {
+        Transform {
+            matrix: multiply(&other.matrix, &self.matrix),
+            inverse: multiply(&self.inverse, &other.inverse),
+        }
+    }
+
+    pub fn location(&self, location: Location) -> Location {
+        apply(&self.matrix, location, 1.0)
+    }
+
+    pub fn direction(&self, direction: Direction) -> Direction {
+        apply(&self.matrix, direction, 0.0)
+    }
+
+    // Normals are transformed by the inverse transpose of the matrix
+    pub fn normal(&self, normal: UnitDirection) -> UnitDirection {
+        let m = &self.inverse;
+        let column = |j: usize| {
+            m[0][j] * normal.get_x() + m[1][j] * normal.get_y() + m[2][j] * normal.get_z()
+        };
+        UnitDirection::new(column(0), column(1), column(2))
+    }
+
+    // The ray in the space before the transform, with the factor by which
+    // distances along it are multiplied
+    pub fn inverse_ray(&self, ray: &Ray) -> (Ray, f64) {
+        let direction = apply(&self.inverse, ray.direction.as_vector(), 0.0);
+        let scale = direction.length();
+        (
+            Ray {
+                location: apply(&self.inverse, ray.location, 1.0),
+                direction: direction.as_unit_vector(),
+                range: ray.range * scale,
+                ttl: ray.ttl,
+                time: ray.time,
+                // only used for hits
+                medium: None,
+            },
+            scale,
+        )
+    }
+
+    pub fn bounds(&self, bounds: &Aabb) -> Aabb {
+        let corners = bounds.corners();
+        corners.iter().skip(1).fold(
+            Aabb::new(self.location(corners[0]), self.location(corners[0])),
+            |res, corner| {
+                let corner = self.location(*corner);
+                res.union(&Aabb::new(corner, corner))
+            },
+        )
+    }
+}
+
+type Quaternion = [f64; 4];
+
+// Transform split into a translation, a rotation and a stretch, which can be
+// interpolated separately (after Shoemake and Duff, "Matrix Animation and
+// Polar Decomposition", 1992)
+#[derive(Copy, Clone)]
+pub struct Decomposition {
+    translation: Vector,
+    rotation: Quaternion,
+    stretch: Matrix,
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    let mut res = *m;
+    for (i, row) in res.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = m[j][i];
+        }
+    }
+    res
+}
+
+fn determinant(m: &Matrix) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+fn to_quaternion(m: &Matrix) -> Quaternion {
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    if trace > 0.0 {
+        let s = 0.5 / (trace + 1.0).sqrt();
+        [
+            0.25 / s,
+            (m[2][1] - m[1][2]) * s,
+            (m[0][2] - m[2][0]) * s,
+            (m[1][0] - m[0][1]) * s,
+        ]
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+        [
+            (m[2][1] - m[1][2]) / s,
+            0.25 * s,
+            (m[0][1] + m[1][0]) / s,
+            (m[0][2] + m[2][0]) / s,
+        ]
+    } else if m[1][1] > m[2][2] {
+        let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+        [
+            (m[0][2] - m[2][0]) / s,
+            (m[0][1] + m[1][0]) / s,
+            0.25 * s,
+            (m[1][2] + m[2][1]) / s,
+        ]
+    } else {
+        let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+        [
+            (m[1][0] - m[0][1]) / s,
+            (m[0][2] + m[2][0]) / s,
+            (m[1][2] + m[2][1]) / s,
+            0.25 * s,
+        ]
+    }
+}
+
+fn quaternion_matrix(q: &Quaternion) -> Matrix {
+    let [w, x, y, z] = *q;
+    [
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y - w * z),
+            2.0 * (x * z + w * y),
+            0.0,
+        ],
+        [
+            2.0 * (x * y + w * z),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z - w * x),
+            0.0,
+        ],
+        [
+            2.0 * (x * z - w * y),
+            2.0 * (y * z + w * x),
+            1.0 - 2.0 * (x * x + y * y),
+            0.0,
+        ],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+fn slerp(a: &Quaternion, b: &Quaternion, t: f64) -> Quaternion {
+    let mut dot: f64 = (0..4).map(|i| a[i] * b[i]).sum();
+    // take the shortest way
+    let b = if dot < 0.0 {
+        dot = -dot;
+        [-b[0], -b[1], -b[2], -b[3]]
+    } else {
+        *b
+    };
+    let (ka, kb) = if dot > 0.9995 {
+        (1.0 - t, t)
+    } else {
+        let theta = dot.acos();
+        let sin = theta.sin();
+        (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+    };
+    let q: Vec<f64> = (0..4).map(|i| ka * a[i] + kb * b[i]).collect();
+    let norm = q.iter().map(|x| x * x).sum::<f64>().sqrt();
+    [q[0] / norm, q[1] / norm, q[2] / norm, q[3] / norm]
+}
+
+impl Decomposition {
+    fn new(transform: &Transform) -> Self {
+        let m = &transform.matrix;
+        let translation = Vector::new(m[0][3], m[1][3], m[2][3]);
+        let mut linear = *m;
+        for row in linear.iter_mut().take(3) {
+            row[3] = 0.0;
+        }
+        // the rotation is the limit of the average of the matrix and its
+        // inverse transpose
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let inverse = match invert(&rotation) {
+                Some(inverse) => transpose(&inverse),
+                None => break,
+            };
+            let mut change: f64 = 0.0;
+            for i in 0..3 {
+                for j in 0..3 {
+                    let next = 0.5 * (rotation[i][j] + inverse[i][j]);
+                    change = change.max((next - rotation[i][j]).abs());
+                    rotation[i][j] = next;
+                }
+            }
+            if change < 1e-12 {
+                break;
+            }
+        }
+        // keep mirroring in the stretch, so that the rotation is proper
+        if determinant(&rotation) < 0.0 {
+            for row in rotation.iter_mut().take(3) {
+                for cell in row.iter_mut().take(3) {
+                    *cell = -*cell;
+                }
+            }
+        }
+        Decomposition {
+            translation,
+            rotation: to_quaternion(&rotation),
+            stretch: multiply(&transpose(&rotation), &linear),
+        }
+    }
+
+    fn transform(&self) -> Result<Transform, String> {
+        let mut matrix = multiply(&quaternion_matrix(&self.rotation), &self.stretch);
+        matrix[0][3] = self.translation.get_x();
+        matrix[1][3] = self.translation.get_y();
+        matrix[2][3] = self.translation.get_z();
+        Transform::from_matrix(matrix).ok_or_else(|| "transform is not invertible".to_string())
+    }
+}
+
+impl Interpolate for Decomposition {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        let mut stretch = IDENTITY;
+        for (i, row) in stretch.iter_mut().enumerate().take(3) {
+            for (j, cell) in row.iter_mut().enumerate().take(3) {
+                *cell = self.stretch[i][j].lerp(&other.stretch[i][j], t);
+            }
+        }
+        Decomposition {
+            translation: self.translation.lerp(&other.translation, t),
+            rotation: slerp(&self.rotation, &other.rotation, t),
+            stretch,
+        }
+    }
+}
+
+// Transform of an object, which may change from frame to frame
+#[derive(Clone)]
+pub enum Motion {
+    Still(Box<Transform>),
+    Keyframes {
+        // decomposed once, to be interpolated quickly
+        decompositions: Animation<Decomposition>,
+        transforms: Animation<Transform>,
+    },
+}
+
+impl Motion {
+    // From `start` at time 0 to `end` at time 1
+    pub fn new(start: &Transform, end: &Transform) -> Result<Self, String> {
+        let keyframes = vec![
+            Keyframe {
+                frame: 0.0,
+                value: *start,
+            },
+            Keyframe {
+                frame: 1.0,
+                value: *end,
+            },
+        ];
+        Motion::try_from(
+            &Animation::new(keyframes, Interpolation::Linear).expect("keyframes are distinct"),
+        )
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        match self {
+            Motion::Still(transform) => **transform,
+            // the stretch is only checked at some times and may still be flat
+            // in between, the closest keyframe is used then
+            Motion::Keyframes {
+                decompositions,
+                transforms,
+            } => decompositions
+                .at(time)
+                .transform()
+                .unwrap_or_else(|_| *transforms.nearest(time)),
+        }
+    }
+
+    // Frames between which it moves
+    pub fn span(&self) -> Option<(f64, f64)> {
+        match self {
+            Motion::Still(_) => None,
+            Motion::Keyframes { transforms, .. } => Some(transforms.span()),
+        }
+    }
+}
+
+// Times between two keyframes at which the stretch is checked
+const CHECKS: usize = 64;
+
+impl TryFrom<&Animation<Transform>> for Motion {
+    type Error = String;
+
+    // The stretch must keep the sign of its determinant: going from a
+    // transform to its mirror image, it would be flat on the way. Flat is
+    // relative to the smallest keyframe, so that tiny objects can move.
+    fn try_from(transforms: &Animation<Transform>) -> Result<Self, Self::Error> {
+        let decompositions = transforms.map(Decomposition::new);
+        let frames = decompositions.frames();
+        let determinants: Vec<f64> = frames
+            .iter()
+            .map(|frame| determinant(&decompositions.at(*frame).stretch))
+            .collect();
+        let sign = determinants[0].signum();
+        let flat = 1e-6
+            * determinants
+                .iter()
+                .map(|d| d.abs())
+                .fold(f64::INFINITY, f64::min);
+        for pair in frames.windows(2) {
+            for i in 0..=CHECKS {
+                let frame = pair[0] + (pair[1] - pair[0]) * i as f64 / CHECKS as f64;
+                if determinant(&decompositions.at(frame).stretch) * sign <= flat {
+                    return Err(format!(
+                        "transform turns into its mirror image between frames {} and {}",
+                        pair[0], pair[1]
+                    ));
+                }
+            }
+        }
+        Ok(Motion::Keyframes {
+            decompositions,
+            transforms: transforms.clone(),
+        })
+    }
+}
+
+impl TryFrom<&Animated<Transform>> for Motion {
+    type Error = String;
+
+    fn try_from(animated: &Animated<Transform>) -> Result<Self, Self::Error> {
+        match animated {
+            Animated::Keyframes(animation) => Motion::try_from(animation),
+            Animated::Constant(transform) => Ok(Motion::Still(Box::new(*transform))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matrix(a: &Matrix, b: &Matrix) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!(
+                    (a[i][j] - b[i][j]).abs() < 1e-9,
+                    "{:?} instead of {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    // Rotated, stretched unevenly, sheared and moved
+    fn transform() -> Transform {
+        serde_json::from_str(
+            r#"[
+                {"scale": {"x": 2, "y": 0.5, "z": 3}},
+                {"matrix": [[1, 0.3, 0, 0], [0, 1, 0, 0], [0.2, 0, 1, 0], [0, 0, 0, 1]]},
+                {"rotate": {"axis": {"x": 1, "y": 2, "z": 3}, "angle": 40}},
+                {"translate": {"x": 1, "y": -2, "z": 5}}
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn inverts_matrices() {
+        let transform = transform();
+        assert_matrix(&multiply(&transform.matrix, &transform.inverse), &IDENTITY);
+        assert_matrix(&multiply(&transform.inverse, &transform.matrix), &IDENTITY);
+        // a zero on the diagonal needs a pivot
+        let swap = [
+            [0.0, 1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        assert_matrix(&invert(&swap).unwrap(), &swap);
+    }
+
+    #[test]
+    fn rejects_flat_transforms() {
+        assert!(Transform::scaling(Vector::new(1.0, 0.0, 1.0)).is_none());
+        assert!(serde_json::from_str::<Transform>(r#"[{"scale": 0}]"#).is_err());
+    }
+
+    #[test]
+    fn composes_in_order() {
+        let transform = Transform::rotation(UnitDirection::new(0.0, 1.0, 0.0), 90.0)
+            .then(&Transform::translation(Vector::new(1.0, 0.0, 0.0)));
+        let location = transform.location(Location::new(1.0, 0.0, 0.0));
+        assert!((location - Vector::new(1.0, 0.0, -1.0)).length() < 1e-9);
+        let direction = transform.direction(Direction::new(1.0, 0.0, 0.0));
+        assert!((direction - Vector::new(0.0, 0.0, -1.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn keeps_normals_perpendicular_to_the_surface() {
+        let transform = transform();
+        let (u, v) = (
+            Direction::new(1.0, 1.0, 0.0),
+            Direction::new(0.0, 1.0, -2.0),
+        );
+        let normal = transform.normal((u ^ v).as_unit_vector());
+        for tangent in [u, v].iter() {
+            let tangent = transform.direction(*tangent).as_unit_vector();
+            assert!((normal * tangent).abs() < 1e-9);
+        }
+        // on the same side of the surface
+        let outside = transform.direction((u ^ v).as_unit_vector().as_vector());
+        assert!(normal * outside > 0.0);
+    }
+
+    #[test]
+    fn decomposes_into_a_rotation_and_a_stretch() {
+        let transform = transform();
+        let decomposition = Decomposition::new(&transform);
+        let rotation = quaternion_matrix(&decomposition.rotation);
+        assert_matrix(&multiply(&transpose(&rotation), &rotation), &IDENTITY);
+        assert!((determinant(&rotation) - 1.0).abs() < 1e-9);
+        assert_matrix(&decomposition.stretch, &transpose(&decomposition.stretch));
+        assert_matrix(
+            &decomposition.transform().unwrap().matrix,
+            &transform.matrix,
+        );
+    }
+
+    #[test]
+    fn keeps_mirroring_in_the_stretch() {
+        let mirror = Transform::scaling(Vector::new(-1.0, 1.0, 1.0))
+            .unwrap()
+            .then(&Transform::rotation(
+                UnitDirection::new(0.0, 0.0, 1.0),
+                30.0,
+            ));
+        let decomposition = Decomposition::new(&mirror);
+        let rotation = quaternion_matrix(&decomposition.rotation);
+        assert!((determinant(&rotation) - 1.0).abs() < 1e-9);
+        assert!(determinant(&decomposition.stretch) < 0.0);
+        assert_matrix(&decomposition.transform().unwrap().matrix, &mirror.matrix);
+    }
+
+    #[test]
+    fn interpolates_rotations() {
+        let axis = UnitDirection::new(0.0, 1.0, 0.0);
+        let motion = Motion::new(
+            &Transform::identity(),
+            &Transform::rotation(axis, 90.0)
+                .then(&Transform::translation(Vector::new(2.0, 0.0, 0.0))),
+        )
+        .unwrap();
+        let halfway = Transform::rotation(axis, 45.0)
+            .then(&Transform::translation(Vector::new(1.0, 0.0, 0.0)));
+        assert_matrix(&motion.at(0.5).matrix, &halfway.matrix);
+        assert!(Motion::new(
+            &Transform::identity(),
+            &Transform::scaling(Vector::new(-1.0, 1.0, 1.0)).unwrap()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn moves_tiny_objects() {
+        let tiny = Transform::scaling(Vector::new(1e-5, 1e-5, 1e-5)).unwrap();
+        let motion = Motion::new(
+            &tiny,
+            &tiny.then(&Transform::translation(Vector::new(1.0, 0.0, 0.0))),
+        )
+        .unwrap();
+        let location = motion.at(0.5).location(Location::new(1.0, 0.0, 0.0));
+        assert!((location - Vector::new(0.5 + 1e-5, 0.0, 0.0)).length() < 1e-9);
+    }
+}
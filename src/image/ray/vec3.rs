@@ -15,6 +15,7 @@ pub type Location = Vector;
 pub type Direction = Vector;
 
 #[derive(Deserialize, Copy, Clone)]
+#[serde(from = "Vector")]
 pub struct UnitVector {
     x: f64,
     y: f64,
@@ -32,6 +33,18 @@ impl Vector {
         UnitVector::new(self.x, self.y, self.z)
     }
 
+    pub fn get_x(&self) -> f64 {
+        self.x
+    }
+
+    pub fn get_y(&self) -> f64 {
+        self.y
+    }
+
+    pub fn get_z(&self) -> f64 {
+        self.z
+    }
+
     pub fn length(&self) -> f64 {
         self.length_squared().sqrt()
     }
@@ -49,7 +62,11 @@ impl Vector {
         let mut res = Vector::new(1.0, 1.0, 1.0);
         let mut rng = rand::thread_rng();
         while res.length_squared() >= 1.0 {
-            res = Vector::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>());
+            res = Vector::new(
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+            );
         }
         res
     }
@@ -73,6 +90,10 @@ impl UnitVector {
         UnitVector { x, y, z }
     }
 
+    pub fn get_x(&self) -> f64 {
+        self.x
+    }
+
     pub fn set_x(&mut self, x: f64) {
         self.x = x;
         self.correct()
@@ -87,6 +108,10 @@ impl UnitVector {
         self.correct()
     }
 
+    pub fn get_z(&self) -> f64 {
+        self.z
+    }
+
     pub fn set_z(&mut self, z: f64) {
         self.z = z;
         self.correct()
@@ -108,6 +133,18 @@ impl UnitVector {
         self.as_vector().rot(axis, angle).as_unit_vector()
     }
 
+    // Two unit vectors forming an orthonormal basis with this one
+    // (Duff et al., "Building an Orthonormal Basis, Revisited", 2017)
+    pub fn orthonormal_basis(&self) -> (UnitVector, UnitVector) {
+        let sign = 1f64.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            UnitVector::unsafe_new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            UnitVector::unsafe_new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
     pub fn random_on_unit_sphere() -> Self {
         Vector::random_in_unit_sphere().as_unit_vector()
     }
@@ -118,24 +155,27 @@ fn parse_vector_arg(value: &str) -> Result<(f64, f64, f64), String> {
         .trim_matches(|p| p == '(' || p == ')')
         .split(',')
         .collect();
-    let x;
-    let y;
-    let z;
-    match t[0].parse::<f64>() {
-        Ok(f) => x = f,
+    let x = match t[0].parse::<f64>() {
+        Ok(f) => f,
         Err(e) => return Err(format!("{} for x", e)),
-    }
-    match t[1].parse::<f64>() {
-        Ok(f) => y = f,
+    };
+    let y = match t[1].parse::<f64>() {
+        Ok(f) => f,
         Err(e) => return Err(format!("{} for y", e)),
-    }
-    match t[2].parse::<f64>() {
-        Ok(f) => z = f,
+    };
+    let z = match t[2].parse::<f64>() {
+        Ok(f) => f,
         Err(e) => return Err(format!("{} for z", e)),
-    }
+    };
     Ok((x, y, z))
 }
 
+impl From<Vector> for UnitVector {
+    fn from(vector: Vector) -> Self {
+        vector.as_unit_vector()
+    }
+}
+
 impl FromArgValue for Vector {
     fn from_arg_value(value: &str) -> Result<Self, String> {
         let (x, y, z) = parse_vector_arg(value)?;
@@ -1,17 +1,28 @@
-mod image;
-
-use argh::FromArgs;
-use image::ray::vec3::{Location, UnitDirection};
-use image::ray::{Background, Sphere};
+use argh::{FromArgValue, FromArgs};
+use rray::image;
+use rray::image::orbit::Orbit;
+use rray::image::output::{Output, EXTENSIONS};
+use rray::image::ray::bvh::Bvh;
+use rray::image::ray::color::Color;
+use rray::image::ray::scene::Scene;
+use rray::image::ray::sky::Sky;
+use rray::image::ray::vec3::{Location, UnitDirection};
+use rray::image::ray::Background;
+use rray::image::Image;
 use std::fs;
+use std::num::NonZeroU16;
+use std::path::Path;
 
 #[derive(FromArgs)]
-/// Generate PNG images given a json file containing the coordinates of spheres and their color
+/// Render a scene described by a json or glTF file as a PNG or EXR image, or
+/// its frames as an APNG, GIF or Y4M animation
 struct Args {
-    /// spheres file name (must end by ".json")
+    /// scene file name (must end by ".json", or ".gltf" or ".glb" for a glTF
+    /// scene)
     #[argh(positional)]
-    spheres: String,
-    /// output file name (must end by ".png")
+    scene: String,
+    /// output file name (must end by ".png", ".exr", ".apng", ".gif" or ".y4m",
+    /// or "-" for a y4m stream on the standard output)
     #[argh(option, short = 'o')]
     output: Option<String>,
     /// width of the image to generate (default: 1920)
@@ -29,42 +40,298 @@ struct Args {
     /// direction of the camera (default: (0,0,-1))
     #[argh(option, short = 'd')]
     camera_direction: Option<UnitDirection>,
+    /// name of a node of the scene to point the camera at (overrides the direction)
+    #[argh(option, short = 't')]
+    camera_target: Option<String>,
     /// field of view of the camera in degrees (default: 80)
     #[argh(option, short = 'f')]
     fov: Option<f64>,
-    /// background of the image (default: blue gradient)
+    /// background of the image, "blue_gradient", "black", "transparent", "sky",
+    /// a color ("#rrggbb" or "r,g,b"), a vertical gradient of colors from the
+    /// bottom to the top separated by ':' (e.g. "#ffffff:0.5,0.7,1" or
+    /// "#000000@0:#ff8800@0.5:#0000ff@1"), or an equirectangular environment
+    /// map lighting the scene (".hdr" or ".exr") (default: blue gradient)
     #[argh(option, short = 'b')]
     background: Option<Background>,
+    /// rotation of the environment map around the vertical axis in degrees (default: 0)
+    #[argh(option)]
+    environment_rotation: Option<f64>,
+    /// multiplier of the light of the environment map or the sky (default: 1)
+    #[argh(option)]
+    environment_intensity: Option<f64>,
+    /// angle of the sun above the horizon in degrees, in [0, 90] (default: 45)
+    #[argh(option)]
+    sun_elevation: Option<f64>,
+    /// direction of the sun in degrees, counterclockwise seen from above from -z
+    /// (default: 0)
+    #[argh(option)]
+    sun_azimuth: Option<f64>,
+    /// haziness of the sky, in [2, 10] (default: 3)
+    #[argh(option)]
+    turbidity: Option<f64>,
+    /// share of the light reflected by the ground below the horizon (default: 0.3)
+    #[argh(option)]
+    ground_albedo: Option<f64>,
+    /// times between which the camera records moving objects, in [0, 1] (default: 0,1)
+    #[argh(option, short = 's')]
+    shutter: Option<image::Shutter>,
+    /// render the frames from start to end (excluded) of an animated scene, as
+    /// numbered files (e.g. 0..24)
+    #[argh(option)]
+    frames: Option<Frames>,
+    /// turn the camera around its target, once every this number of frames
+    /// (renders one turn unless frames are given)
+    #[argh(option)]
+    orbit: Option<usize>,
+    /// center of the orbit (default: the camera target, or (0,0,0))
+    #[argh(option)]
+    orbit_center: Option<Location>,
+    /// distance of the camera from the center of the orbit (default: 5)
+    #[argh(option)]
+    orbit_radius: Option<f64>,
+    /// angle of the camera above the center of the orbit in degrees, in (-90, 90)
+    /// (default: 20)
+    #[argh(option)]
+    orbit_elevation: Option<f64>,
+    /// height gained by the camera at each turn, for a helix (default: 0)
+    #[argh(option)]
+    orbit_rise: Option<f64>,
+    /// frames per second of animated outputs (default: 24)
+    #[argh(option)]
+    fps: Option<NonZeroU16>,
+    /// denoise the image using its albedo and normals (useful with low multisampling)
+    #[argh(switch)]
+    denoise: bool,
+    /// leave the background out of the image, with an alpha channel (png, exr,
+    /// apng and gif outputs), while it still lights the scene
+    #[argh(switch)]
+    alpha: bool,
+}
+
+// Range of frames, "start..end" or "start..=end"
+struct Frames {
+    start: i64,
+    end: i64,
+}
+
+impl FromArgValue for Frames {
+    fn from_arg_value(value: &str) -> Result<Self, String> {
+        let (start, end, inclusive) = match value.find("..=") {
+            Some(i) => (&value[..i], &value[i + 3..], true),
+            None => match value.find("..") {
+                Some(i) => (&value[..i], &value[i + 2..], false),
+                None => return Err("expected start..end".to_string()),
+            },
+        };
+        let start = start
+            .trim()
+            .parse::<i64>()
+            .map_err(|e| format!("{} for the first frame", e))?;
+        let end = end
+            .trim()
+            .parse::<i64>()
+            .map_err(|e| format!("{} for the last frame", e))?
+            + inclusive as i64;
+        if start < end {
+            Ok(Frames { start, end })
+        } else {
+            Err("there are no frames in this range".to_string())
+        }
+    }
 }
 
 fn main() {
     let args: Args = argh::from_env();
-    if !args.spheres.ends_with(".json") {
-        panic!("Error: spheres file must be a json file");
-    }
-    let objects: Vec<Sphere> = serde_json::from_str(
-        &fs::read_to_string(&args.spheres).expect(&format!("Error opening {}", &args.spheres)),
-    )
-    .expect(&format!("Error parsing {}", &args.spheres));
-    let output = match args.output {
+    let extension = Path::new(&args.scene)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("");
+    let scene: Scene = match extension {
+        "json" => serde_json::from_str(
+            &fs::read_to_string(&args.scene)
+                .unwrap_or_else(|_| panic!("Error opening {}", &args.scene)),
+        )
+        .unwrap_or_else(|e| panic!("Error parsing {}: {}", &args.scene, e)),
+        "gltf" | "glb" => Scene::load_gltf(&args.scene).unwrap_or_else(|e| panic!("Error: {}", e)),
+        _ => panic!("Error: scene file must be a json, gltf or glb file"),
+    };
+    // built once, and again for each frame only to bound moving objects
+    // around where they are then
+    let mut objects = Bvh::new(
+        scene
+            .objects()
+            .unwrap_or_else(|e| panic!("Error in {}: {}", &args.scene, e)),
+    );
+    let moving = objects.has_moving_objects();
+    let output = match &args.output {
         Some(file_path) => {
-            if !file_path.ends_with(".png") {
-                panic!("Error: output file must be a png file");
+            if file_path != "-" && !EXTENSIONS.iter().any(|ext| file_path.ends_with(ext)) {
+                panic!("Error: output file must be a png, exr, apng, gif or y4m file");
             }
-            file_path
+            file_path.clone()
         }
-        None => args.spheres.replace(".json", ".png"),
+        None => Path::new(&args.scene)
+            .with_extension("png")
+            .to_string_lossy()
+            .into_owned(),
     };
-    let mut image = image::Image::new(
-        args.width.unwrap_or(1920),
-        args.height.unwrap_or(1080),
-        args.multisampling.unwrap_or(100),
-        args.camera_location.unwrap_or(Location::new(0.0, 0.0, 0.0)),
-        args.camera_direction
-            .unwrap_or(UnitDirection::new(0.0, 0.0, -1.0)),
-        args.fov.unwrap_or(80.0),
-        args.background.unwrap_or(Background::BlueGradient),
-    );
-    image.compute(objects);
-    image.write(&output);
+    let camera = &scene.camera;
+    let target = match (&args.camera_target, args.camera_direction) {
+        (Some(name), _) => Some(name),
+        (None, Some(_)) => None,
+        (None, None) => camera.target.as_ref(),
+    };
+    let target_center = |name: &str, time: f64| {
+        scene
+            .bounds(name, time)
+            .unwrap_or_else(|e| panic!("Error: cannot point the camera at {}: {}", name, e))
+            .centroid()
+    };
+    let orbit = args.orbit.map(|period| {
+        if period == 0 {
+            panic!("Error: an orbit takes at least one frame");
+        }
+        let elevation = args.orbit_elevation.unwrap_or(20.0);
+        // the camera can't look straight up or down
+        if elevation.abs() >= 90.0 || elevation.is_nan() {
+            panic!("Error: the orbit elevation must be strictly between -90 and 90 degrees");
+        }
+        Orbit {
+            center: args.orbit_center.unwrap_or_else(|| match target {
+                Some(name) => target_center(name, 0.0),
+                None => Location::new(0.0, 0.0, 0.0),
+            }),
+            radius: args.orbit_radius.unwrap_or(5.0),
+            elevation,
+            rise: args.orbit_rise.unwrap_or(0.0),
+            period,
+        }
+    });
+    let frames = match (&args.frames, &orbit) {
+        (Some(frames), _) => frames.start..frames.end,
+        (None, Some(orbit)) => 0..orbit.period as i64,
+        (None, None) => 0..1,
+    };
+    let width = args.width.unwrap_or(1920);
+    let height = args.height.unwrap_or(1080);
+    let fps = args.fps.map_or(24, NonZeroU16::get);
+    let background = match args.background.clone().or_else(|| scene.background.clone()) {
+        Some(Background::Environment(environment)) => {
+            Background::Environment(environment.rotated(args.environment_rotation.unwrap_or(0.0)))
+        }
+        Some(Background::DefaultSky) => {
+            let albedo = args.ground_albedo.unwrap_or(0.3);
+            Background::Sky(Box::new(
+                Sky::new(
+                    args.sun_elevation.unwrap_or(45.0),
+                    args.sun_azimuth.unwrap_or(0.0),
+                    args.turbidity.unwrap_or(3.0),
+                    Color::new(albedo, albedo, albedo),
+                )
+                .unwrap_or_else(|e| panic!("Error: {}", e)),
+            ))
+        }
+        Some(background) => background,
+        None => Background::BlueGradient,
+    }
+    .with_intensity(args.environment_intensity.unwrap_or(1.0));
+    let alpha = args.alpha || matches!(background, Background::Transparent);
+    // single png and exr images are written as they are, without a frame number
+    let mut sequence = if args.frames.is_some()
+        || orbit.is_some()
+        || !(output.ends_with(".png") || output.ends_with(".exr"))
+    {
+        Some(Output::new(
+            &output,
+            width,
+            height,
+            frames.clone().count(),
+            fps,
+            alpha,
+        ))
+    } else {
+        None
+    };
+    for frame in frames {
+        let time = frame as f64;
+        let multisampling = args.multisampling.unwrap_or(100);
+        let fov = args
+            .fov
+            .or_else(|| camera.fov.as_ref().map(|fov| fov.at(time)))
+            .or_else(|| {
+                // rray's field of view is horizontal
+                camera.vertical_fov.as_ref().map(|fov| {
+                    let ratio = width as f64 / height as f64;
+                    (2.0 * ((fov.at(time) / 2.0).to_radians().tan() * ratio).atan()).to_degrees()
+                })
+            })
+            .unwrap_or(80.0);
+        let background = background.clone();
+        let mut image = match &orbit {
+            Some(orbit) => Image::looking_at(
+                width,
+                height,
+                multisampling,
+                orbit.location(time),
+                orbit.target(time),
+                fov,
+                background,
+            ),
+            None => {
+                let location = args
+                    .camera_location
+                    .or_else(|| camera.location.as_ref().map(|location| location.at(time)))
+                    .unwrap_or(Location::new(0.0, 0.0, 0.0));
+                match target {
+                    Some(name) => Image::looking_at(
+                        width,
+                        height,
+                        multisampling,
+                        location,
+                        target_center(name, time),
+                        fov,
+                        background,
+                    ),
+                    None => Image::new(
+                        width,
+                        height,
+                        multisampling,
+                        location,
+                        args.camera_direction
+                            .or_else(|| {
+                                camera
+                                    .direction
+                                    .as_ref()
+                                    .map(|direction| direction.at(time))
+                            })
+                            .unwrap_or(UnitDirection::new(0.0, 0.0, -1.0)),
+                        fov,
+                        background,
+                    ),
+                }
+            }
+        };
+        if let Some(shutter) = args.shutter {
+            image.set_shutter(shutter);
+        }
+        image.set_frame(time);
+        image.set_alpha(args.alpha);
+        image.set_medium(scene.medium.clone());
+        image.set_lights(scene.lights.clone());
+        if moving {
+            let (open, close) = image.interval();
+            objects = objects.during(open, close);
+        }
+        image.compute(&objects);
+        if args.denoise {
+            image.denoise();
+        }
+        match sequence.as_mut() {
+            Some(sequence) => sequence.write(image, frame),
+            None => image.write(&output),
+        }
+    }
+    if let Some(sequence) = sequence {
+        sequence.finish();
+    }
 }